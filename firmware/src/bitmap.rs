@@ -0,0 +1,209 @@
+//! Monochrome bitmap for the OLED, and text drawn on it.
+//!
+//! Laid out like the SSD1306 and SH1106 display RAM: eight pages of 128
+//! columns, each column byte eight pixels tall with the least significant
+//! bit at the top, so pages go to the display as they are.
+use core::fmt::{self, Write};
+
+use crate::charmap::{self, CharMap};
+use crate::font;
+use crate::lcd::{self, CharacterDisplay};
+
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+pub const PAGES: usize = HEIGHT / 8;
+
+// Character cell, including a column of space after the glyph.
+pub const CHAR_WIDTH: i32 = font::WIDTH as i32 + 1;
+
+#[derive(Clone, PartialEq)]
+pub struct Bitmap {
+    pages: [[u8; WIDTH]; PAGES],
+}
+
+impl Bitmap {
+    pub const fn new() -> Self {
+        Bitmap {
+            pages: [[0; WIDTH]; PAGES],
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pages = [[0; WIDTH]; PAGES];
+    }
+
+    // Display RAM for one page, eight rows of pixels.
+    pub fn page(&self, page: usize) -> &[u8; WIDTH] {
+        &self.pages[page]
+    }
+
+    // For the host to read back what was drawn.
+    #[cfg(not(target_os = "none"))]
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        x < WIDTH && y < HEIGHT && self.pages[y / 8][x] & 1 << (y % 8) != 0
+    }
+
+    // Pixels off the bitmap are ignored, so drawing can run off the edges.
+    pub fn set(&mut self, x: i32, y: i32, on: bool) {
+        if !(0..WIDTH as i32).contains(&x) || !(0..HEIGHT as i32).contains(&y) {
+            return;
+        }
+        let (page, bit) = (y as usize / 8, 1 << (y % 8));
+        if on {
+            self.pages[page][x as usize] |= bit;
+        } else {
+            self.pages[page][x as usize] &= !bit;
+        }
+    }
+
+    pub fn fill(&mut self, x: i32, y: i32, width: i32, height: i32, on: bool) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set(x, y, on);
+            }
+        }
+    }
+
+    // Outline of a rectangle, one pixel wide.
+    pub fn frame(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.fill(x, y, width, 1, true);
+        self.fill(x, y + height - 1, width, 1, true);
+        self.fill(x, y, 1, height, true);
+        self.fill(x + width - 1, y, 1, height, true);
+    }
+
+    // Columns of eight pixels, least significant bit at the top, with each
+    // pixel drawn scale pixels square. Clear pixels are drawn too.
+    pub fn columns(&mut self, x: i32, y: i32, columns: &[u8], scale: i32) {
+        for (i, column) in columns.iter().enumerate() {
+            for row in 0..8 {
+                let on = column & 1 << row != 0;
+                self.fill(x + i as i32 * scale, y + row * scale, scale, scale, on);
+            }
+        }
+    }
+
+    // Rows of pixels `width` wide, most significant bit on the left, as for
+    // the display's user-defined characters.
+    pub fn rows(&mut self, x: i32, y: i32, width: i32, rows: &[u8]) {
+        for (j, row) in rows.iter().enumerate() {
+            for i in 0..width {
+                self.set(x + i, y + j as i32, row & 1 << (width - 1 - i) != 0);
+            }
+        }
+    }
+
+    // Text in the OLED's font, scaled up, with the top left of the first
+    // character at (x, y). Returns x after the last character.
+    pub fn text(&mut self, x: i32, y: i32, s: &str, scale: i32) -> i32 {
+        let mut x = x;
+        for c in s.chars() {
+            self.glyph(x, y, charmap::OLED.encode(c), scale);
+            x += CHAR_WIDTH * scale;
+        }
+        x
+    }
+
+    // Width of text drawn at a scale.
+    pub fn text_width(s: &str, scale: i32) -> i32 {
+        s.chars().count() as i32 * CHAR_WIDTH * scale
+    }
+
+    fn glyph(&mut self, x: i32, y: i32, code: u8, scale: i32) {
+        let glyph = font::glyph(code).unwrap_or(&[0; font::WIDTH]);
+        self.columns(x, y, glyph, scale);
+        self.fill(x + font::WIDTH as i32 * scale, y, scale, 8 * scale, false);
+    }
+}
+
+// The bitmap as a character display, for the text layouts. Rows are spaced
+// out to fill the height, and user-defined characters are kept here.
+pub struct TextGrid {
+    bitmap: Bitmap,
+    cgram: [[u8; 8]; 8],
+    x: usize,
+    y: usize,
+}
+
+impl TextGrid {
+    pub const COLS: usize = WIDTH / CHAR_WIDTH as usize;
+    pub const ROWS: usize = 4;
+    const ROW_HEIGHT: i32 = (HEIGHT / Self::ROWS) as i32;
+
+    pub const fn new() -> Self {
+        TextGrid {
+            bitmap: Bitmap::new(),
+            cgram: [[0; 8]; 8],
+            x: 0,
+            y: 0,
+        }
+    }
+
+    pub fn bitmap(&self) -> &Bitmap {
+        &self.bitmap
+    }
+
+    pub fn bitmap_mut(&mut self) -> &mut Bitmap {
+        &mut self.bitmap
+    }
+}
+
+impl CharacterDisplay for TextGrid {
+    const COLS: usize = TextGrid::COLS;
+    const ROWS: usize = TextGrid::ROWS;
+
+    fn init(&mut self) {
+        self.clear();
+    }
+    fn charmap(&self) -> &'static CharMap {
+        &charmap::OLED
+    }
+    fn cursor(&mut self, _show_cursor: bool, _blink_cursor: bool) {}
+    // Addresses outside the screen leave the position off the end of the
+    // last row, where writes are ignored.
+    fn addr(&mut self, addr: u8) {
+        self.x = Self::COLS;
+        self.y = Self::ROWS - 1;
+        for y in 0..Self::ROWS {
+            let start = lcd::ddram_addr(Self::COLS, 0, y as u8);
+            if (start..start + Self::COLS as u8).contains(&addr) {
+                self.x = (addr - start) as usize;
+                self.y = y;
+            }
+        }
+    }
+    fn char(&mut self, c: u8) {
+        if self.x < Self::COLS && self.y < Self::ROWS {
+            let x = self.x as i32 * CHAR_WIDTH;
+            // Glyphs are eight pixels tall, centred in the row.
+            let y = self.y as i32 * Self::ROW_HEIGHT + (Self::ROW_HEIGHT - 8) / 2;
+            match c {
+                0..=15 => {
+                    self.bitmap.rows(
+                        x,
+                        y,
+                        CHAR_WIDTH,
+                        &self.cgram[c as usize & 7].map(|r| r << 1),
+                    );
+                }
+                _ => self.bitmap.glyph(x, y, c, 1),
+            }
+        }
+        self.x += 1;
+    }
+    fn clear(&mut self) {
+        self.bitmap.clear();
+        self.x = 0;
+        self.y = 0;
+    }
+    fn program_character(&mut self, index: u8, glyph: &[u8; 8]) {
+        self.cgram[index as usize & 7] = *glyph;
+    }
+}
+
+impl Write for TextGrid {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.string(s).map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! Spindle encoder capture, for replaying real cuts offline.
+//!
+//! Encoder deltas are summed per millisecond and stored as (time since
+//! previous sample, delta) pairs, so that idle periods take no space. When
+//! the buffer fills the oldest samples are overwritten.
+//!
+//! A dump is text: a "# els-capture <version>" line, the machine
+//! configuration as "# key=value" lines, a "time_ms,delta" header, then
+//! one line per sample.
+pub const VERSION: u32 = 1;
+const CAPTURE_SIZE: usize = 8192; // samples, 4 bytes each.
+
+// Longest line of a dump, including the newline.
+pub const DUMP_LINE_MAX: usize = 24;
+
+#[derive(Clone, Copy)]
+struct Sample {
+    dt_ms: u16,
+    delta: i16,
+}
+
+pub struct Capture {
+    samples: [Sample; CAPTURE_SIZE],
+    head: usize,
+    len: usize,
+    running: bool,
+    // Timestamp the oldest sample's dt_ms is relative to.
+    base_ms: i64,
+    last_ms: i64,
+    pending_ms: i64,
+    pending_delta: i32,
+}
+
+impl Capture {
+    pub const fn new() -> Self {
+        Capture {
+            samples: [Sample { dt_ms: 0, delta: 0 }; CAPTURE_SIZE],
+            head: 0,
+            len: 0,
+            running: false,
+            base_ms: 0,
+            last_ms: 0,
+            pending_ms: 0,
+            pending_delta: 0,
+        }
+    }
+
+    // Discard any previous capture and start recording.
+    pub fn start(&mut self, now_ms: i64) {
+        self.head = 0;
+        self.len = 0;
+        self.base_ms = now_ms;
+        self.last_ms = now_ms;
+        self.pending_ms = now_ms;
+        self.pending_delta = 0;
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        if self.running {
+            self.flush();
+            self.running = false;
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn get_len(&self) -> usize {
+        self.len
+    }
+
+    // Duration covered by the stored samples (ms).
+    pub fn get_duration_ms(&self) -> i64 {
+        self.last_ms - self.base_ms
+    }
+
+    // Record the encoder delta for a control loop iteration.
+    pub fn record(&mut self, now_ms: i64, delta: i32) {
+        if !self.running {
+            return;
+        }
+        if now_ms != self.pending_ms {
+            self.flush();
+            self.pending_ms = now_ms;
+        }
+        self.pending_delta += delta;
+    }
+
+    fn flush(&mut self) {
+        if self.pending_delta == 0 {
+            return;
+        }
+        // Long idle gaps are recorded as empty samples.
+        while self.pending_ms - self.last_ms > u16::MAX as i64 {
+            self.push(u16::MAX, 0);
+        }
+        let mut dt_ms = (self.pending_ms - self.last_ms) as u16;
+        // Bursts too large for one sample are split over several in the
+        // same millisecond.
+        while self.pending_delta != 0 {
+            let delta = self.pending_delta.clamp(i16::MIN as i32, i16::MAX as i32);
+            self.push(dt_ms, delta as i16);
+            self.pending_delta -= delta;
+            dt_ms = 0;
+        }
+    }
+
+    fn push(&mut self, dt_ms: u16, delta: i16) {
+        self.last_ms += dt_ms as i64;
+        let tail = (self.head + self.len) % CAPTURE_SIZE;
+        if self.len == CAPTURE_SIZE {
+            // Overwrite the oldest sample.
+            self.base_ms += self.samples[self.head].dt_ms as i64;
+            self.head = (self.head + 1) % CAPTURE_SIZE;
+        } else {
+            self.len += 1;
+        }
+        self.samples[tail] = Sample { dt_ms, delta };
+    }
+
+    // Start a dump of the stored samples. Stop the capture first, so
+    // that samples aren't overwritten while the dump is in progress.
+    pub fn dump(&self) -> Dump {
+        Dump {
+            index: 0,
+            time_ms: 0,
+        }
+    }
+}
+
+// Position within a capture dump, so that it can be sent a few lines at a
+// time without holding up the control loop.
+pub struct Dump {
+    index: usize,
+    time_ms: i64,
+}
+
+impl Dump {
+    // Next sample as (ms since start of capture, encoder delta).
+    pub fn next(&mut self, capture: &Capture) -> Option<(i64, i32)> {
+        if self.index >= capture.len {
+            return None;
+        }
+        let s = capture.samples[(capture.head + self.index) % CAPTURE_SIZE];
+        self.index += 1;
+        self.time_ms += s.dt_ms as i64;
+        Some((self.time_ms, s.delta as i32))
+    }
+}
@@ -0,0 +1,282 @@
+//! Character ROM tables.
+//!
+//! Each display has its own character ROM. Mostly printable ASCII where
+//! you'd expect it, but they differ in the rest, so text is encoded through
+//! the table for the ROM the display has. Characters it doesn't have are
+//! shown as a replacement glyph rather than failing the whole string.
+
+pub struct CharMap {
+    // Character shown for each code, or '\0' where it's user-defined, blank
+    // or has no Unicode equivalent.
+    codes: [char; 256],
+    // Characters without a code of their own, shown as a similar one.
+    aliases: &'static [(char, u8)],
+    // Code shown for characters the display doesn't have.
+    replacement: u8,
+}
+
+// Build a table from runs of codes whose characters are consecutive
+// (first code, last code, first character), then single codes.
+const fn table(runs: &[(u8, u8, u32)], codes: &[(u8, char)]) -> [char; 256] {
+    let mut table = ['\0'; 256];
+    let mut i = 0;
+    while i < runs.len() {
+        let (first, last, c) = runs[i];
+        let mut code = first as usize;
+        while code <= last as usize {
+            table[code] = match char::from_u32(c + (code - first as usize) as u32) {
+                Some(c) => c,
+                None => '\0',
+            };
+            code += 1;
+        }
+        i += 1;
+    }
+    let mut i = 0;
+    while i < codes.len() {
+        table[codes[i].0 as usize] = codes[i].1;
+        i += 1;
+    }
+    table
+}
+
+const ASCII: (u8, u8, u32) = (0x20, 0x7e, 0x20);
+// Half-width katakana, in JIS X 0201 order.
+#[cfg(not(feature = "oled"))]
+const KATAKANA: (u8, u8, u32) = (0xa1, 0xdf, 0xff61);
+#[cfg(not(feature = "oled"))]
+const LATIN_1: (u8, u8, u32) = (0xc0, 0xff, 0xc0);
+
+// The firmware only has the tables for the display it's built for; the host
+// has them all.
+
+// Newhaven VFD font.
+#[cfg(not(feature = "oled"))]
+pub static VFD: CharMap = CharMap {
+    codes: table(
+        &[ASCII, KATAKANA],
+        &[
+            (0x1c, '▼'),
+            (0x1d, '▶'),
+            (0x1e, '◀'),
+            (0x1f, '▲'),
+            (0x5c, '¥'),
+            (0x7e, '→'),
+            (0x7f, '←'),
+            (0x8c, '\\'),
+            (0x8e, '~'),
+            (0x94, '●'),
+            (0x95, '○'),
+            (0x9e, '↑'),
+            (0x9f, '↓'),
+            (0xdf, '°'),
+            (0xe0, 'α'),
+            (0xe2, 'β'),
+            (0xe3, 'ε'),
+            (0xe4, 'μ'),
+            (0xe5, 'σ'),
+            (0xe6, 'ρ'),
+            (0xf2, 'θ'),
+            (0xf3, 'ω'),
+            (0xf4, 'Ω'),
+            (0xf6, 'Σ'),
+            (0xf7, 'π'),
+        ],
+    ),
+    aliases: &[('µ', 0xe4)],
+    replacement: b'?',
+};
+
+// HD44780 A00 ROM (Japanese), the one most LCD modules have.
+#[cfg(not(feature = "oled"))]
+pub static HD44780_A00: CharMap = CharMap {
+    codes: table(
+        &[ASCII, KATAKANA],
+        &[
+            (0x5c, '¥'),
+            (0x7e, '→'),
+            (0x7f, '←'),
+            (0xe0, 'α'),
+            (0xe1, 'ä'),
+            (0xe2, 'β'),
+            (0xe3, 'ε'),
+            (0xe4, 'μ'),
+            (0xe5, 'σ'),
+            (0xe6, 'ρ'),
+            (0xe8, '√'),
+            (0xec, '¢'),
+            (0xee, 'ñ'),
+            (0xef, 'ö'),
+            (0xf2, 'θ'),
+            (0xf3, '∞'),
+            (0xf4, 'Ω'),
+            (0xf5, 'ü'),
+            (0xf6, 'Σ'),
+            (0xf7, 'π'),
+            (0xfa, '千'),
+            (0xfb, '万'),
+            (0xfc, '円'),
+            (0xfd, '÷'),
+            (0xff, '█'),
+        ],
+    ),
+    // The katakana semi-voiced mark is the usual stand-in for a degree sign.
+    aliases: &[('µ', 0xe4), ('°', 0xdf)],
+    replacement: 0xff,
+};
+
+// HD44780 A02 ROM (European).
+#[cfg(not(feature = "oled"))]
+pub static HD44780_A02: CharMap = CharMap {
+    codes: table(
+        &[ASCII, LATIN_1],
+        &[
+            (0x10, '▶'),
+            (0x11, '◀'),
+            (0x12, '“'),
+            (0x13, '”'),
+            (0x16, '●'),
+            (0x17, '↵'),
+            (0x18, '↑'),
+            (0x19, '↓'),
+            (0x1a, '→'),
+            (0x1b, '←'),
+            (0x1c, '≤'),
+            (0x1d, '≥'),
+            (0x1e, '▲'),
+            (0x1f, '▼'),
+            (0x7f, '⌂'),
+            (0x80, 'Б'),
+            (0x81, 'Д'),
+            (0x82, 'Ж'),
+            (0x83, 'З'),
+            (0x84, 'И'),
+            (0x85, 'Й'),
+            (0x86, 'Л'),
+            (0x87, 'П'),
+            (0x88, 'У'),
+            (0x89, 'Ц'),
+            (0x8a, 'Ч'),
+            (0x8b, 'Ш'),
+            (0x8c, 'Щ'),
+            (0x8d, 'Ъ'),
+            (0x8e, 'Ы'),
+            (0x8f, 'Э'),
+            (0x90, 'α'),
+            (0x91, '♪'),
+            (0x92, 'Γ'),
+            (0x93, 'π'),
+            (0x94, 'Σ'),
+            (0x95, 'σ'),
+            (0x97, 'τ'),
+            (0x99, 'Θ'),
+            (0x9a, 'Ω'),
+            (0x9b, 'δ'),
+            (0x9c, '∞'),
+            (0x9d, '♥'),
+            (0x9e, 'ε'),
+            (0x9f, '∩'),
+            (0xa1, '¡'),
+            (0xa2, '¢'),
+            (0xa3, '£'),
+            (0xa4, '¤'),
+            (0xa5, '¥'),
+            (0xa6, '¦'),
+            (0xa7, '§'),
+            (0xa8, 'ƒ'),
+            (0xa9, '©'),
+            (0xaa, 'ª'),
+            (0xab, '«'),
+            (0xac, 'Ю'),
+            (0xad, 'Я'),
+            (0xae, '®'),
+            (0xaf, '‘'),
+            (0xb0, '°'),
+            (0xb1, '±'),
+            (0xb2, '²'),
+            (0xb3, '³'),
+            (0xb4, '₧'),
+            (0xb5, 'µ'),
+            (0xb6, '¶'),
+            (0xb7, '·'),
+            (0xb8, 'ω'),
+            (0xb9, '¹'),
+            (0xba, 'º'),
+            (0xbb, '»'),
+            (0xbc, '¼'),
+            (0xbd, '½'),
+            (0xbe, '¾'),
+            (0xbf, '¿'),
+        ],
+    ),
+    aliases: &[('μ', 0xb5), ('θ', 0x99)],
+    replacement: 0xbf,
+};
+
+// The OLED's font (font.rs), ASCII and the symbols the user interface uses.
+#[cfg(any(feature = "oled", not(target_os = "none")))]
+pub static OLED: CharMap = CharMap {
+    codes: table(
+        &[ASCII],
+        &[
+            (0x80, 'μ'),
+            (0x81, '●'),
+            (0x82, '○'),
+            (0x83, '↑'),
+            (0x84, '↓'),
+            (0x85, '→'),
+            (0x86, '←'),
+        ],
+    ),
+    aliases: &[('µ', 0x80)],
+    replacement: b'?',
+};
+
+impl CharMap {
+    // Code for a character, if the display has it.
+    pub fn get(&self, c: char) -> Option<u8> {
+        if c == '\0' {
+            return None;
+        }
+        if c.is_ascii() && self.codes[c as usize] == c {
+            return Some(c as u8);
+        }
+        self.codes
+            .iter()
+            .position(|&code| code == c)
+            .map(|code| code as u8)
+            .or_else(|| {
+                self.aliases
+                    .iter()
+                    .find(|&&(alias, _)| alias == c)
+                    .map(|&(_, code)| code)
+            })
+    }
+
+    // Code for a character, or the replacement if the display doesn't have
+    // it.
+    pub fn encode(&self, c: char) -> u8 {
+        self.get(c).unwrap_or(self.replacement)
+    }
+}
+
+// For the host's checks of the tables.
+#[cfg(not(target_os = "none"))]
+impl CharMap {
+    // Character shown for a code, if it has one.
+    pub fn decode(&self, code: u8) -> Option<char> {
+        match self.codes[code as usize] {
+            '\0' => None,
+            c => Some(c),
+        }
+    }
+
+    pub fn replacement(&self) -> u8 {
+        self.replacement
+    }
+
+    // Further characters shown as a similar one.
+    pub fn aliases(&self) -> &'static [(char, u8)] {
+        self.aliases
+    }
+}
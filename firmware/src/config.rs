@@ -0,0 +1,227 @@
+//! Machine configuration.
+use core::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    UnknownKey,
+    InvalidValue,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnknownKey => write!(f, "unknown configuration key"),
+            Error::InvalidValue => write!(f, "invalid configuration value"),
+        }
+    }
+}
+
+// Input filter settings for a quadrature encoder timer: the digital filter
+// (the ICxF setting, 0-15) and its sampling clock prescaler (1, 2 or 4).
+#[derive(Clone, Copy, PartialEq)]
+pub struct EncoderFilter {
+    pub filter: i64,
+    pub prescaler: i64,
+}
+
+// Encoder, leadscrew, drive ratio and motor parameters, and display
+// settings. The defaults are the constants in main.rs, but may be changed at
+// runtime.
+#[derive(Clone, Copy)]
+pub struct Config {
+    pub encoder_ppr: i64,
+    pub encoder_ratio_spindle: i64,
+    pub encoder_ratio_encoder: i64,
+    pub leadscrew_pitch: i64, // µm
+    pub drive_ratio_motor: i64,
+    pub drive_ratio_leadscrew: i64,
+    pub motor_ppr: i64,
+    pub spindle_enc: EncoderFilter,
+    pub feed_enc: EncoderFilter,
+    pub mode_enc: EncoderFilter,
+    pub display_brightness: i64, // %
+    // Dim the display after this long idle with the servo off and the
+    // spindle stopped, or never if 0.
+    pub display_dim_timeout: i64, // s
+}
+
+impl Config {
+    pub const KEYS: [&'static str; 15] = [
+        "encoder_ppr",
+        "encoder_ratio_spindle",
+        "encoder_ratio_encoder",
+        "leadscrew_pitch",
+        "drive_ratio_motor",
+        "drive_ratio_leadscrew",
+        "motor_ppr",
+        "spindle_enc_filter",
+        "spindle_enc_prescaler",
+        "feed_enc_filter",
+        "feed_enc_prescaler",
+        "mode_enc_filter",
+        "mode_enc_prescaler",
+        "display_brightness",
+        "display_dim_timeout",
+    ];
+    // Limits on each setting. Together they can still overflow the 32.32
+    // fixed point feed calculations, so those are checked as well.
+    const MAX_PPR: i64 = 100_000;
+    const MAX_RATIO: i64 = 1000;
+    const MAX_PITCH: i64 = 100_000;
+    const MAX_FILTER: i64 = 15;
+    const MAX_PRESCALER: i64 = 4;
+    const MAX_BRIGHTNESS: i64 = 100;
+    const MAX_DIM_TIMEOUT: i64 = 24 * 60 * 60;
+    // Coarsest feed the calculations must handle, as a fraction of μm per
+    // revolution: a 6 TPI thread, the coarsest the user interface offers.
+    const MAX_FEED: (i64, i64) = (25_400, 6);
+    // Largest feed factor that fractional encoder pulses (16.16 fixed
+    // point) can be multiplied by without overflow.
+    const MAX_FEED_FACTOR: i64 = 1 << 47;
+
+    pub const fn new() -> Self {
+        Config {
+            encoder_ppr: crate::ENCODER_PPR,
+            encoder_ratio_spindle: crate::ENCODER_RATIO_SPINDLE,
+            encoder_ratio_encoder: crate::ENCODER_RATIO_ENCODER,
+            leadscrew_pitch: crate::LEADSCREW_PITCH,
+            drive_ratio_motor: crate::DRIVE_RATIO_MOTOR,
+            drive_ratio_leadscrew: crate::DRIVE_RATIO_LEADSCREW,
+            motor_ppr: crate::MOTOR_PPR,
+            spindle_enc: EncoderFilter {
+                filter: crate::SPINDLE_ENC_FILTER,
+                prescaler: crate::SPINDLE_ENC_PRESCALER,
+            },
+            feed_enc: EncoderFilter {
+                filter: crate::UI_ENC_FILTER,
+                prescaler: crate::UI_ENC_PRESCALER,
+            },
+            mode_enc: EncoderFilter {
+                filter: crate::UI_ENC_FILTER,
+                prescaler: crate::UI_ENC_PRESCALER,
+            },
+            display_brightness: crate::DISPLAY_BRIGHTNESS,
+            display_dim_timeout: crate::DISPLAY_DIM_TIMEOUT,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Result<i64, Error> {
+        match key {
+            "encoder_ppr" => Ok(self.encoder_ppr),
+            "encoder_ratio_spindle" => Ok(self.encoder_ratio_spindle),
+            "encoder_ratio_encoder" => Ok(self.encoder_ratio_encoder),
+            "leadscrew_pitch" => Ok(self.leadscrew_pitch),
+            "drive_ratio_motor" => Ok(self.drive_ratio_motor),
+            "drive_ratio_leadscrew" => Ok(self.drive_ratio_leadscrew),
+            "motor_ppr" => Ok(self.motor_ppr),
+            "spindle_enc_filter" => Ok(self.spindle_enc.filter),
+            "spindle_enc_prescaler" => Ok(self.spindle_enc.prescaler),
+            "feed_enc_filter" => Ok(self.feed_enc.filter),
+            "feed_enc_prescaler" => Ok(self.feed_enc.prescaler),
+            "mode_enc_filter" => Ok(self.mode_enc.filter),
+            "mode_enc_prescaler" => Ok(self.mode_enc.prescaler),
+            "display_brightness" => Ok(self.display_brightness),
+            "display_dim_timeout" => Ok(self.display_dim_timeout),
+            _ => Err(Error::UnknownKey),
+        }
+    }
+
+    // Change a setting, unless the result would be invalid.
+    pub fn set(&mut self, key: &str, value: i64) -> Result<(), Error> {
+        let mut config = *self;
+        config.set_field(key, value)?;
+        config.validate()?;
+        *self = config;
+        Ok(())
+    }
+
+    // Check that the feed calculations stay in range for any feed.
+    pub fn validate(&self) -> Result<(), Error> {
+        let (micron, per) = Self::MAX_FEED;
+        match self.feed_factor(micron, per) {
+            Some(_) => Ok(()),
+            None => Err(Error::InvalidValue),
+        }
+    }
+
+    // Motor pulses per spindle encoder pulse (32.32 fixed point) for a feed
+    // of `micron / per` μm per spindle revolution, or None if the
+    // calculation overflows.
+    pub fn feed_factor(&self, micron: i64, per: i64) -> Option<i64> {
+        // XXX consider fixed point split; is 32.32 ideal?
+        // Pulses to fractional turns (32.32 fixed point).
+        let mut t: i64 = (1i64 << 32).checked_mul(self.encoder_ratio_spindle)?;
+        t /= self.encoder_ppr * self.encoder_ratio_encoder;
+        // Encoder turns to fractional leadscrew turns (32.32).
+        t = t.checked_mul(micron)? / per;
+        t = t.checked_mul(self.drive_ratio_leadscrew)?;
+        t /= self.drive_ratio_motor * self.leadscrew_pitch;
+        // Leadscrew turns to encoder pulses (32.32).
+        t = t.checked_mul(self.motor_ppr)?;
+        if t.abs() > Self::MAX_FEED_FACTOR {
+            return None;
+        }
+        Some(t)
+    }
+
+    fn set_field(&mut self, key: &str, value: i64) -> Result<(), Error> {
+        let (field, min, max) = match key {
+            "encoder_ppr" => (&mut self.encoder_ppr, 1, Self::MAX_PPR),
+            "encoder_ratio_spindle" => (&mut self.encoder_ratio_spindle, 1, Self::MAX_RATIO),
+            "encoder_ratio_encoder" => (&mut self.encoder_ratio_encoder, 1, Self::MAX_RATIO),
+            "leadscrew_pitch" => (&mut self.leadscrew_pitch, 1, Self::MAX_PITCH),
+            "drive_ratio_motor" => (&mut self.drive_ratio_motor, 1, Self::MAX_RATIO),
+            "drive_ratio_leadscrew" => (&mut self.drive_ratio_leadscrew, 1, Self::MAX_RATIO),
+            "motor_ppr" => (&mut self.motor_ppr, 1, Self::MAX_PPR),
+            "spindle_enc_filter" => (&mut self.spindle_enc.filter, 0, Self::MAX_FILTER),
+            "spindle_enc_prescaler" => (&mut self.spindle_enc.prescaler, 1, Self::MAX_PRESCALER),
+            "feed_enc_filter" => (&mut self.feed_enc.filter, 0, Self::MAX_FILTER),
+            "feed_enc_prescaler" => (&mut self.feed_enc.prescaler, 1, Self::MAX_PRESCALER),
+            "mode_enc_filter" => (&mut self.mode_enc.filter, 0, Self::MAX_FILTER),
+            "mode_enc_prescaler" => (&mut self.mode_enc.prescaler, 1, Self::MAX_PRESCALER),
+            "display_brightness" => (&mut self.display_brightness, 0, Self::MAX_BRIGHTNESS),
+            "display_dim_timeout" => (&mut self.display_dim_timeout, 0, Self::MAX_DIM_TIMEOUT),
+            _ => return Err(Error::UnknownKey),
+        };
+        // Ratios may be negative to reverse direction; the rest may not.
+        let signed = key.contains("ratio");
+        let magnitude = if signed { value.abs() } else { value };
+        if magnitude < min || magnitude > max {
+            return Err(Error::InvalidValue);
+        }
+        if key.ends_with("_prescaler") && !(value as u64).is_power_of_two() {
+            return Err(Error::InvalidValue);
+        }
+        *field = value;
+        Ok(())
+    }
+
+    // Convert spindle encoder pulses per minute to spindle RPM x1000.
+    pub fn encoder_ppm_to_milli_rpm(&self, ppm: i64) -> i64 {
+        (ppm * 1000 * self.encoder_ratio_spindle) / (self.encoder_ratio_encoder * self.encoder_ppr)
+    }
+
+    // Spindle, feed and mode encoder input filter settings.
+    pub fn get_encoder_filters(&self) -> [EncoderFilter; 3] {
+        [self.spindle_enc, self.feed_enc, self.mode_enc]
+    }
+
+    // Whole spindle revolutions for a spindle encoder position, rounding
+    // towards negative infinity so revolution boundaries stay at the same
+    // spindle angle either side of zero.
+    pub fn spindle_revolutions(&self, position: i64) -> i64 {
+        let mut numerator = position * self.encoder_ratio_spindle;
+        let mut denominator = self.encoder_ppr * self.encoder_ratio_encoder;
+        if denominator < 0 {
+            numerator = -numerator;
+            denominator = -denominator;
+        }
+        numerator.div_euclid(denominator)
+    }
+
+    // Convert spindle encoder pulses per minute to spindle RPM.
+    pub fn encoder_ppm_to_rpm(&self, ppm: i64) -> i32 {
+        ((ppm * self.encoder_ratio_spindle) / (self.encoder_ratio_encoder * self.encoder_ppr))
+            as i32
+    }
+}
@@ -0,0 +1,566 @@
+//! Line-oriented command console.
+//!
+//! Each command is a single line of whitespace separated words. Every
+//! command is answered by zero or more lines of output followed by a line
+//! containing "ok", or by a single "err <reason>" line.
+use core::fmt::{self, Write};
+
+use crate::capture::{self, Capture};
+use crate::config;
+use crate::control::Control;
+use crate::index;
+use crate::servo::Servo;
+use crate::telemetry;
+use crate::timing;
+use crate::userinterface::{Mode, UI};
+use crate::view::Renderer;
+
+const LINE_MAX: usize = 64;
+const TX_BUFFER_SIZE: usize = 1024;
+// Room for the longest response. Lines are only taken from a port while its
+// transmit buffer has this much free.
+pub const RESPONSE_MAX: usize = 512;
+
+#[derive(Debug)]
+pub enum ParseError {
+    Empty,
+    TooLong,
+    UnknownCommand,
+    BadArgument,
+    TooManyArguments,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::Empty => write!(f, "empty command"),
+            ParseError::TooLong => write!(f, "line too long"),
+            ParseError::UnknownCommand => write!(f, "unknown command"),
+            ParseError::BadArgument => write!(f, "bad argument"),
+            ParseError::TooManyArguments => write!(f, "too many arguments"),
+        }
+    }
+}
+
+pub enum Command<'a> {
+    Help,
+    Status,
+    // Query or change operating mode.
+    Mode(Option<Mode>),
+    // Query or change feed rate (μm/rev).
+    Feed(Option<i32>),
+    // Query or change metric thread pitch (μm).
+    Pitch(Option<i32>),
+    // Query or change imperial thread pitch (TPI).
+    Tpi(Option<i32>),
+    // Query, set (μm) or clear the carriage stop.
+    Stop(Option<StopOp>),
+    // List, query or change machine configuration.
+    Config(Option<&'a str>, Option<i64>),
+    // Stream telemetry frames at the given rate (Hz, zero disables).
+    Telemetry(u32),
+    // Query, start, stop or dump the spindle encoder capture.
+    Capture(Option<CaptureOp>),
+    // Show or reset the control interrupt timing statistics.
+    Timing(bool),
+    // Show the spindle encoder index check statistics.
+    Encoder,
+    // Reboot into the system DFU bootloader.
+    Dfu,
+}
+
+pub enum StopOp {
+    Set(i32),
+    Clear,
+}
+
+pub enum CaptureOp {
+    Start,
+    Stop,
+    Dump,
+}
+
+// Console ports, for directing telemetry.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Port {
+    Uart,
+    #[cfg(feature = "usb")]
+    Usb,
+}
+
+// Actions that the caller must take after a command has been answered.
+#[derive(PartialEq)]
+pub enum Action {
+    None,
+    Telemetry(u32),
+    // Send the capture samples, followed by "ok".
+    CaptureDump,
+    TimingReset,
+    Bootloader,
+}
+
+fn parse_number<T: core::str::FromStr>(word: Option<&str>) -> Result<Option<T>, ParseError> {
+    match word {
+        None => Ok(None),
+        Some(w) => w.parse().map(Some).map_err(|_| ParseError::BadArgument),
+    }
+}
+
+pub fn parse(line: &str) -> Result<Command<'_>, ParseError> {
+    let mut words = line.split_whitespace();
+    let command = match words.next().ok_or(ParseError::Empty)? {
+        "help" | "?" => Command::Help,
+        "status" => Command::Status,
+        "mode" => match words.next() {
+            None => Command::Mode(None),
+            Some(name) => {
+                Command::Mode(Some(Mode::from_name(name).ok_or(ParseError::BadArgument)?))
+            }
+        },
+        "feed" => Command::Feed(parse_number(words.next())?),
+        "pitch" => Command::Pitch(parse_number(words.next())?),
+        "tpi" => Command::Tpi(parse_number(words.next())?),
+        "stop" => Command::Stop(match words.next() {
+            None => None,
+            Some("clear") => Some(StopOp::Clear),
+            word => Some(StopOp::Set(
+                parse_number(word)?.ok_or(ParseError::BadArgument)?,
+            )),
+        }),
+        "config" => {
+            let key = words.next();
+            Command::Config(key, parse_number(words.next())?)
+        }
+        "telemetry" => {
+            Command::Telemetry(parse_number(words.next())?.ok_or(ParseError::BadArgument)?)
+        }
+        "capture" => Command::Capture(match words.next() {
+            None => None,
+            Some("start") => Some(CaptureOp::Start),
+            Some("stop") => Some(CaptureOp::Stop),
+            Some("dump") => Some(CaptureOp::Dump),
+            Some(_) => return Err(ParseError::BadArgument),
+        }),
+        "timing" => match words.next() {
+            None => Command::Timing(false),
+            Some("reset") => Command::Timing(true),
+            Some(_) => return Err(ParseError::BadArgument),
+        },
+        "encoder" => Command::Encoder,
+        "dfu" => Command::Dfu,
+        _ => return Err(ParseError::UnknownCommand),
+    };
+    if words.next().is_some() {
+        return Err(ParseError::TooManyArguments);
+    }
+    Ok(command)
+}
+
+// Fixed point value with three decimal places.
+struct Milli(i64);
+
+impl fmt::Display for Milli {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let v = self.0.unsigned_abs();
+        write!(f, "{}{}.{:03}", sign, v / 1000, v % 1000)
+    }
+}
+
+// Accumulates received bytes into lines.
+pub struct LineBuffer {
+    buf: [u8; LINE_MAX],
+    len: usize,
+    overflow: bool,
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        LineBuffer {
+            buf: [0; LINE_MAX],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    // Add a received byte. Returns the line once it is complete.
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, ParseError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let len = core::mem::take(&mut self.len);
+                if core::mem::take(&mut self.overflow) {
+                    return Some(Err(ParseError::TooLong));
+                }
+                if len == 0 {
+                    // Ignore blank lines, including the second half of CRLF.
+                    return None;
+                }
+                Some(core::str::from_utf8(&self.buf[..len]).map_err(|_| ParseError::BadArgument))
+            }
+            // Backspace/delete.
+            0x08 | 0x7f => {
+                self.len = self.len.saturating_sub(1);
+                None
+            }
+            _ if self.len < self.buf.len() => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+                None
+            }
+            _ => {
+                self.overflow = true;
+                None
+            }
+        }
+    }
+}
+
+// Transmit ring buffer, so that responses can be sent without blocking the
+// control loop. Output that doesn't fit is dropped, so responses are only
+// started when there is room for all of them.
+pub struct TxBuffer {
+    buf: [u8; TX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl TxBuffer {
+    pub const fn new() -> Self {
+        TxBuffer {
+            buf: [0; TX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+    pub fn peek(&self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        Some(self.buf[self.head])
+    }
+    pub fn pop(&mut self) {
+        if self.len > 0 {
+            self.head = (self.head + 1) % self.buf.len();
+            self.len -= 1;
+        }
+    }
+    pub fn free(&self) -> usize {
+        self.buf.len() - self.len
+    }
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.len >= self.buf.len() {
+            return false;
+        }
+        self.buf[(self.head + self.len) % self.buf.len()] = byte;
+        self.len += 1;
+        true
+    }
+}
+
+impl TxBuffer {
+    // Queue a block of bytes, only if all of it fits.
+    pub fn push_all(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() > self.free() {
+            return false;
+        }
+        for &b in bytes {
+            self.push(b);
+        }
+        true
+    }
+
+    // Queue a block of streamed output, only if it leaves room for a
+    // response, so that commands are still answered while streaming.
+    pub fn push_stream(&mut self, bytes: &[u8]) -> bool {
+        if bytes.len() + RESPONSE_MAX > self.free() {
+            return false;
+        }
+        self.push_all(bytes)
+    }
+}
+
+impl Write for TxBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            if !self.push(b) {
+                return Err(fmt::Error);
+            }
+        }
+        Ok(())
+    }
+}
+
+// Machine state needed to answer commands.
+pub struct Context<'a, 'b, R> {
+    pub ui: &'a mut UI<'b, R>,
+    pub control: &'a mut Control,
+    pub servo: &'a Servo,
+    pub capture: &'a mut Capture,
+    // A capture dump is being sent, on either port.
+    pub dumping: bool,
+    pub timing: &'a timing::Stats,
+    pub index: &'a index::Stats,
+    pub now_ms: i64,
+    // Spindle encoder pulses per minute, and per minute per second.
+    pub spindle_velocity: i64,
+    pub spindle_acceleration: i64,
+    pub rpm: i32,
+    pub spindle_pos: i64,
+    pub motor_pos: i64,
+    // Steps emitted less those counted by the step loopback.
+    #[cfg(feature = "step-loopback")]
+    pub step_mismatch: i64,
+}
+
+// Parse and execute a line received from the console, writing the
+// response to `out`. If there isn't room for any response, the command is
+// refused with "err busy".
+pub fn handle<R>(line: Result<&str, ParseError>, out: &mut TxBuffer, ctx: &mut Context<R>) -> Action
+where
+    R: Renderer,
+{
+    if out.free() < RESPONSE_MAX {
+        out.push_all(b"err busy\n");
+        return Action::None;
+    }
+    let result = match line.and_then(parse) {
+        Ok(command) => execute(command, out, ctx),
+        Err(e) => {
+            writeln!(out, "err {}", e).ok();
+            return Action::None;
+        }
+    };
+    match result {
+        // The caller finishes the response once the dump is complete.
+        Ok(Action::CaptureDump) => Action::CaptureDump,
+        Ok(action) => {
+            writeln!(out, "ok").ok();
+            action
+        }
+        Err(e) => {
+            writeln!(out, "err {}", e).ok();
+            Action::None
+        }
+    }
+}
+
+fn execute<R, W>(
+    command: Command,
+    out: &mut W,
+    ctx: &mut Context<R>,
+) -> Result<Action, &'static str>
+where
+    R: Renderer,
+    W: Write,
+{
+    match command {
+        Command::Help => {
+            writeln!(out, "status").ok();
+            writeln!(out, "mode [off|feed|thread|thread-imperial]").ok();
+            writeln!(out, "feed [μm/rev]").ok();
+            writeln!(out, "pitch [μm]").ok();
+            writeln!(out, "tpi [threads/inch]").ok();
+            writeln!(out, "stop [μm|clear]").ok();
+            writeln!(out, "config [key [value]]").ok();
+            writeln!(out, "telemetry <Hz>").ok();
+            writeln!(out, "capture [start|stop|dump]").ok();
+            writeln!(out, "timing [reset]").ok();
+            writeln!(out, "encoder").ok();
+            writeln!(out, "dfu").ok();
+        }
+        Command::Status => {
+            write!(
+                out,
+                "rpm={} mode={} feed={} pitch={} tpi={} spindle={} pos={}",
+                ctx.rpm,
+                ctx.ui.get_mode().name(),
+                ctx.ui.get_feed_rate(),
+                ctx.ui.get_metric_thread_pitch(),
+                ctx.ui.get_imperial_thread_pitch(),
+                ctx.spindle_pos,
+                ctx.control.pulses_to_micron(ctx.motor_pos),
+            )
+            .ok();
+            let config = ctx.control.get_config();
+            write!(out, " revs={}", config.spindle_revolutions(ctx.spindle_pos)).ok();
+            let velocity = config.encoder_ppm_to_milli_rpm(ctx.spindle_velocity);
+            let acceleration = config.encoder_ppm_to_milli_rpm(ctx.spindle_acceleration);
+            write!(
+                out,
+                " velocity={} accel={}",
+                Milli(velocity),
+                Milli(acceleration)
+            )
+            .ok();
+            #[cfg(feature = "step-loopback")]
+            write!(out, " step_mismatch={}", ctx.step_mismatch).ok();
+            write!(
+                out,
+                " display={}",
+                ctx.ui.get_display_error().map_or("ok", |e| e.name())
+            )
+            .ok();
+            writeln!(
+                out,
+                " servo={} fault={} lost={}",
+                if ctx.servo.motion_allowed() {
+                    "ok"
+                } else {
+                    "off"
+                },
+                ctx.servo.get_fault().map_or("none", |f| f.name()),
+                ctx.control.pulses_to_micron(ctx.servo.get_lost_pulses()),
+            )
+            .ok();
+        }
+        Command::Mode(None) => {
+            writeln!(out, "mode={}", ctx.ui.get_mode().name()).ok();
+        }
+        Command::Mode(Some(mode)) => ctx.ui.set_mode(ctx.control, mode, ctx.rpm)?,
+        Command::Feed(None) => {
+            writeln!(out, "feed={}", ctx.ui.get_feed_rate()).ok();
+        }
+        Command::Feed(Some(feed)) => ctx.ui.set_feed_rate(ctx.control, feed)?,
+        Command::Pitch(None) => {
+            writeln!(out, "pitch={}", ctx.ui.get_metric_thread_pitch()).ok();
+        }
+        Command::Pitch(Some(pitch)) => {
+            ctx.ui
+                .set_metric_thread_pitch(ctx.control, pitch, ctx.rpm)?
+        }
+        Command::Tpi(None) => {
+            writeln!(out, "tpi={}", ctx.ui.get_imperial_thread_pitch()).ok();
+        }
+        Command::Tpi(Some(tpi)) => ctx
+            .ui
+            .set_imperial_thread_pitch(ctx.control, tpi, ctx.rpm)?,
+        Command::Stop(None) => match ctx.ui.get_stop() {
+            Some(stop) => {
+                let distance = stop - ctx.control.pulses_to_micron(ctx.motor_pos);
+                writeln!(out, "stop={} distance={}", stop, distance).ok();
+            }
+            None => {
+                writeln!(out, "stop=none").ok();
+            }
+        },
+        Command::Stop(Some(op)) => {
+            let stop = match op {
+                StopOp::Set(stop) => Some(stop as i64),
+                StopOp::Clear => None,
+            };
+            ctx.ui
+                .set_stop(stop, ctx.control.pulses_to_micron(ctx.motor_pos));
+        }
+        Command::Config(None, _) => {
+            let config = ctx.control.get_config();
+            for key in config::Config::KEYS {
+                writeln!(out, "{}={}", key, config.get(key).unwrap_or(0)).ok();
+            }
+        }
+        Command::Config(Some(key), None) => {
+            let value = ctx
+                .control
+                .get_config()
+                .get(key)
+                .map_err(|_| "unknown key")?;
+            writeln!(out, "{}={}", key, value).ok();
+        }
+        Command::Config(Some(key), Some(value)) => {
+            // Changing the machine configuration under a running servo
+            // would make it jump. Display settings are harmless.
+            if ctx.ui.get_mode() != Mode::ServoOff && !key.starts_with("display_") {
+                return Err("servo must be off to change configuration");
+            }
+            let mut config = *ctx.control.get_config();
+            config.set(key, value).map_err(|e| match e {
+                config::Error::UnknownKey => "unknown key",
+                config::Error::InvalidValue => "invalid value",
+            })?;
+            ctx.control.set_config(config);
+        }
+        Command::Telemetry(hz) => {
+            if hz > telemetry::Stream::MAX_RATE {
+                return Err("rate too high");
+            }
+            if hz != 0 && 1000 % hz != 0 {
+                // Frames are scheduled on whole milliseconds.
+                return Err("rate must divide 1000");
+            }
+            return Ok(Action::Telemetry(hz));
+        }
+        Command::Capture(None) => {
+            writeln!(
+                out,
+                "capture={} samples={} duration={}",
+                if ctx.capture.is_running() {
+                    "running"
+                } else {
+                    "stopped"
+                },
+                ctx.capture.get_len(),
+                ctx.capture.get_duration_ms(),
+            )
+            .ok();
+        }
+        // The dump reads the buffer as it goes, so it mustn't change.
+        Command::Capture(Some(_)) if ctx.dumping => return Err("dump in progress"),
+        Command::Capture(Some(CaptureOp::Start)) => ctx.capture.start(ctx.now_ms),
+        Command::Capture(Some(CaptureOp::Stop)) => ctx.capture.stop(),
+        Command::Capture(Some(CaptureOp::Dump)) => {
+            ctx.capture.stop();
+            writeln!(out, "# els-capture {}", capture::VERSION).ok();
+            let config = ctx.control.get_config();
+            for key in config::Config::KEYS {
+                writeln!(out, "# {}={}", key, config.get(key).unwrap_or(0)).ok();
+            }
+            writeln!(out, "time_ms,delta").ok();
+            return Ok(Action::CaptureDump);
+        }
+        Command::Timing(false) => {
+            let t = ctx.timing;
+            writeln!(
+                out,
+                "budget={} min={} max={} pulse_max={} slow={} overruns={}",
+                timing::BUDGET_US,
+                if t.min_us == u32::MAX { 0 } else { t.min_us },
+                t.max_us,
+                t.pulse_max_us,
+                t.slow_count,
+                t.get_overruns(),
+            )
+            .ok();
+            for (i, count) in t.histogram.iter().enumerate() {
+                let from = i as u32 * timing::HISTOGRAM_BUCKET_US;
+                if i == t.histogram.len() - 1 {
+                    writeln!(out, "{}+ {}", from, count).ok();
+                } else {
+                    writeln!(
+                        out,
+                        "{}-{} {}",
+                        from,
+                        from + timing::HISTOGRAM_BUCKET_US - 1,
+                        count
+                    )
+                    .ok();
+                }
+            }
+        }
+        Command::Timing(true) => return Ok(Action::TimingReset),
+        Command::Encoder => {
+            let i = ctx.index;
+            writeln!(
+                out,
+                "checked={} errors={} last_error={} max_error={}",
+                i.checked, i.errors, i.last_error, i.max_error,
+            )
+            .ok();
+        }
+        Command::Dfu => {
+            if ctx.ui.get_mode() != Mode::ServoOff || ctx.rpm != 0 {
+                return Err("servo must be off and spindle stopped");
+            }
+            return Ok(Action::Bootloader);
+        }
+    }
+    Ok(Action::None)
+}
@@ -1,4 +1,5 @@
 //! Encoder/Servo control
+use crate::config::Config;
 
 #[derive(Clone, Copy)]
 pub enum Direction {
@@ -15,10 +16,20 @@ impl From<Direction> for bool {
     }
 }
 
+// Most recently requested feed, retained so the feed factor can be
+// recalculated if the machine configuration changes.
+#[derive(Clone, Copy)]
+enum Feed {
+    MicronPerRev(i32),
+    Tpi(i32),
+}
+
 pub struct Control {
     // XXX direction?
     feed_rate_micron_per_rev: i32,
 
+    config: Config,
+    feed: Feed,
     feed_per_rev_factor: i64,
     fractional_pulses_remaining: i64,
     last_direction: Direction,
@@ -28,17 +39,24 @@ impl Control {
     pub fn new() -> Self {
         Control {
             feed_rate_micron_per_rev: 0,
+            config: Config::new(),
+            feed: Feed::MicronPerRev(0),
             feed_per_rev_factor: 0,
             fractional_pulses_remaining: 0,
             last_direction: Direction::Forward,
         }
     }
-    pub fn feed_per_rev(
-        &mut self,
-        encoder_pulses: i32,
-        _elapsed_ms: u32,
-    ) -> (Direction, u32) {
-        let mut t: i64 = encoder_pulses as i64 * self.feed_per_rev_factor;
+    // Motor pulses for a spindle movement in encoder pulses (16.16 fixed
+    // point), such as the change in extrapolated spindle position.
+    pub fn feed_per_rev_fraction(&mut self, encoder_pulses: i64) -> (Direction, u32) {
+        let whole = encoder_pulses >> 16;
+        let fraction = encoder_pulses & 0xffff;
+        let t = whole * self.feed_per_rev_factor + ((fraction * self.feed_per_rev_factor) >> 16);
+        self.pulses_for(t)
+    }
+    // Convert a number of motor pulses (32.32 fixed point) to whole pulses
+    // and direction.
+    fn pulses_for(&mut self, mut t: i64) -> (Direction, u32) {
         t += self.fractional_pulses_remaining;
         // Retain remainder for next round.
         let mut pulses: i32 = (t >> 32) as i32;
@@ -60,47 +78,53 @@ impl Control {
     pub fn get_feed_rate_micron_per_rev(&self) -> i32 {
         self.feed_rate_micron_per_rev
     }
-    pub fn get_last_direction(&self) -> Direction {
-        self.last_direction
-    }
     pub fn get_fractional_pulses_remaining(&self) -> i64 {
         self.fractional_pulses_remaining
     }
+    pub fn get_feed_per_rev_factor(&self) -> i64 {
+        self.feed_per_rev_factor
+    }
+    // Use a factor calculated by another instance, restarting the
+    // fractional pulse count if it has changed.
+    pub fn load_feed_per_rev_factor(&mut self, factor: i64) {
+        if factor != self.feed_per_rev_factor {
+            self.feed_per_rev_factor = factor;
+            self.fractional_pulses_remaining = 0;
+        }
+    }
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+    pub fn set_config(&mut self, config: Config) {
+        self.config = config;
+        match self.feed {
+            Feed::MicronPerRev(feed) => self.set_feed_rate_micron_per_rev(feed),
+            Feed::Tpi(tpi) => self.set_feed_rate_tpi(tpi),
+        }
+    }
+    // Convert a count of motor pulses to carriage travel.
+    pub fn pulses_to_micron(&self, pulses: i64) -> i64 {
+        let c = &self.config;
+        (pulses * c.drive_ratio_motor * c.leadscrew_pitch) / (c.drive_ratio_leadscrew * c.motor_ppr)
+    }
     pub fn set_feed_rate_micron_per_rev(&mut self, feed: i32) {
         // XXX bounds checking.
         self.feed_rate_micron_per_rev = feed;
+        self.feed = Feed::MicronPerRev(feed);
         self.fractional_pulses_remaining = 0;
-        // XXX consider fixed point split; is 32.32 ideal?
-        // Precalculate multiplication factor.
-        // Pulses to fractional turns (32.32 fixed point).
-        let mut t: i64 = 1 << 32;
-        t *= crate::ENCODER_RATIO_SPINDLE;
-        t /= crate::ENCODER_PPR * crate::ENCODER_RATIO_ENCODER;
-        // Encoder turns to fractional leadscrew turns (32.32).
-        t *= self.feed_rate_micron_per_rev as i64;
-        t *= crate::DRIVE_RATIO_LEADSCREW;
-        t /= crate::DRIVE_RATIO_MOTOR * crate::LEADSCREW_PITCH;
-        // Leadscrew turns to encoder pulses (32.32).
-        t *= crate::MOTOR_PPR;
-        self.feed_per_rev_factor = t
+        self.set_feed_factor(feed as i64, 1);
     }
     pub fn set_feed_rate_tpi(&mut self, tpi: i32) {
         // XXX bounds checking.
         self.feed_rate_micron_per_rev = 2540/tpi; // Approximation only.
+        self.feed = Feed::Tpi(tpi);
         self.fractional_pulses_remaining = 0;
-        // XXX consider fixed point split; is 32.32 ideal?
-        // Precalculate multiplication factor.
-        // Pulses to fractional turns (32.32 fixed point).
-        let mut t: i64 = 1 << 32;
-        t *= crate::ENCODER_RATIO_SPINDLE;
-        t /= crate::ENCODER_PPR * crate::ENCODER_RATIO_ENCODER;
-        // Encoder turns to fractional leadscrew turns (32.32).
-        t *= 25400;
-        t /= tpi as i64;
-        t *= crate::DRIVE_RATIO_LEADSCREW;
-        t /= crate::DRIVE_RATIO_MOTOR * crate::LEADSCREW_PITCH;
-        // Leadscrew turns to encoder pulses (32.32).
-        t *= crate::MOTOR_PPR;
-        self.feed_per_rev_factor = t
+        self.set_feed_factor(25400, tpi as i64);
+    }
+    // Precalculate multiplication factor. The configuration is validated
+    // for every feed the user interface offers; anything coarser that
+    // would overflow stops the carriage instead.
+    fn set_feed_factor(&mut self, micron: i64, per: i64) {
+        self.feed_per_rev_factor = self.config.feed_factor(micron, per).unwrap_or(0);
     }
 }
@@ -0,0 +1,106 @@
+//! Position accumulation for quadrature encoders on wrapping hardware
+//! counters.
+
+// Extends a BITS wide hardware counter to a 64-bit position, counted from
+// where the counter was when created. The counter must be sampled at least
+// once every half wrap, i.e. every 32767 pulses for a 16-bit counter.
+pub struct Encoder<const BITS: u32> {
+    count: u32,
+    position: i64,
+    // Position that whole detents have been taken up to.
+    detent_position: i64,
+}
+
+impl<const BITS: u32> Encoder<BITS> {
+    const SHIFT: u32 = 32 - BITS;
+
+    pub const fn new(count: u32) -> Self {
+        Encoder {
+            count,
+            position: 0,
+            detent_position: 0,
+        }
+    }
+
+    // Add a hardware counter sample, returning the movement since the last.
+    pub fn update(&mut self, count: u32) -> i64 {
+        // Shift the counter to the top bits so that wrapping arithmetic
+        // works the same whatever its width.
+        let delta = ((count << Self::SHIFT).wrapping_sub(self.count << Self::SHIFT) as i32
+            >> Self::SHIFT) as i64;
+        self.count = count;
+        self.position += delta;
+        delta
+    }
+
+    pub fn get_position(&self) -> i64 {
+        self.position
+    }
+
+    // Position for a hardware count taken since the last update, or
+    // latched by the hardware shortly before it.
+    pub fn get_position_at(&self, count: u32) -> i64 {
+        let offset = ((self.count << Self::SHIFT).wrapping_sub(count << Self::SHIFT) as i32
+            >> Self::SHIFT) as i64;
+        self.position - offset
+    }
+
+    // Whole detents moved since the last call, for encoders with the given
+    // pulses per detent. Part detents carry over to the next call, so
+    // that movement isn't lost and jitter at a detent doesn't count.
+    pub fn take_detents(&mut self, pulses_per_detent: u32) -> i32 {
+        let detents = (self.position - self.detent_position) / pulses_per_detent as i64;
+        self.detent_position += detents * pulses_per_detent as i64;
+        detents as i32
+    }
+}
+
+// Software quadrature decoder for sampled encoder signals, counting the
+// same way as the timers' encoder mode. Used to compare the raw signals
+// with the filtered hardware count. Samples where both signals changed
+// can't be decoded; they're glitches, or movement too fast for the
+// sample rate.
+pub struct Quadrature {
+    phase: u8,
+    position: i64,
+    invalid: u32,
+}
+
+impl Quadrature {
+    pub const fn new(a: bool, b: bool) -> Self {
+        Quadrature {
+            phase: Self::phase(a, b),
+            position: 0,
+            invalid: 0,
+        }
+    }
+
+    // Position in the forward sequence 00, 10, 11, 01 of (A, B).
+    const fn phase(a: bool, b: bool) -> u8 {
+        match (a, b) {
+            (false, false) => 0,
+            (true, false) => 1,
+            (true, true) => 2,
+            (false, true) => 3,
+        }
+    }
+
+    pub fn update(&mut self, a: bool, b: bool) {
+        let phase = Self::phase(a, b);
+        match phase.wrapping_sub(self.phase) & 3 {
+            1 => self.position += 1,
+            3 => self.position -= 1,
+            2 => self.invalid = self.invalid.wrapping_add(1),
+            _ => (),
+        }
+        self.phase = phase;
+    }
+
+    pub fn get_position(&self) -> i64 {
+        self.position
+    }
+
+    pub fn get_invalid(&self) -> u32 {
+        self.invalid
+    }
+}
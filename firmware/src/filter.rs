@@ -0,0 +1,119 @@
+//! Signal filters.
+
+pub trait Filter {
+    // Add a sample.
+    fn update(&mut self, value: i32);
+    // Filtered value, rounded to the nearest integer.
+    fn value(&self) -> i32;
+}
+
+// Divide, rounding halves away from zero.
+fn div_round(n: i64, d: i64) -> i64 {
+    if (n < 0) == (d < 0) {
+        (n + d / 2) / d
+    } else {
+        (n - d / 2) / d
+    }
+}
+
+// Exponential moving average: each sample moves the output 1/2^SHIFT of
+// the way towards it.
+pub struct Iir<const SHIFT: u32> {
+    state: i64, // 32.16 fixed point.
+}
+
+impl<const SHIFT: u32> Iir<SHIFT> {
+    pub const fn new() -> Self {
+        Iir { state: 0 }
+    }
+}
+
+impl<const SHIFT: u32> Filter for Iir<SHIFT> {
+    fn update(&mut self, value: i32) {
+        self.state += (((value as i64) << 16) - self.state) >> SHIFT;
+    }
+    fn value(&self) -> i32 {
+        div_round(self.state, 1 << 16) as i32
+    }
+}
+
+// Median of the last N samples, which rejects glitches shorter than half
+// the window without smearing steps.
+pub struct Median<const N: usize> {
+    ring_fifo: [i32; N],
+    offset: usize,
+}
+
+impl<const N: usize> Median<N> {
+    pub const fn new() -> Self {
+        Median {
+            ring_fifo: [0; N],
+            offset: 0,
+        }
+    }
+}
+
+impl<const N: usize> Filter for Median<N> {
+    fn update(&mut self, value: i32) {
+        self.ring_fifo[self.offset] = value;
+        self.offset = (self.offset + 1) % N;
+    }
+    fn value(&self) -> i32 {
+        let mut sorted = self.ring_fifo;
+        sorted.sort_unstable();
+        if N % 2 == 1 {
+            sorted[N / 2]
+        } else {
+            div_round(sorted[N / 2 - 1] as i64 + sorted[N / 2] as i64, 2) as i32
+        }
+    }
+}
+
+// Finite impulse response filter over the last N samples, with the given
+// tap weights (most recent sample first). Sums are 64-bit, so any window
+// length and i32 sample range is safe.
+pub struct Fir<const N: usize> {
+    taps: [i32; N],
+    taps_sum: i64,
+    ring_fifo: [i32; N],
+    offset: usize,
+}
+
+impl<const N: usize> Fir<N> {
+    // Taps must not sum to zero.
+    pub const fn new(taps: [i32; N]) -> Self {
+        let mut taps_sum = 0;
+        let mut i = 0;
+        while i < N {
+            taps_sum += taps[i] as i64;
+            i += 1;
+        }
+        assert!(taps_sum != 0);
+        Fir {
+            taps,
+            taps_sum,
+            ring_fifo: [0; N],
+            offset: 0,
+        }
+    }
+    // Equally weighted (moving average) filter.
+    pub const fn boxcar() -> Self {
+        Self::new([1; N])
+    }
+}
+
+impl<const N: usize> Filter for Fir<N> {
+    fn update(&mut self, value: i32) {
+        self.ring_fifo[self.offset] = value;
+        self.offset = (self.offset + 1) % N;
+    }
+    fn value(&self) -> i32 {
+        let mut sum: i64 = 0;
+        for (i, &tap) in self.taps.iter().enumerate() {
+            // Most recent sample is just before offset.
+            let sample = self.ring_fifo[(self.offset + N - 1 - i) % N];
+            sum += tap as i64 * sample as i64;
+        }
+        div_round(sum, self.taps_sum) as i32
+    }
+}
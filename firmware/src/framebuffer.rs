@@ -0,0 +1,166 @@
+//! Shadow framebuffer for character displays.
+//!
+//! Writes go to an in-memory copy of the screen, and flush() sends only the
+//! cells that differ from what the display is already showing. Every byte
+//! written to the display busy-waits for the bus timing, so redrawing an
+//! unchanged screen costs nothing and a changing number costs a few bytes.
+//! Address commands are only sent where the display's auto-incrementing
+//! address doesn't already point at the next changed cell.
+use core::fmt::{self, Write};
+
+use crate::charmap::CharMap;
+use crate::lcd::{self, CharacterDisplay, Error};
+
+// Changed cells this close together are joined by rewriting the unchanged
+// cells between them, which costs no more than an address command.
+const MAX_GAP: usize = 1;
+
+pub struct FrameBuffer<'a, D, const COLS: usize, const ROWS: usize> {
+    display: &'a mut D,
+    cells: [[u8; COLS]; ROWS],
+    // What the display is showing, valid unless stale.
+    shown: [[u8; COLS]; ROWS],
+    stale: bool,
+    x: usize,
+    y: usize,
+}
+
+impl<'a, D, const COLS: usize, const ROWS: usize> FrameBuffer<'a, D, COLS, ROWS>
+where
+    D: CharacterDisplay,
+{
+    pub fn new(display: &'a mut D) -> FrameBuffer<'a, D, COLS, ROWS> {
+        const { assert!(COLS == D::COLS && ROWS == D::ROWS) };
+        FrameBuffer {
+            display,
+            cells: [[b' '; COLS]; ROWS],
+            shown: [[b' '; COLS]; ROWS],
+            stale: true,
+            x: 0,
+            y: 0,
+        }
+    }
+}
+
+impl<'a, D, const COLS: usize, const ROWS: usize> CharacterDisplay
+    for FrameBuffer<'a, D, COLS, ROWS>
+where
+    D: CharacterDisplay,
+{
+    const COLS: usize = COLS;
+    const ROWS: usize = ROWS;
+
+    fn charmap(&self) -> &'static CharMap {
+        self.display.charmap()
+    }
+    fn init(&mut self) {
+        self.display.init();
+        self.cells = [[b' '; COLS]; ROWS];
+        self.shown = [[b' '; COLS]; ROWS];
+        self.stale = false;
+        self.x = 0;
+        self.y = 0;
+    }
+    fn cursor(&mut self, show_cursor: bool, blink_cursor: bool) {
+        self.display.cursor(show_cursor, blink_cursor);
+    }
+    // Addresses outside the screen leave the cursor off the end of the last
+    // row, where writes are ignored.
+    fn addr(&mut self, addr: u8) {
+        self.x = COLS;
+        self.y = ROWS - 1;
+        for y in 0..ROWS {
+            let start = lcd::ddram_addr(COLS, 0, y as u8);
+            if (start..start + COLS as u8).contains(&addr) {
+                self.x = (addr - start) as usize;
+                self.y = y;
+            }
+        }
+    }
+    fn char(&mut self, c: u8) {
+        if self.x < COLS && self.y < ROWS {
+            self.cells[self.y][self.x] = c;
+        }
+        self.x += 1;
+    }
+    fn clear(&mut self) {
+        self.cells = [[b' '; COLS]; ROWS];
+        self.x = 0;
+        self.y = 0;
+    }
+    // Character generator RAM isn't buffered. Cells showing the character
+    // change as soon as it's programmed.
+    fn program_character(&mut self, index: u8, glyph: &[u8; 8]) {
+        self.display.program_character(index, glyph);
+    }
+    fn brightness(&mut self, percent: u8) {
+        self.display.brightness(percent);
+    }
+    fn position(&mut self, x: u8, y: u8) -> Result<(), Error> {
+        if x as usize > COLS || y as usize >= ROWS {
+            return Err(Error::BoundsError {});
+        }
+        self.x = x as usize;
+        self.y = y as usize;
+        Ok(())
+    }
+    // Like the display itself, a string that doesn't fit in the rest of the
+    // line isn't written at all.
+    fn string(&mut self, s: &str) -> Result<u8, Error> {
+        let mut d: [u8; COLS] = [0u8; COLS];
+        let mut i: usize = 0;
+        for c in s.chars() {
+            if self.x + i >= COLS {
+                return Err(Error::BoundsError {});
+            }
+            d[i] = self.display.charmap().encode(c);
+            i += 1;
+        }
+        self.cells[self.y][self.x..self.x + i].copy_from_slice(&d[0..i]);
+        self.x += i;
+        Ok(i as u8)
+    }
+    fn at(&mut self, x: u8, y: u8) -> &mut Self {
+        self.position(x, y).ok();
+        self
+    }
+    fn flush(&mut self) -> Result<(), Error> {
+        for y in 0..ROWS {
+            // Column the display's address counter is at on this row.
+            let mut next: Option<usize> = None;
+            let mut x = 0;
+            while x < COLS {
+                if !self.stale && self.cells[y][x] == self.shown[y][x] {
+                    x += 1;
+                    continue;
+                }
+                match next {
+                    Some(n) if x - n <= MAX_GAP => {
+                        for i in n..x {
+                            self.display.char(self.cells[y][i]);
+                        }
+                    }
+                    _ => {
+                        self.display.position(x as u8, y as u8).ok();
+                    }
+                }
+                self.display.char(self.cells[y][x]);
+                next = Some(x + 1);
+                x += 1;
+            }
+        }
+        self.shown = self.cells;
+        self.stale = false;
+        self.display.flush()
+    }
+}
+
+impl<'a, D, const COLS: usize, const ROWS: usize> Write for FrameBuffer<'a, D, COLS, ROWS>
+where
+    D: CharacterDisplay,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.string(s).map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
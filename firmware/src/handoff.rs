@@ -0,0 +1,81 @@
+//! Lock-free handoff between the control interrupt and the background loop.
+//!
+//! Both rely on the control interrupt running to completion whenever it
+//! preempts the background loop, and on each having a single writer.
+use core::cell::UnsafeCell;
+use core::sync::atomic::{fence, AtomicU32, AtomicUsize, Ordering};
+
+// Double buffer, written by the background loop and read by the interrupt.
+// The writer fills the inactive slot and then switches to it, so the
+// interrupt always reads a complete value.
+pub struct Mailbox<T> {
+    slots: [UnsafeCell<T>; 2],
+    active: AtomicUsize,
+}
+
+unsafe impl<T: Send> Sync for Mailbox<T> {}
+
+impl<T: Copy> Mailbox<T> {
+    pub const fn new(value: T) -> Self {
+        Mailbox {
+            slots: [UnsafeCell::new(value), UnsafeCell::new(value)],
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    // Background loop only.
+    pub fn write(&self, value: T) {
+        let next = 1 - self.active.load(Ordering::Relaxed);
+        // The interrupt only reads the active slot, and can't be part way
+        // through reading this one from before the last switch.
+        unsafe { *self.slots[next].get() = value };
+        self.active.store(next, Ordering::Release);
+    }
+
+    // Control interrupt only.
+    pub fn read(&self) -> T {
+        let active = self.active.load(Ordering::Acquire);
+        unsafe { *self.slots[active].get() }
+    }
+}
+
+// Sequence lock, written by the interrupt and read by the background loop
+// or lower priority interrupts.
+// The reader retries if the interrupt updated the value while it was
+// being copied.
+pub struct SeqLock<T> {
+    seq: AtomicU32,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Sync for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(value: T) -> Self {
+        SeqLock {
+            seq: AtomicU32::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    // Control interrupt only.
+    pub fn write(&self, value: T) {
+        let seq = self.seq.load(Ordering::Relaxed);
+        self.seq.store(seq.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        unsafe { *self.value.get() = value };
+        self.seq.store(seq.wrapping_add(2), Ordering::Release);
+    }
+
+    // Lower priority than the control interrupt only.
+    pub fn read(&self) -> T {
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            let value = unsafe { core::ptr::read_volatile(self.value.get()) };
+            fence(Ordering::Acquire);
+            if seq.is_multiple_of(2) && self.seq.load(Ordering::Relaxed) == seq {
+                return value;
+            }
+        }
+    }
+}
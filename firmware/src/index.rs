@@ -0,0 +1,74 @@
+//! Spindle encoder health monitoring using the encoder's index pulse.
+//!
+//! The hardware latches the spindle encoder count on each index pulse.
+//! Moving the same way, successive index pulses should be a whole number
+//! of encoder revolutions apart; anything else means counts were missed
+//! or gained in between. When the spindle reverses, the index pulse is
+//! seen again from its other edge and the interval isn't checked.
+
+// Statistics since startup. Errors are in encoder counts.
+#[derive(Clone, Copy)]
+pub struct Stats {
+    // Intervals between index pulses that were checked.
+    pub checked: u32,
+    pub errors: u32,
+    pub last_error: i32,
+    pub max_error: i32,
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Stats {
+            checked: 0,
+            errors: 0,
+            last_error: 0,
+            max_error: 0,
+        }
+    }
+}
+
+pub struct IndexMonitor {
+    last_position: Option<i64>,
+    stats: Stats,
+}
+
+impl IndexMonitor {
+    pub const fn new() -> Self {
+        IndexMonitor {
+            last_position: None,
+            stats: Stats::new(),
+        }
+    }
+
+    // Add the spindle encoder position latched by an index pulse, given the
+    // encoder's counts per revolution.
+    pub fn update(&mut self, position: i64, counts_per_rev: i64) {
+        let last_position = self.last_position.replace(position);
+        let Some(last_position) = last_position else {
+            return;
+        };
+        if counts_per_rev <= 0 {
+            return;
+        }
+        let interval = position - last_position;
+        if interval.abs() < counts_per_rev / 2 {
+            // Reversed through the index.
+            return;
+        }
+        // Distance from the nearest whole revolution.
+        let error = (interval + counts_per_rev / 2).rem_euclid(counts_per_rev) - counts_per_rev / 2;
+        self.stats.checked = self.stats.checked.wrapping_add(1);
+        if error != 0 {
+            let error = error.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+            self.stats.errors = self.stats.errors.wrapping_add(1);
+            self.stats.last_error = error;
+            if error.unsigned_abs() > self.stats.max_error.unsigned_abs() {
+                self.stats.max_error = error;
+            }
+        }
+    }
+
+    pub fn get_stats(&self) -> &Stats {
+        &self.stats
+    }
+}
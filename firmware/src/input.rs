@@ -0,0 +1,257 @@
+//! Knob and button input, as events.
+//!
+//! Samples the buttons and the knobs' encoder counts every pass of the main
+//! loop, debounces the buttons and takes the knobs in whole detents, and
+//! queues events for the user interface: presses and releases, long
+//! presses, double clicks, two buttons held together, and knobs turned with
+//! or without their button held.
+use crate::encoder::Encoder;
+
+// A button must read the same for this long to change state.
+const DEBOUNCE_MS: i64 = 20;
+const LONG_PRESS_MS: i64 = 1000;
+// Longest time from the release of one click to the release of the next.
+const DOUBLE_CLICK_MS: i64 = 400;
+const CHORD_MS: i64 = 1000;
+const QUEUE_LEN: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Button {
+    Button1 = 0,
+    ModeKnob,
+    FeedKnob,
+}
+
+impl Button {
+    const ALL: [Button; 3] = [Button::Button1, Button::ModeKnob, Button::FeedKnob];
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Knob {
+    Mode = 0,
+    Feed,
+}
+
+impl Knob {
+    const ALL: [Knob; 2] = [Knob::Mode, Knob::Feed];
+
+    // The knob's push switch.
+    fn button(&self) -> Button {
+        match self {
+            Knob::Mode => Button::ModeKnob,
+            Knob::Feed => Button::FeedKnob,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    Press(Button),
+    Release(Button),
+    // Held down on its own, without turning its knob.
+    LongPress(Button),
+    // The second of two short presses close together. The first is just a
+    // press and release.
+    DoubleClick(Button),
+    // Two buttons held down together, the first pressed first. Neither is
+    // then a long press or a click.
+    Chord(Button, Button),
+    // Detents turned, positive clockwise. Turns are dropped while two
+    // buttons are held, as the knobs get nudged when held together.
+    Turn(Knob, i32),
+    // Detents turned with the knob's button held. The press is then not a
+    // long press or a click.
+    PressedTurn(Knob, i32),
+}
+
+#[derive(Clone, Copy)]
+struct ButtonState {
+    // Last sample, and when it last changed.
+    raw: bool,
+    raw_since: i64,
+    down: bool,
+    pressed_at: i64,
+    // This press has been taken as something other than a click.
+    used: bool,
+    // Release of the last click, for double clicks.
+    clicked_at: Option<i64>,
+}
+
+impl ButtonState {
+    const fn new() -> Self {
+        ButtonState {
+            raw: false,
+            raw_since: 0,
+            down: false,
+            pressed_at: 0,
+            used: false,
+            clicked_at: None,
+        }
+    }
+}
+
+pub struct Input {
+    buttons: [ButtonState; 3],
+    knobs: [Encoder<16>; 2],
+    // A chord has been sent for the buttons held now.
+    chorded: bool,
+    queue: [Event; QUEUE_LEN],
+    len: usize,
+}
+
+impl Input {
+    // Starting from the knobs' hardware counts.
+    pub const fn new(mode_count: u32, feed_count: u32) -> Self {
+        Input {
+            buttons: [ButtonState::new(); 3],
+            knobs: [Encoder::new(mode_count), Encoder::new(feed_count)],
+            chorded: false,
+            queue: [Event::Press(Button::Button1); QUEUE_LEN],
+            len: 0,
+        }
+    }
+
+    // Sample the inputs, true for buttons pressed.
+    pub fn update(
+        &mut self,
+        now_ms: i64,
+        button1: bool,
+        mode_button: bool,
+        feed_button: bool,
+        mode_count: u32,
+        feed_count: u32,
+    ) {
+        for (button, raw) in Button::ALL
+            .into_iter()
+            .zip([button1, mode_button, feed_button])
+        {
+            self.debounce(now_ms, button, raw);
+        }
+
+        let held = self.buttons.iter().filter(|b| b.down).count();
+        if held >= 2 {
+            for state in self.buttons.iter_mut().filter(|b| b.down) {
+                state.used = true;
+            }
+            if !self.chorded {
+                let mut down = Button::ALL.into_iter().filter(|&b| self.state(b).down);
+                if let (Some(a), Some(b)) = (down.next(), down.next()) {
+                    let (first, second) = if self.state(b).pressed_at < self.state(a).pressed_at {
+                        (b, a)
+                    } else {
+                        (a, b)
+                    };
+                    if now_ms - self.state(second).pressed_at >= CHORD_MS {
+                        self.push(Event::Chord(first, second));
+                        self.chorded = true;
+                    }
+                }
+            }
+        } else {
+            self.chorded = false;
+        }
+        for button in Button::ALL {
+            let state = &mut self.buttons[button as usize];
+            if state.down && !state.used && now_ms - state.pressed_at >= LONG_PRESS_MS {
+                state.used = true;
+                self.push(Event::LongPress(button));
+            }
+        }
+
+        for (knob, count) in Knob::ALL.into_iter().zip([mode_count, feed_count]) {
+            let encoder = &mut self.knobs[knob as usize];
+            encoder.update(count);
+            let detents = encoder.take_detents(crate::UI_ENCODER_PULSE_PER_DETENT);
+            if detents == 0 || held >= 2 {
+                continue;
+            }
+            let state = &mut self.buttons[knob.button() as usize];
+            if state.down {
+                state.used = true;
+                self.push(Event::PressedTurn(knob, detents));
+            } else {
+                self.push(Event::Turn(knob, detents));
+            }
+        }
+    }
+
+    // The oldest event not yet taken.
+    pub fn next_event(&mut self) -> Option<Event> {
+        if self.len == 0 {
+            return None;
+        }
+        let event = self.queue[0];
+        self.queue.copy_within(1..self.len, 0);
+        self.len -= 1;
+        Some(event)
+    }
+
+    // Debounced button state.
+    pub fn is_down(&self, button: Button) -> bool {
+        self.state(button).down
+    }
+
+    pub fn any_down(&self) -> bool {
+        self.buttons.iter().any(|b| b.down)
+    }
+
+    // Knob position in encoder pulses.
+    pub fn get_position(&self, knob: Knob) -> i64 {
+        self.knobs[knob as usize].get_position()
+    }
+
+    fn state(&self, button: Button) -> &ButtonState {
+        &self.buttons[button as usize]
+    }
+
+    fn debounce(&mut self, now_ms: i64, button: Button, raw: bool) {
+        let state = &mut self.buttons[button as usize];
+        if raw != state.raw {
+            state.raw = raw;
+            state.raw_since = now_ms;
+        }
+        if raw == state.down || now_ms - state.raw_since < DEBOUNCE_MS {
+            return;
+        }
+        state.down = raw;
+        if raw {
+            state.pressed_at = now_ms;
+            state.used = false;
+            self.push(Event::Press(button));
+            return;
+        }
+        let clicked_at = state.clicked_at.take();
+        let double = match clicked_at {
+            Some(t) => !state.used && now_ms - t <= DOUBLE_CLICK_MS,
+            None => false,
+        };
+        if !state.used && !double {
+            state.clicked_at = Some(now_ms);
+        }
+        self.push(Event::Release(button));
+        if double {
+            self.push(Event::DoubleClick(button));
+        }
+    }
+
+    // Turns of a knob since the last event taken are added together, so
+    // fast turning doesn't fill the queue. Events are dropped if it's full.
+    fn push(&mut self, event: Event) {
+        if let Some(last) = self.queue[..self.len].last_mut() {
+            match (last, event) {
+                (Event::Turn(k, n), Event::Turn(knob, detents))
+                | (Event::PressedTurn(k, n), Event::PressedTurn(knob, detents))
+                    if *k == knob =>
+                {
+                    *n += detents;
+                    return;
+                }
+                _ => (),
+            }
+        }
+        if self.len < QUEUE_LEN {
+            self.queue[self.len] = event;
+            self.len += 1;
+        }
+    }
+}
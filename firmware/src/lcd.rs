@@ -1,46 +1,166 @@
 //! Interface for HD44870-style character displays
-use core::fmt::{self, Write};
+use crate::charmap::CharMap;
+use core::fmt;
+#[cfg(not(feature = "oled"))]
+use core::fmt::Write;
+#[cfg(not(feature = "oled"))]
 use embedded_hal::delay::DelayNs;
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+use embedded_hal::digital::InputPin;
+#[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
 use embedded_hal::digital::OutputPin;
+#[cfg(feature = "lcd-i2c")]
+use embedded_hal::i2c::I2c;
 
-#[derive(Debug)]
+// LCD controllers are much slower than the VFD. Without reading the busy
+// flag, wait this long after each byte (most commands take 37μs, with
+// margin for slower clones)...
+#[cfg(not(feature = "oled"))]
+const COMMAND_DELAY_US: u32 = 50;
+// ...and this long after a clear.
+#[cfg(not(feature = "oled"))]
+const CLEAR_DELAY_US: u32 = 2000;
+// Time for the LCD to reset itself after power on.
+#[cfg(any(feature = "lcd-4bit", feature = "lcd-i2c"))]
+const POWER_ON_DELAY_MS: u32 = 50;
+// Give up waiting for the busy flag after this many polls, each at least
+// 460ns. This is well over the slowest command (clear, 1.52ms on an
+// HD44780).
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+const BUSY_POLL_LIMIT: u32 = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     BoundsError,
-    UnsupportedCharacter { c: char },
+    // Only the 8-bit driver reads the busy flag.
+    #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+    BusyTimeout,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Error::BoundsError => write!(f, "Screen bounds exceeded"),
-            Error::UnsupportedCharacter { c } => {
-                write!(f, "display does not support character {}", c)
-            }
+            #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+            Error::BusyTimeout => write!(f, "display busy flag timed out"),
+        }
+    }
+}
+
+impl Error {
+    // Short name, for the console.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Error::BoundsError => "bounds",
+            #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+            Error::BusyTimeout => "busy_timeout",
         }
     }
 }
 
-// XXX want clear_to_eol() too. Maybe also program_character()
+// Display size, given to the drivers to set their COLS and ROWS. 16x2, 20x4
+// and 40x2 displays are supported.
+#[cfg(not(feature = "oled"))]
+pub struct Geometry<const COLS: usize, const ROWS: usize>;
+
+// Longest line of any supported display.
+const MAX_COLS: usize = 40;
+
+// Display data RAM address of a character position. The third and fourth
+// lines of four line displays continue on from the first and second.
+pub const fn ddram_addr(cols: usize, x: u8, y: u8) -> u8 {
+    let rows = [0, 0x40, cols as u8, 0x40 + cols as u8];
+    rows[y as usize] + x
+}
+
+// XXX want clear_to_eol() too.
 
 pub trait CharacterDisplay {
+    // Size in characters.
+    const COLS: usize;
+    const ROWS: usize;
+    // Unused in the OLED build: its text grid isn't a display of its own, so
+    // is never initialised or dimmed.
+    #[allow(dead_code)]
     fn init(&mut self);
+    // The display's character ROM.
+    fn charmap(&self) -> &'static CharMap;
     #[allow(dead_code)]
     fn cursor(&mut self, show_cursor: bool, blink_cursor: bool);
-    #[allow(dead_code)]
     fn addr(&mut self, addr: u8);
-    #[allow(dead_code)]
     fn char(&mut self, c: u8);
-    #[allow(dead_code)]
     fn clear(&mut self);
+    // Set the brightness, as a percentage, to the nearest level the display
+    // has at or above it. Displays without brightness control ignore it.
+    // Unused in the OLED build, as init().
     #[allow(dead_code)]
-    fn position(&mut self, x: u8, y: u8) -> Result<(), Error>;
-    #[allow(dead_code)]
-    fn string(&mut self, s: &str) -> Result<u8, Error>;
-    #[allow(dead_code)]
-    fn at(&mut self, x: u8, y: u8) -> &mut Self;
+    fn brightness(&mut self, _percent: u8) {}
+    // Program one of the eight user-defined characters (codes 0-7) from
+    // its rows of five pixels, top first. This leaves the display's address
+    // in character generator RAM, so set the position before writing text.
+    fn program_character(&mut self, index: u8, glyph: &[u8; 8]);
+    fn position(&mut self, x: u8, y: u8) -> Result<(), Error> {
+        if x as usize > Self::COLS || y as usize >= Self::ROWS {
+            return Err(Error::BoundsError {});
+        }
+        self.addr(ddram_addr(Self::COLS, x, y));
+        Ok(())
+    }
+    // Nothing is written unless the whole string can be.
+    fn string(&mut self, s: &str) -> Result<u8, Error> {
+        let mut d: [u8; MAX_COLS] = [0u8; MAX_COLS];
+        let mut i: usize = 0;
+        for c in s.chars() {
+            if i >= Self::COLS {
+                return Err(Error::BoundsError {});
+            }
+            d[i] = self.charmap().encode(c);
+            i += 1;
+        }
+        for c in &d[0..i] {
+            self.char(*c);
+        }
+        Ok(i as u8)
+    }
+    fn at(&mut self, x: u8, y: u8) -> &mut Self {
+        self.position(x, y).ok();
+        self
+    }
+    // Send any buffered changes to the display. Unbuffered displays write
+    // immediately, so there's nothing to do. Returns any error the display
+    // had since the last flush that its writes couldn't report.
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+// A data pin, which is turned around while the busy flag is read so that
+// nothing drives against the display. DB7 carries the flag, so also reads.
+pub trait DataPin: OutputPin {
+    fn set_input(&mut self);
+    fn set_output(&mut self);
 }
 
-pub struct Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY> {
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+pub struct Display8Bit<
+    'a,
+    RS,
+    RW,
+    E,
+    DB0,
+    DB1,
+    DB2,
+    DB3,
+    DB4,
+    DB5,
+    DB6,
+    DB7,
+    DELAY,
+    const COLS: usize,
+    const ROWS: usize,
+> {
     rs: &'a mut RS,
     rw: &'a mut RW,
     e: &'a mut E,
@@ -53,25 +173,51 @@ pub struct Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DE
     db6: &'a mut DB6,
     db7: &'a mut DB7,
     delay: &'a mut DELAY,
+    charmap: &'static CharMap,
+    // Brightness bits (BR1, BR0) of the function set command.
+    brightness: u8,
+    // Set if the busy flag didn't clear, after which the display is written
+    // with fixed delays instead of waiting for it.
+    busy_timeout: bool,
+    // The timeout, until flush() reports it.
+    error: Option<Error>,
 }
-impl<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY>
-    Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY>
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl<
+        'a,
+        RS,
+        RW,
+        E,
+        DB0,
+        DB1,
+        DB2,
+        DB3,
+        DB4,
+        DB5,
+        DB6,
+        DB7,
+        DELAY,
+        const COLS: usize,
+        const ROWS: usize,
+    > Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS>
 where
     RS: OutputPin,
     RW: OutputPin,
     E: OutputPin,
-    DB0: OutputPin,
-    DB1: OutputPin,
-    DB2: OutputPin,
-    DB3: OutputPin,
-    DB4: OutputPin,
-    DB5: OutputPin,
-    DB6: OutputPin,
-    DB7: OutputPin,
+    DB0: DataPin,
+    DB1: DataPin,
+    DB2: DataPin,
+    DB3: DataPin,
+    DB4: DataPin,
+    DB5: DataPin,
+    DB6: DataPin,
+    DB7: DataPin + InputPin,
     DELAY: DelayNs,
 {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
+        _geometry: Geometry<COLS, ROWS>,
+        charmap: &'static CharMap,
         rs: &'a mut RS,
         rw: &'a mut RW,
         e: &'a mut E,
@@ -84,9 +230,11 @@ where
         db6: &'a mut DB6,
         db7: &'a mut DB7,
         delay: &'a mut DELAY,
-    ) -> Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY> {
+    ) -> Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS> {
+        // RW idles low, as the data pins are outputs other than while
+        // reading the busy flag.
         rs.set_high().ok();
-        rw.set_high().ok();
+        rw.set_low().ok();
         e.set_high().ok();
         db0.set_high().ok();
         db1.set_high().ok();
@@ -109,12 +257,72 @@ where
             db6,
             db7,
             delay,
+            charmap,
+            brightness: 0b00,
+            busy_timeout: false,
+            error: None,
         }
     }
-    fn write(&mut self, rs: bool, words: &[u8]) {
+    fn set_data_input(&mut self) {
+        self.db0.set_input();
+        self.db1.set_input();
+        self.db2.set_input();
+        self.db3.set_input();
+        self.db4.set_input();
+        self.db5.set_input();
+        self.db6.set_input();
+        self.db7.set_input();
+    }
+    fn set_data_output(&mut self) {
+        self.db0.set_output();
+        self.db1.set_output();
+        self.db2.set_output();
+        self.db3.set_output();
+        self.db4.set_output();
+        self.db5.set_output();
+        self.db6.set_output();
+        self.db7.set_output();
+    }
+    // Wait for the display to finish the last command, returning false if
+    // it doesn't. E idles high, so each poll starts a new read cycle. The
+    // display drives all eight data lines while E is high with RW high, so
+    // they're only outputs again once RW is low.
+    fn wait_busy(&mut self) -> bool {
+        self.set_data_input();
+        self.rs.set_low().ok();
+        self.rw.set_high().ok();
+        let mut ready = false;
+        for _ in 0..BUSY_POLL_LIMIT {
+            self.e.set_low().ok();
+            // PWel = 230ns
+            self.delay.delay_ns(230);
+            self.e.set_high().ok();
+            // Tddr = 160ns
+            self.delay.delay_ns(230);
+            if self.db7.is_low().unwrap_or(false) {
+                ready = true;
+                break;
+            }
+        }
+        self.rw.set_low().ok();
+        self.set_data_output();
+        ready
+    }
+    // Each word waits for the display to be ready for it, rather than
+    // waiting afterwards, so the display works while the caller gets on
+    // with something else. Once the busy flag has timed out, each word is
+    // followed by a fixed delay instead, as for the LCD drivers, and only
+    // the write that timed out returns the error.
+    fn write(&mut self, rs: bool, words: &[u8]) -> Result<(), Error> {
         //hprintln!("xxx write rs={} word={:?}", rs, words);
-        self.rs.set_state(rs.into()).ok();
+        let mut result = Ok(());
         for word in words {
+            if !self.busy_timeout && !self.wait_busy() {
+                self.busy_timeout = true;
+                self.error = Some(Error::BusyTimeout);
+                result = Err(Error::BusyTimeout);
+            }
+            self.rs.set_state(rs.into()).ok();
             self.rw.set_low().ok();
             self.e.set_high().ok();
             // Tas = 20ns
@@ -133,31 +341,68 @@ where
             // PWel = 230ns
             self.delay.delay_ns(230);
             self.e.set_high().ok();
-            self.rw.set_high().ok();
+            if self.busy_timeout {
+                // Clear and return home are the slow commands.
+                if !rs && word & 0b11111100 == 0 {
+                    self.delay.delay_us(CLEAR_DELAY_US);
+                } else {
+                    self.delay.delay_us(COMMAND_DELAY_US);
+                }
+            }
         }
+        result
     }
 }
 
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
 #[allow(clippy::unusual_byte_groupings)]
-impl<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY> CharacterDisplay
-    for Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY>
+impl<
+        'a,
+        RS,
+        RW,
+        E,
+        DB0,
+        DB1,
+        DB2,
+        DB3,
+        DB4,
+        DB5,
+        DB6,
+        DB7,
+        DELAY,
+        const COLS: usize,
+        const ROWS: usize,
+    > CharacterDisplay
+    for Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS>
 where
     RS: OutputPin,
     RW: OutputPin,
     E: OutputPin,
-    DB0: OutputPin,
-    DB1: OutputPin,
-    DB2: OutputPin,
-    DB3: OutputPin,
-    DB4: OutputPin,
-    DB5: OutputPin,
-    DB6: OutputPin,
-    DB7: OutputPin,
+    DB0: DataPin,
+    DB1: DataPin,
+    DB2: DataPin,
+    DB3: DataPin,
+    DB4: DataPin,
+    DB5: DataPin,
+    DB6: DataPin,
+    DB7: DataPin + InputPin,
     DELAY: DelayNs,
 {
+    const COLS: usize = COLS;
+    const ROWS: usize = ROWS;
+
+    fn charmap(&self) -> &'static CharMap {
+        self.charmap
+    }
     fn init(&mut self) {
-        let d: [u8; 4] = [0b00_111_0_00, 0b00001_100, 0b1_0000000, 0b00000001];
-        self.write(false, &d);
+        let d: [u8; 4] = [
+            0b00_111_0_00 | self.brightness,
+            0b00001_100,
+            0b1_0000000,
+            0b00000001,
+        ];
+        self.busy_timeout = false;
+        self.write(false, &d).ok();
     }
     fn cursor(&mut self, show_cursor: bool, blink_cursor: bool) {
         self.write(
@@ -165,82 +410,263 @@ where
             &[0b00001_100
                 | (if show_cursor { 0x10 } else { 0b00 })
                 | (if blink_cursor { 0b01 } else { 0b00 })],
-        );
+        )
+        .ok();
     }
     fn addr(&mut self, addr: u8) {
         //hprintln!("addr {:#x}", addr);
-        self.write(false, &[0b1_0000000 | addr]);
+        self.write(false, &[0b1_0000000 | addr]).ok();
     }
     fn char(&mut self, c: u8) {
-        self.write(true, &[c]);
+        self.write(true, &[c]).ok();
     }
     fn clear(&mut self) {
-        self.write(false, &[0b00000001]);
+        self.write(false, &[0b00000001]).ok();
+    }
+    fn program_character(&mut self, index: u8, glyph: &[u8; 8]) {
+        self.write(false, &[0b01_000000 | (index & 0b111) << 3])
+            .ok();
+        self.write(true, glyph).ok();
+    }
+    // The VFD has 100%, 75%, 50% and 25% brightness. HD44780 LCDs ignore
+    // these bits.
+    fn brightness(&mut self, percent: u8) {
+        self.brightness = match percent {
+            76.. => 0b00,
+            51..=75 => 0b01,
+            26..=50 => 0b10,
+            _ => 0b11,
+        };
+        self.write(false, &[0b00_111_0_00 | self.brightness]).ok();
     }
+    // As the default, but reporting a busy flag timeout.
     fn position(&mut self, x: u8, y: u8) -> Result<(), Error> {
-        if x > 16 || y > 1 {
+        if x as usize > Self::COLS || y as usize >= Self::ROWS {
             return Err(Error::BoundsError {});
         }
-        //hprintln!("pos x={} y={}", x, y);
-        self.addr(x | (y * 0x40));
-        Ok(())
+        self.write(false, &[0b1_0000000 | ddram_addr(Self::COLS, x, y)])
     }
+    // As the default, but sent with a single write.
     fn string(&mut self, s: &str) -> Result<u8, Error> {
-        let mut d: [u8; 16] = [0u8; 16];
+        let mut d: [u8; MAX_COLS] = [0u8; MAX_COLS];
         let mut i: usize = 0;
         for c in s.chars() {
-            if i >= d.len() {
+            if i >= Self::COLS {
                 return Err(Error::BoundsError {});
             }
-            d[i] = match c {
-                '\\' => 0b10001100,
-                '~' => 0b10001110,
-                'Σ' => 0b11110110,
-                '◀' => 0b00011110,
-                '▲' => 0b00011111,
-                '▶' => 0b00011101,
-                '▼' => 0b00011100,
-                '←' => 0b01111111,
-                '↑' => 0b10011110,
-                '→' => 0b01111110,
-                '↓' => 0b10011111,
-                '●' => 0b10010100,
-                '°' => 0b11011111,
-                '○' => 0b10010101,
-                'α' => 0b11100000,
-                'β' => 0b11100010,
-                'θ' => 0b11110010,
-                'μ' => 0b11100100,
-                'π' => 0b11110111,
-                'Ω' => 0b11110100,
-                'ω' => 0b11110011,
-                'ρ' => 0b11100110,
-                'σ' => 0b11100101,
-                'ε' => 0b11100011,
-                ' '..='}' => c as u8,
-                _ => return Err(Error::UnsupportedCharacter { c }),
-            };
+            d[i] = self.charmap().encode(c);
             i += 1;
         }
-        self.write(true, &d[0..i]);
+        self.write(true, &d[0..i])?;
         Ok(i as u8)
     }
-    fn at(&mut self, x: u8, y: u8) -> &mut Self {
-        self.position(x, y).ok();
-        self
+    // Single characters and commands can't return the busy flag timeout, so
+    // it's kept for here.
+    fn flush(&mut self) -> Result<(), Error> {
+        self.error.take().map_or(Ok(()), Err)
+    }
+}
+
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl<
+        'a,
+        RS,
+        RW,
+        E,
+        DB0,
+        DB1,
+        DB2,
+        DB3,
+        DB4,
+        DB5,
+        DB6,
+        DB7,
+        DELAY,
+        const COLS: usize,
+        const ROWS: usize,
+    > Write
+    for Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS>
+where
+    RS: OutputPin,
+    RW: OutputPin,
+    E: OutputPin,
+    DB0: DataPin,
+    DB1: DataPin,
+    DB2: DataPin,
+    DB3: DataPin,
+    DB4: DataPin,
+    DB5: DataPin,
+    DB6: DataPin,
+    DB7: DataPin + InputPin,
+    DELAY: DelayNs,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.string(s).map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
+
+// HD44780 LCD on a 4-bit bus (DB4-DB7), which needs four fewer pins.
+#[cfg(feature = "lcd-4bit")]
+pub struct Display4Bit<
+    'a,
+    RS,
+    RW,
+    E,
+    DB4,
+    DB5,
+    DB6,
+    DB7,
+    DELAY,
+    const COLS: usize,
+    const ROWS: usize,
+> {
+    rs: &'a mut RS,
+    rw: &'a mut RW,
+    e: &'a mut E,
+    db4: &'a mut DB4,
+    db5: &'a mut DB5,
+    db6: &'a mut DB6,
+    db7: &'a mut DB7,
+    delay: &'a mut DELAY,
+    charmap: &'static CharMap,
+}
+
+#[cfg(feature = "lcd-4bit")]
+impl<'a, RS, RW, E, DB4, DB5, DB6, DB7, DELAY, const COLS: usize, const ROWS: usize>
+    Display4Bit<'a, RS, RW, E, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS>
+where
+    RS: OutputPin,
+    RW: OutputPin,
+    E: OutputPin,
+    DB4: OutputPin,
+    DB5: OutputPin,
+    DB6: OutputPin,
+    DB7: OutputPin,
+    DELAY: DelayNs,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        _geometry: Geometry<COLS, ROWS>,
+        charmap: &'static CharMap,
+        rs: &'a mut RS,
+        rw: &'a mut RW,
+        e: &'a mut E,
+        db4: &'a mut DB4,
+        db5: &'a mut DB5,
+        db6: &'a mut DB6,
+        db7: &'a mut DB7,
+        delay: &'a mut DELAY,
+    ) -> Display4Bit<'a, RS, RW, E, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS> {
+        rs.set_low().ok();
+        rw.set_low().ok();
+        e.set_low().ok();
+        Display4Bit {
+            rs,
+            rw,
+            e,
+            db4,
+            db5,
+            db6,
+            db7,
+            delay,
+            charmap,
+        }
+    }
+    fn write_nibble(&mut self, rs: bool, nibble: u8) {
+        self.rs.set_state(rs.into()).ok();
+        self.rw.set_low().ok();
+        // Tas = 40ns
+        self.delay.delay_ns(40);
+        self.e.set_high().ok();
+        self.db4.set_state((nibble & 0b0001 != 0).into()).ok();
+        self.db5.set_state((nibble & 0b0010 != 0).into()).ok();
+        self.db6.set_state((nibble & 0b0100 != 0).into()).ok();
+        self.db7.set_state((nibble & 0b1000 != 0).into()).ok();
+        // PWeh = 230ns
+        self.delay.delay_ns(230);
+        self.e.set_low().ok();
+        // PWel = 230ns
+        self.delay.delay_ns(230);
+    }
+    fn write(&mut self, rs: bool, words: &[u8]) {
+        for word in words {
+            self.write_nibble(rs, word >> 4);
+            self.write_nibble(rs, word & 0x0f);
+            self.delay.delay_us(COMMAND_DELAY_US);
+        }
+    }
+}
+
+#[cfg(feature = "lcd-4bit")]
+#[allow(clippy::unusual_byte_groupings)]
+impl<'a, RS, RW, E, DB4, DB5, DB6, DB7, DELAY, const COLS: usize, const ROWS: usize>
+    CharacterDisplay for Display4Bit<'a, RS, RW, E, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS>
+where
+    RS: OutputPin,
+    RW: OutputPin,
+    E: OutputPin,
+    DB4: OutputPin,
+    DB5: OutputPin,
+    DB6: OutputPin,
+    DB7: OutputPin,
+    DELAY: DelayNs,
+{
+    const COLS: usize = COLS;
+    const ROWS: usize = ROWS;
+
+    fn charmap(&self) -> &'static CharMap {
+        self.charmap
+    }
+    // Initialisation by instruction, which gets the controller into 4-bit
+    // mode from any state, including half way through a byte.
+    fn init(&mut self) {
+        self.delay.delay_ms(POWER_ON_DELAY_MS);
+        self.write_nibble(false, 0b0011);
+        self.delay.delay_us(4100);
+        self.write_nibble(false, 0b0011);
+        self.delay.delay_us(100);
+        self.write_nibble(false, 0b0011);
+        self.delay.delay_us(100);
+        self.write_nibble(false, 0b0010);
+        self.delay.delay_us(100);
+        self.write(
+            false,
+            &[0b00_101_0_00, 0b00001_100, 0b000001_10, 0b1_0000000],
+        );
+        self.clear();
+    }
+    fn cursor(&mut self, show_cursor: bool, blink_cursor: bool) {
+        self.write(
+            false,
+            &[0b00001_100
+                | (if show_cursor { 0b10 } else { 0b00 })
+                | (if blink_cursor { 0b01 } else { 0b00 })],
+        );
+    }
+    fn addr(&mut self, addr: u8) {
+        self.write(false, &[0b1_0000000 | addr]);
+    }
+    fn char(&mut self, c: u8) {
+        self.write(true, &[c]);
+    }
+    fn clear(&mut self) {
+        self.write(false, &[0b00000001]);
+        self.delay.delay_us(CLEAR_DELAY_US);
+    }
+    fn program_character(&mut self, index: u8, glyph: &[u8; 8]) {
+        self.write(false, &[0b01_000000 | (index & 0b111) << 3]);
+        self.write(true, glyph);
     }
 }
 
-impl<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY> Write
-    for Display8Bit<'a, RS, RW, E, DB0, DB1, DB2, DB3, DB4, DB5, DB6, DB7, DELAY>
+#[cfg(feature = "lcd-4bit")]
+impl<'a, RS, RW, E, DB4, DB5, DB6, DB7, DELAY, const COLS: usize, const ROWS: usize> Write
+    for Display4Bit<'a, RS, RW, E, DB4, DB5, DB6, DB7, DELAY, COLS, ROWS>
 where
     RS: OutputPin,
     RW: OutputPin,
     E: OutputPin,
-    DB0: OutputPin,
-    DB1: OutputPin,
-    DB2: OutputPin,
-    DB3: OutputPin,
     DB4: OutputPin,
     DB5: OutputPin,
     DB6: OutputPin,
@@ -252,3 +678,139 @@ where
         Ok(())
     }
 }
+
+// PCF8574 port bits on the common I2C LCD backpacks. DB4-DB7 are P4-P7.
+#[cfg(feature = "lcd-i2c")]
+const PCF8574_RS: u8 = 0b0001;
+#[cfg(feature = "lcd-i2c")]
+const PCF8574_E: u8 = 0b0100;
+#[cfg(feature = "lcd-i2c")]
+const PCF8574_BACKLIGHT: u8 = 0b1000;
+
+// HD44780 LCD behind a PCF8574 I2C port expander backpack, which drives it
+// in 4-bit mode. RW is held low, since the port can't be read back quickly
+// enough to be worth it.
+#[cfg(feature = "lcd-i2c")]
+pub struct DisplayI2c<'a, I2C, DELAY, const COLS: usize, const ROWS: usize> {
+    i2c: &'a mut I2C,
+    address: u8,
+    delay: &'a mut DELAY,
+    charmap: &'static CharMap,
+    backlight: bool,
+}
+
+#[cfg(feature = "lcd-i2c")]
+impl<'a, I2C, DELAY, const COLS: usize, const ROWS: usize> DisplayI2c<'a, I2C, DELAY, COLS, ROWS>
+where
+    I2C: I2c,
+    DELAY: DelayNs,
+{
+    pub fn new(
+        _geometry: Geometry<COLS, ROWS>,
+        charmap: &'static CharMap,
+        i2c: &'a mut I2C,
+        address: u8,
+        delay: &'a mut DELAY,
+    ) -> DisplayI2c<'a, I2C, DELAY, COLS, ROWS> {
+        DisplayI2c {
+            i2c,
+            address,
+            delay,
+            charmap,
+            backlight: true,
+        }
+    }
+    fn backlight_bit(&self) -> u8 {
+        if self.backlight {
+            PCF8574_BACKLIGHT
+        } else {
+            0
+        }
+    }
+    // Each I2C byte takes much longer than the E pulse width, so E is
+    // raised with the data and lowered in the following byte.
+    fn write_nibble(&mut self, rs: bool, nibble: u8) {
+        let port = nibble << 4 | if rs { PCF8574_RS } else { 0 } | self.backlight_bit();
+        self.i2c.write(self.address, &[port | PCF8574_E, port]).ok();
+    }
+    fn write(&mut self, rs: bool, words: &[u8]) {
+        for word in words {
+            self.write_nibble(rs, word >> 4);
+            self.write_nibble(rs, word & 0x0f);
+            self.delay.delay_us(COMMAND_DELAY_US);
+        }
+    }
+}
+
+#[cfg(feature = "lcd-i2c")]
+#[allow(clippy::unusual_byte_groupings)]
+impl<'a, I2C, DELAY, const COLS: usize, const ROWS: usize> CharacterDisplay
+    for DisplayI2c<'a, I2C, DELAY, COLS, ROWS>
+where
+    I2C: I2c,
+    DELAY: DelayNs,
+{
+    const COLS: usize = COLS;
+    const ROWS: usize = ROWS;
+
+    fn charmap(&self) -> &'static CharMap {
+        self.charmap
+    }
+    // Initialisation by instruction, as for Display4Bit.
+    fn init(&mut self) {
+        self.delay.delay_ms(POWER_ON_DELAY_MS);
+        self.write_nibble(false, 0b0011);
+        self.delay.delay_us(4100);
+        self.write_nibble(false, 0b0011);
+        self.delay.delay_us(100);
+        self.write_nibble(false, 0b0011);
+        self.delay.delay_us(100);
+        self.write_nibble(false, 0b0010);
+        self.delay.delay_us(100);
+        self.write(
+            false,
+            &[0b00_101_0_00, 0b00001_100, 0b000001_10, 0b1_0000000],
+        );
+        self.clear();
+    }
+    fn cursor(&mut self, show_cursor: bool, blink_cursor: bool) {
+        self.write(
+            false,
+            &[0b00001_100
+                | (if show_cursor { 0b10 } else { 0b00 })
+                | (if blink_cursor { 0b01 } else { 0b00 })],
+        );
+    }
+    fn addr(&mut self, addr: u8) {
+        self.write(false, &[0b1_0000000 | addr]);
+    }
+    fn char(&mut self, c: u8) {
+        self.write(true, &[c]);
+    }
+    fn clear(&mut self) {
+        self.write(false, &[0b00000001]);
+        self.delay.delay_us(CLEAR_DELAY_US);
+    }
+    fn program_character(&mut self, index: u8, glyph: &[u8; 8]) {
+        self.write(false, &[0b01_000000 | (index & 0b111) << 3]);
+        self.write(true, glyph);
+    }
+    // The backpack's backlight is only on or off, so it's off below 50%.
+    fn brightness(&mut self, percent: u8) {
+        self.backlight = percent >= 50;
+        self.i2c.write(self.address, &[self.backlight_bit()]).ok();
+    }
+}
+
+#[cfg(feature = "lcd-i2c")]
+impl<'a, I2C, DELAY, const COLS: usize, const ROWS: usize> Write
+    for DisplayI2c<'a, I2C, DELAY, COLS, ROWS>
+where
+    I2C: I2c,
+    DELAY: DelayNs,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.string(s).map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
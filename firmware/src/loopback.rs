@@ -0,0 +1,45 @@
+//! Hardware count of emitted step pulses, to audit the pulse generator.
+//!
+//! The step output (PB0) is jumpered to PA2, TIM9's channel 1 input, and
+//! TIM9 counts its rising edges. Once a control interrupt period's pulses
+//! have been emitted, the count should match the number of pulses the
+//! pulse generator was asked for.
+use stm32f4xx_hal::gpio::{Alternate, PA2};
+use stm32f4xx_hal::pac;
+use stm32f4xx_hal::rcc::{Enable, Reset};
+
+use crate::encoder::Encoder;
+
+pub struct StepCounter {
+    tim: pac::TIM9,
+    count: Encoder<16>,
+    _pin: PA2<Alternate<3>>,
+}
+
+impl StepCounter {
+    pub fn new(tim: pac::TIM9, pin: PA2<Alternate<3>>) -> Self {
+        unsafe {
+            pac::TIM9::enable_unchecked();
+            pac::TIM9::reset_unchecked();
+            // Count rising edges on channel 1's input (external clock
+            // mode 1, triggered by TI1FP1).
+            tim.ccmr1_input().write(|w| w.cc1s().bits(0b01));
+            tim.smcr.write(|w| w.ts().bits(0b101).sms().bits(0b111));
+            tim.arr.write(|w| w.arr().bits(u16::MAX));
+        }
+        tim.cr1.write(|w| w.cen().set_bit());
+        let count = Encoder::new(tim.cnt.read().cnt().bits() as u32);
+        StepCounter {
+            tim,
+            count,
+            _pin: pin,
+        }
+    }
+
+    // Pulses counted since startup. Must be called at least every 32767
+    // pulses.
+    pub fn update(&mut self) -> i64 {
+        self.count.update(self.tim.cnt.read().cnt().bits() as u32);
+        self.count.get_position()
+    }
+}
@@ -2,31 +2,71 @@
 #![no_main]
 
 //use panic_probe as _;
-use panic_halt as _;
 use stm32f4xx_hal as hal;
 
 mod lcd;
 use lcd::*;
+#[cfg(feature = "oled")]
+mod bitmap;
+mod capture;
+mod charmap;
+mod config;
+mod console;
 mod control;
-mod fir;
+mod encoder;
+mod filter;
+use filter::Filter;
+#[cfg(feature = "oled")]
+mod font;
+#[cfg(not(feature = "oled"))]
+mod framebuffer;
+#[cfg(not(feature = "oled"))]
+use framebuffer::FrameBuffer;
+mod handoff;
+mod index;
+mod input;
+#[cfg(feature = "step-loopback")]
+mod loopback;
+mod motion;
+#[cfg(feature = "oled")]
+mod oled;
+#[cfg(feature = "oled")]
+mod oledview;
 mod userinterface;
 use userinterface::Mode;
 mod pulse;
+mod qei;
 use pulse::Pulser;
+mod reset;
+mod servo;
+mod telemetry;
+mod textview;
+#[cfg(not(feature = "oled"))]
+use textview::TextRenderer;
+mod timing;
+mod tracker;
+mod velocity;
+mod view;
+mod widgets;
 
 use core::cell::{Cell, RefCell};
 use core::fmt::Write;
 //use cortex_m::asm::delay;
 use cortex_m::interrupt::Mutex;
+use cortex_m::peripheral::DWT;
 use cortex_m_rt::entry;
 //use cortex_m_semihosting::hprintln;
 use hal::dwt::DwtExt;
+#[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
 use hal::gpio::Speed;
 use hal::pac;
 use hal::pac::interrupt;
 use hal::prelude::*;
 use hal::qei::Qei;
-use hal::timer::{CounterUs, Event};
+use hal::timer::{CounterHz, CounterUs, Event};
+use hal::watchdog::IndependentWatchdog;
+#[cfg(feature = "usb")]
+use usb_device::prelude::*;
 
 const ENCODER_PPR: i64 = 2000;
 const ENCODER_RATIO_SPINDLE: i64 = -40;
@@ -39,18 +79,87 @@ const DRIVE_RATIO_LEADSCREW: i64 = 80; // 40 tooth pulley and x0.5 gearbox.
 const MOTOR_PPR: i64 = 3200;
 
 const UI_ENCODER_PULSE_PER_DETENT: u32 = 2;
+// Encoder input filters: the timer's digital filter setting (ICxF, 0-15)
+// and filter clock prescaler (1, 2 or 4).
+const SPINDLE_ENC_FILTER: i64 = 0;
+const SPINDLE_ENC_PRESCALER: i64 = 1;
+const UI_ENC_FILTER: i64 = 0;
+const UI_ENC_PRESCALER: i64 = 1;
 const DISPLAY_UPDATE_RATE: u32 = 10; // Hz
 
+// Display size: 16x2, 20x4 or 40x2.
+#[cfg(not(feature = "oled"))]
+const DISPLAY_COLS: usize = 16;
+#[cfg(not(feature = "oled"))]
+const DISPLAY_ROWS: usize = 2;
+const DISPLAY_BRIGHTNESS: i64 = 100; // %
+const DISPLAY_DIM_TIMEOUT: i64 = 600; // s
+
+// Character ROM: the VFD's, or for LCDs HD44780 A00 (Japanese), or A02
+// (European) with the lcd-a02 feature. The OLED has its own font.
+#[cfg(not(feature = "oled"))]
+static DISPLAY_CHARMAP: &charmap::CharMap = if cfg!(feature = "lcd-a02") {
+    &charmap::HD44780_A02
+} else if cfg!(any(feature = "lcd-4bit", feature = "lcd-i2c")) {
+    &charmap::HD44780_A00
+} else {
+    &charmap::VFD
+};
+
 const RPM_SMOOTH_UPDATE_RATE: u32 = 50; // Hz
 const RPM_SMOOTH_DISPLAY_RATE: u32 = DISPLAY_UPDATE_RATE; // Hz
 const RPM_SMOOTH_FIR_DEPTH: usize = 20;
+// Velocity samples to take the median of before smoothing, to reject glitches.
+const RPM_MEDIAN_DEPTH: usize = 3;
+
+const CONTROL_RATE: u32 = 20_000; // Hz
+
+const WATCHDOG_TIMEOUT: u32 = 250; // ms
 
-type Fir = fir::FirFilter<RPM_SMOOTH_FIR_DEPTH>;
-static G_RPM: Mutex<Cell<i32>> = Mutex::new(Cell::new(0));
-static G_ENC: Mutex<Cell<i32>> = Mutex::new(Cell::new(0));
+const CONSOLE_BAUD_RATE: u32 = 115200;
+#[cfg(feature = "usb")]
+const USB_VID: u16 = 0x16c0; // pid.codes shared VID/PID for CDC-ACM.
+#[cfg(feature = "usb")]
+const USB_PID: u16 = 0x27dd;
+#[cfg(feature = "lcd-i2c")]
+const LCD_I2C_ADDRESS: u8 = 0x27; // PCF8574 with A0-A2 high; 0x3f for PCF8574A.
+#[cfg(feature = "lcd-i2c")]
+const LCD_I2C_SPEED: u32 = 100; // kHz
+#[cfg(all(feature = "oled", not(feature = "oled-spi")))]
+const OLED_I2C_ADDRESS: u8 = 0x3c; // 0x3d with the address jumper moved.
+#[cfg(all(feature = "oled", not(feature = "oled-spi")))]
+const OLED_I2C_SPEED: u32 = 400; // kHz
+#[cfg(feature = "oled-spi")]
+const OLED_SPI_SPEED: u32 = 8; // MHz; the controllers manage 10MHz.
+
+// The controller on the OLED module: SSD1306, or SH1106 (on most 1.3" ones)
+// with the sh1106 feature.
+#[cfg(feature = "oled")]
+const OLED_CONTROLLER: oled::Controller = if cfg!(feature = "sh1106") {
+    oled::Controller::Sh1106
+} else {
+    oled::Controller::Ssd1306
+};
+#[cfg(all(feature = "lcd-4bit", feature = "lcd-i2c"))]
+compile_error!("the lcd-4bit and lcd-i2c features can't be used together");
+#[cfg(all(feature = "oled", any(feature = "lcd-4bit", feature = "lcd-i2c")))]
+compile_error!("the oled feature can't be used with lcd-4bit or lcd-i2c");
+#[cfg(all(
+    feature = "lcd-a02",
+    not(any(feature = "lcd-4bit", feature = "lcd-i2c"))
+))]
+compile_error!("the lcd-a02 feature needs lcd-4bit or lcd-i2c");
+
+type RpmMedian = filter::Median<RPM_MEDIAN_DEPTH>;
+type RpmFilter = filter::Fir<RPM_SMOOTH_FIR_DEPTH>;
+// Smoothed spindle encoder pulses per minute.
+static G_ENC_PPM: Mutex<Cell<i64>> = Mutex::new(Cell::new(0));
 static G_NOW: Mutex<Cell<i64>> = Mutex::new(Cell::new(0));
 static G_TIM: Mutex<RefCell<Option<CounterUs<pac::TIM5>>>> = Mutex::new(RefCell::new(None));
 
+// The interrupt attribute hands TIM below to the handler as a unique
+// reference, which the lint can't see.
+#[allow(static_mut_refs)]
 #[interrupt]
 fn TIM5() {
     // Take static reference to counter.
@@ -60,7 +169,8 @@ fn TIM5() {
     });
 
     let mut ms: i64 = 0;
-    static RPM_FIR: Mutex<RefCell<Fir>> = Mutex::new(RefCell::new(Fir::new()));
+    static RPM_MEDIAN: Mutex<RefCell<RpmMedian>> = Mutex::new(RefCell::new(RpmMedian::new()));
+    static RPM_FIR: Mutex<RefCell<RpmFilter>> = Mutex::new(RefCell::new(RpmFilter::boxcar()));
     cortex_m::interrupt::free(|cs| {
         // Update monotonic millisecond counter.
         let now = G_NOW.borrow(cs);
@@ -68,49 +178,157 @@ fn TIM5() {
         // I'll fix it closer to then.
         ms = now.get().saturating_add(1);
         now.set(ms);
-        // Calculate a FIR-smoothed RPM value every RPM_SMOOTH_UPDATE_RATE Hz.
+        // Smooth the control interrupt's spindle velocity estimate for
+        // display, sampling it every RPM_SMOOTH_UPDATE_RATE Hz.
         if ms % (1000 / RPM_SMOOTH_UPDATE_RATE) as i64 == 0 {
-            let enc = G_ENC.borrow(cs);
-            RPM_FIR.borrow(cs).borrow_mut().update(enc.get());
-            enc.set(0);
+            let velocity = G_STATUS.read().spindle_velocity;
+            let mut median = RPM_MEDIAN.borrow(cs).borrow_mut();
+            median.update(velocity as i32);
+            RPM_FIR.borrow(cs).borrow_mut().update(median.value());
         }
         if ms % (1000 / RPM_SMOOTH_DISPLAY_RATE) as i64 == 0 {
-            let mut val = RPM_FIR.borrow(cs).borrow().filtered_value() as i64;
-            // 'val' is average encoder pulses per interval over the FIR
-            // period; convert this to RPM.
-            val *= RPM_SMOOTH_UPDATE_RATE as i64; // pulses per second.
-            val *= 60; // pulses per minute.
-            val = (val * ENCODER_RATIO_SPINDLE) / ENCODER_RATIO_ENCODER;
-            val /= ENCODER_PPR; // RPM
-            G_RPM.borrow(cs).set(val as i32);
+            // Encoder pulses per minute; the main loop converts this to RPM
+            // using the machine configuration.
+            let val = RPM_FIR.borrow(cs).borrow().value() as i64;
+            G_ENC_PPM.borrow(cs).set(val);
         }
     });
 
     let _ = tim.wait();
 }
 
+// Commanded state for, and status from, the control interrupt.
+static G_COMMAND: handoff::Mailbox<motion::Command> = handoff::Mailbox::new(motion::Command::new());
+static G_STATUS: handoff::SeqLock<motion::Status> = handoff::SeqLock::new(motion::Status::new());
+static G_TIMING: handoff::SeqLock<timing::Stats> = handoff::SeqLock::new(timing::Stats::new());
+
+// Resources owned by the control interrupt.
+struct ControlResources {
+    timer: CounterHz<pac::TIM10>,
+    spindle_enc: Qei<pac::TIM2>,
+    spindle_position: encoder::Encoder<32>,
+    spindle_raw: encoder::Quadrature,
+    #[cfg(feature = "step-loopback")]
+    step_counter: loopback::StepCounter,
+    motion: motion::Motion,
+    loop_timer: timing::LoopTimer,
+    timing_reset: u32,
+}
+static G_CONTROL: Mutex<RefCell<Option<ControlResources>>> = Mutex::new(RefCell::new(None));
+
+// Spindle encoder A and B signals, as read from the pins.
+fn read_spindle_signals() -> (bool, bool) {
+    let idr = unsafe { (*pac::GPIOA::ptr()).idr.read().bits() };
+    (idr & (1 << 0) != 0, idr & (1 << 1) != 0)
+}
+
+// The drive's servo OK input (PA3), read directly as for the spindle
+// signals. It's inverted.
+fn read_servo_ok() -> bool {
+    let idr = unsafe { (*pac::GPIOA::ptr()).idr.read().bits() };
+    idr & (1 << 3) == 0
+}
+
+// Spindle encoder count latched by an index pulse since the last call, if
+// there was one.
+fn take_spindle_index() -> Option<u32> {
+    // Qei only uses TIM2's channels 1 and 2, and only the control interrupt
+    // touches channel 3.
+    let tim2 = unsafe { &*pac::TIM2::ptr() };
+    if tim2.sr.read().cc3if().bit_is_set() {
+        // Reading the capture clears the flag.
+        Some(tim2.ccr3().read().bits())
+    } else {
+        None
+    }
+}
+
+// Encoder sampling and step generation run at a fixed rate here, so that
+// they aren't held up by UI and display work in the background loop.
+#[interrupt]
+fn TIM1_UP_TIM10() {
+    // Nothing else preempts this interrupt, so the critical section costs
+    // little.
+    cortex_m::interrupt::free(|cs| {
+        if let Some(control) = G_CONTROL.borrow(cs).borrow_mut().as_mut() {
+            control.loop_timer.start();
+            let _ = control.timer.wait();
+            let command = G_COMMAND.read();
+            if command.timing_reset != control.timing_reset {
+                control.loop_timer.reset();
+                control.timing_reset = command.timing_reset;
+            }
+            let now_cycles = DWT::cycle_count();
+            control.spindle_position.update(control.spindle_enc.count());
+            let spindle_index =
+                take_spindle_index().map(|count| control.spindle_position.get_position_at(count));
+            let (a, b) = read_spindle_signals();
+            control.spindle_raw.update(a, b);
+            let mut status = control.motion.tick(
+                control.spindle_position.get_position(),
+                spindle_index,
+                now_cycles,
+                read_servo_ok(),
+                &command,
+                &mut control.loop_timer,
+            );
+            status.spindle_raw_position = control.spindle_raw.get_position();
+            status.spindle_raw_invalid = control.spindle_raw.get_invalid();
+            #[cfg(feature = "step-loopback")]
+            {
+                status.step_mismatch = status.steps_emitted - control.step_counter.update();
+            }
+            G_STATUS.write(status);
+            G_TIMING.write(*control.loop_timer.end());
+        }
+    });
+}
+
+// The display's data pins are turned around to read its busy flag.
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl<const P: char, const N: u8> lcd::DataPin for hal::gpio::DynamicPin<P, N> {
+    fn set_input(&mut self) {
+        self.make_floating_input();
+    }
+    fn set_output(&mut self) {
+        self.make_push_pull_output();
+    }
+}
+
 #[entry]
 fn main() -> ! {
     //hprintln!("start");
     let dp = pac::Peripherals::take().unwrap();
-    let cp = cortex_m::peripheral::Peripherals::take().unwrap();
+    let mut cp = cortex_m::peripheral::Peripherals::take().unwrap();
+    let reset_info = reset::take_reset_info(&dp.RCC, &dp.PWR, &dp.RTC);
     let rcc = dp.RCC.constrain();
 
     let gpioa = dp.GPIOA.split();
     let gpiob = dp.GPIOB.split();
     let gpioc = dp.GPIOC.split();
 
+    #[cfg(not(feature = "usb"))]
     let clocks = rcc
         .cfgr
         .use_hse(25.MHz())
         .sysclk(100.MHz())
         .hclk(25.MHz())
         .freeze();
+    // USB needs a 48MHz clock, which can't be derived from the PLL alongside
+    // a 100MHz system clock.
+    #[cfg(feature = "usb")]
+    let clocks = rcc
+        .cfgr
+        .use_hse(25.MHz())
+        .sysclk(96.MHz())
+        .hclk(24.MHz())
+        .require_pll48clk()
+        .freeze();
 
     let dwt = cp.DWT.constrain(cp.DCB, &clocks);
     let mut ns_delay = dwt.delay();
     let mut timer = dp.TIM5.counter_us(&clocks);
-    let mut delay = dp.TIM9.delay_us(&clocks);
+    let mut delay = dp.TIM11.delay_us(&clocks);
 
     // Start the timer to give us a 1kHz clock interrupt.
     timer.start(1.millis()).unwrap();
@@ -122,14 +340,58 @@ fn main() -> ! {
 
     // Encoders and associated switches.
     let spindle_enc = Qei::new(dp.TIM2, (gpioa.pa0, gpioa.pa1));
+    // The spindle encoder's index pulse, on TIM2's channel 3 input, latches
+    // the count into capture channel 3. It's pulled down so that nothing is
+    // captured if the index isn't connected. (TIM2's external trigger pin,
+    // PA5, shares channel 1 with PA0.) The step loopback uses the pin
+    // instead.
+    #[cfg(not(feature = "step-loopback"))]
+    {
+        let _spindle_index = gpioa.pa2.into_alternate::<1>().internal_pull_down(true);
+        unsafe {
+            let tim2 = &*pac::TIM2::ptr();
+            tim2.ccmr2_input().modify(|_, w| w.cc3s().ti3());
+            tim2.ccer.modify(|_, w| w.cc3e().set_bit());
+        }
+    }
     let feed_enc = Qei::new(dp.TIM3, (gpioa.pa6, gpioa.pa7));
     let mode_enc = Qei::new(dp.TIM4, (gpiob.pb6, gpiob.pb7));
     let feed_enc_sw = gpioc.pc15.into_input();
     let mode_enc_sw = gpiob.pb8.into_input();
+    let mut input = input::Input::new(mode_enc.count().into(), feed_enc.count().into());
 
     // Control buttons.
     let button1 = gpioa.pa4.into_input();
 
+    // Serial console.
+    let (mut console_tx, mut console_rx) = dp
+        .USART1
+        .serial((gpioa.pa9, gpioa.pa10), CONSOLE_BAUD_RATE.bps(), &clocks)
+        .unwrap()
+        .split();
+
+    // USB console.
+    #[cfg(feature = "usb")]
+    let usb_bus = hal::otg_fs::UsbBus::new(
+        hal::otg_fs::USB::new(
+            (dp.OTG_FS_GLOBAL, dp.OTG_FS_DEVICE, dp.OTG_FS_PWRCLK),
+            (gpioa.pa11, gpioa.pa12),
+            &clocks,
+        ),
+        cortex_m::singleton!(: [u32; 1024] = [0; 1024]).unwrap(),
+    );
+    #[cfg(feature = "usb")]
+    let mut usb_serial = usbd_serial::SerialPort::new(&usb_bus);
+    #[cfg(feature = "usb")]
+    let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(USB_VID, USB_PID))
+        .device_class(usbd_serial::USB_CLASS_CDC)
+        .strings(&[StringDescriptors::default()
+            .manufacturer("djm")
+            .product("TU-2506V-ELS")
+            .serial_number("0")])
+        .unwrap()
+        .build();
+
     // Optocoupled inputs.
     let servo_ok_in = gpioa.pa3.into_input();
 
@@ -140,20 +402,105 @@ fn main() -> ! {
     let mut motor_enable_out = gpiob.pb10.into_push_pull_output();
     let mut motor_dir_out = gpiob.pb1.into_push_pull_output();
     let mut motor_step_out = gpiob.pb0.into_push_pull_output().erase_number();
+    motor_dir_out.set_low();
+    motor_enable_out.set_low();
+    motor_step_out.set_low();
 
     // Display I/O.
+    #[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
     let mut disp_rs = gpiob.pb3.into_push_pull_output().speed(Speed::Medium);
+    #[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
     let mut disp_rw = gpiob.pb9.into_push_pull_output().speed(Speed::Medium);
+    #[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
     let mut disp_e = gpiob.pb4.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db0 = gpioa.pa15.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db1 = gpioa.pa12.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db2 = gpioa.pa8.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db3 = gpiob.pb14.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db4 = gpiob.pb13.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db5 = gpiob.pb12.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db6 = gpiob.pb15.into_push_pull_output().speed(Speed::Medium);
-    let mut disp_db7 = gpioa.pa11.into_push_pull_output().speed(Speed::Medium);
+    #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+    let mut disp_db0 = gpioa
+        .pa15
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(not(any(
+        feature = "usb",
+        feature = "lcd-4bit",
+        feature = "lcd-i2c",
+        feature = "oled"
+    )))]
+    let mut disp_db1 = gpioa
+        .pa12
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(all(
+        feature = "usb",
+        not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled"))
+    ))]
+    let mut disp_db1 = gpiob
+        .pb5
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+    let mut disp_db2 = gpioa
+        .pa8
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+    let mut disp_db3 = gpiob
+        .pb14
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
+    let mut disp_db4 = gpiob
+        .pb13
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
+    let mut disp_db5 = gpiob
+        .pb12
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
+    let mut disp_db6 = gpiob
+        .pb15
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(not(any(feature = "usb", feature = "lcd-i2c", feature = "oled")))]
+    let mut disp_db7 = gpioa
+        .pa11
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    #[cfg(all(feature = "usb", not(any(feature = "lcd-i2c", feature = "oled"))))]
+    let mut disp_db7 = gpiob
+        .pb2
+        .into_push_pull_output()
+        .speed(Speed::Medium)
+        .into_dynamic();
+    // Each data pin is dynamic so that it can be turned around, and
+    // starts out as an output.
+    #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+    {
+        disp_db0.make_push_pull_output();
+        disp_db1.make_push_pull_output();
+        disp_db2.make_push_pull_output();
+        disp_db3.make_push_pull_output();
+    }
+    #[cfg(not(any(feature = "lcd-i2c", feature = "oled")))]
+    {
+        disp_db4.make_push_pull_output();
+        disp_db5.make_push_pull_output();
+        disp_db6.make_push_pull_output();
+        disp_db7.make_push_pull_output();
+    }
+    #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
     let mut display = lcd::Display8Bit::new(
+        lcd::Geometry::<DISPLAY_COLS, DISPLAY_ROWS>,
+        DISPLAY_CHARMAP,
         &mut disp_rs,
         &mut disp_rw,
         &mut disp_e,
@@ -167,108 +514,436 @@ fn main() -> ! {
         &mut disp_db7,
         &mut ns_delay,
     );
-    display.init();
-    write!(display.at(6, 0), "hello").ok();
-    write!(display.at(6, 1), "there!").ok();
-    delay.delay_ms(500u32);
-    display.clear();
-    motor_dir_out.set_low();
-    motor_enable_out.set_low();
-    motor_step_out.set_low();
-    let mut pulser = Pulser::new(&mut motor_step_out);
-    let mut cold: bool = true;
-    let mut last_ms: i64 = 0;
+    #[cfg(feature = "lcd-4bit")]
+    let mut display = lcd::Display4Bit::new(
+        lcd::Geometry::<DISPLAY_COLS, DISPLAY_ROWS>,
+        DISPLAY_CHARMAP,
+        &mut disp_rs,
+        &mut disp_rw,
+        &mut disp_e,
+        &mut disp_db4,
+        &mut disp_db5,
+        &mut disp_db6,
+        &mut disp_db7,
+        &mut ns_delay,
+    );
+    #[cfg(feature = "lcd-i2c")]
+    let mut disp_i2c = dp.I2C3.i2c(
+        (gpioa.pa8, gpiob.pb4.into_input()),
+        LCD_I2C_SPEED.kHz(),
+        &clocks,
+    );
+    #[cfg(feature = "lcd-i2c")]
+    let mut display = lcd::DisplayI2c::new(
+        lcd::Geometry::<DISPLAY_COLS, DISPLAY_ROWS>,
+        DISPLAY_CHARMAP,
+        &mut disp_i2c,
+        LCD_I2C_ADDRESS,
+        &mut ns_delay,
+    );
+    #[cfg(all(feature = "oled", not(feature = "oled-spi")))]
+    let mut disp_i2c = dp.I2C3.i2c(
+        (gpioa.pa8, gpiob.pb4.into_input()),
+        OLED_I2C_SPEED.kHz(),
+        &clocks,
+    );
+    #[cfg(all(feature = "oled", not(feature = "oled-spi")))]
+    let mut display = oled::Oled::new(
+        oled::I2cInterface::new(&mut disp_i2c, OLED_I2C_ADDRESS),
+        OLED_CONTROLLER,
+    );
+    // SPI modules take the VFD's DB3-DB6 pins, and a reset pin that they
+    // need pulsed at power on.
+    #[cfg(feature = "oled-spi")]
+    let mut disp_spi = dp.SPI2.spi(
+        (gpiob.pb13, hal::gpio::NoPin::new(), gpiob.pb15),
+        embedded_hal::spi::MODE_0,
+        OLED_SPI_SPEED.MHz(),
+        &clocks,
+    );
+    #[cfg(feature = "oled-spi")]
+    let mut disp_cs = gpiob.pb12.into_push_pull_output();
+    #[cfg(feature = "oled-spi")]
+    let mut disp_dc = gpiob.pb14.into_push_pull_output();
+    #[cfg(feature = "oled-spi")]
+    {
+        let mut disp_res = gpioa.pa8.into_push_pull_output();
+        disp_res.set_low();
+        delay.delay_us(10u32);
+        disp_res.set_high();
+    }
+    #[cfg(feature = "oled-spi")]
+    let mut display = oled::Oled::new(
+        oled::SpiInterface::new(&mut disp_spi, &mut disp_cs, &mut disp_dc),
+        OLED_CONTROLLER,
+    );
+    #[cfg(not(feature = "oled"))]
+    {
+        display.init();
+        write!(display.at(6, 0), "hello").ok();
+        write!(display.at(6, 1), "there!").ok();
+        delay.delay_ms(500u32);
+        display.clear();
+    }
+    #[cfg(feature = "oled")]
+    {
+        display.init(&mut ns_delay);
+        let mut grid = bitmap::TextGrid::new();
+        write!(grid.at(8, 1), "hello").ok();
+        write!(grid.at(8, 2), "there!").ok();
+        display.flush(grid.bitmap());
+        delay.delay_ms(500u32);
+    }
+    let cycles_per_us = clocks.hclk().raw() / 1_000_000;
+
+    // Hand the spindle encoder and motor step/direction outputs to the
+    // control interrupt. It preempts everything else.
+    let spindle_position = encoder::Encoder::<32>::new(spindle_enc.count());
+    let (a, b) = read_spindle_signals();
+    let spindle_raw = encoder::Quadrature::new(a, b);
+    let mut control_timer = dp.TIM10.counter_hz(&clocks);
+    control_timer.start(CONTROL_RATE.Hz()).unwrap();
+    control_timer.listen(Event::Update);
+    let motion = motion::Motion::new(
+        Pulser::new(motor_step_out),
+        motor_dir_out,
+        spindle_position.get_position(),
+        cycles_per_us,
+    );
+    cortex_m::interrupt::free(|cs| {
+        *G_CONTROL.borrow(cs).borrow_mut() = Some(ControlResources {
+            timer: control_timer,
+            spindle_enc,
+            spindle_position,
+            spindle_raw,
+            #[cfg(feature = "step-loopback")]
+            step_counter: loopback::StepCounter::new(dp.TIM9, gpioa.pa2.into_alternate()),
+            motion,
+            loop_timer: timing::LoopTimer::new(cycles_per_us),
+            timing_reset: 0,
+        })
+    });
+    unsafe {
+        cp.NVIC.set_priority(pac::Interrupt::TIM1_UP_TIM10, 0x00);
+        cp.NVIC.set_priority(pac::Interrupt::TIM5, 0x10);
+        cortex_m::peripheral::NVIC::unmask(pac::Interrupt::TIM1_UP_TIM10);
+    }
+
     let mut now_ms: i64 = 0;
     let mut next_ui_ms: i64 = 0;
-    let mut spindle_enc_count = spindle_enc.count() as i32;
-    let mut spindle_enc_last = spindle_enc_count;
-    let mut spindle_enc_delta: i32 = 0;
-    let mut smoothed_rpm: i32 = 0;
-    let mut ui = userinterface::UI::new(&mut display);
+    let mut spindle_enc_last: i64 = 0;
+    let mut smoothed_enc_ppm: i64 = 0;
+    #[cfg(not(feature = "oled"))]
+    let mut frame_buffer: FrameBuffer<_, DISPLAY_COLS, DISPLAY_ROWS> =
+        FrameBuffer::new(&mut display);
+    #[cfg(not(feature = "oled"))]
+    let mut renderer = TextRenderer::new(&mut frame_buffer);
+    #[cfg(feature = "oled")]
+    let mut renderer = oledview::OledRenderer::new(&mut display);
+    let mut ui = userinterface::UI::new(&mut renderer);
+    if let Some((message1, message2)) = reset_info.message() {
+        ui.set_boot_message(message1, message2);
+    }
     let mut control = control::Control::new();
-    let mut last_motor_dir: bool = false;
+    qei::set_filters(control.get_config());
+    let mut encoder_filters_last = control.get_config().get_encoder_filters();
+    let mut servo = servo::Servo::new();
     let mut last_motor_enable: bool = false;
     let mut motor_pulses_since_last_ui: u32 = 0;
+    let mut motor_position_last: i64 = 0;
+    let mut lost_pulses_last: i64 = 0;
+    let mut timing_reset: u32 = 0;
+    let mut index_errors_last: u32 = 0;
+    let mut step_mismatch_last: i64 = 0;
+    let mut servo_dropouts_last: u32 = 0;
+    let mut console_line = console::LineBuffer::new();
+    let mut console_out = console::TxBuffer::new();
+    #[cfg(feature = "usb")]
+    let mut usb_line = console::LineBuffer::new();
+    #[cfg(feature = "usb")]
+    let mut usb_out = console::TxBuffer::new();
+    // Last packet received over USB, and how much of it has been taken.
+    #[cfg(feature = "usb")]
+    let mut usb_rx = [0u8; 64];
+    #[cfg(feature = "usb")]
+    let (mut usb_rx_len, mut usb_rx_pos) = (0, 0);
+    let mut telemetry = telemetry::Stream::new();
+    let mut telemetry_port = console::Port::Uart;
+    // Kept off the stack, as it is 32kB.
+    let capture = cortex_m::singleton!(: capture::Capture = capture::Capture::new()).unwrap();
+    let mut capture_dump: Option<(console::Port, capture::Dump)> = None;
+    let mut loop_start = DWT::cycle_count();
+    // Start the watchdog last, so the startup delays above don't trip it.
+    let mut watchdog = IndependentWatchdog::new(dp.IWDG);
+    watchdog.stop_on_debug(&dp.DBGMCU, true);
+    watchdog.start(WATCHDOG_TIMEOUT.millis());
     loop {
+        watchdog.feed();
+        let now_cycles = DWT::cycle_count();
+        telemetry.record_main_loop_time(now_cycles.wrapping_sub(loop_start) / cycles_per_us);
+        loop_start = now_cycles;
+
         // Twiddle board LED as heartbeat.
         board_led.set_state((now_ms % 200 < 100).into());
 
+        // Calculate number/direction of spindle encoder and motor pulses
+        // since the last iteration.
+        let status = G_STATUS.read();
+        let timing_stats = G_TIMING.read();
+        let spindle_enc_count = status.spindle_count;
+        let spindle_enc_delta = spindle_enc_count - spindle_enc_last;
+        spindle_enc_last = spindle_enc_count;
+        let motor_position = status.motor_position;
+        let motor_pulses = motor_position - motor_position_last;
+        motor_position_last = motor_position;
+        motor_pulses_since_last_ui += motor_pulses.unsigned_abs() as u32;
+        telemetry.record_motor_pulses(motor_pulses as i32);
         cortex_m::interrupt::free(|cs| {
-            // Calculate number/direction of spindle encoder pulses.
-            spindle_enc_count = spindle_enc.count() as i32;
-            spindle_enc_delta = spindle_enc_count - spindle_enc_last;
-            spindle_enc_last = spindle_enc_count;
             // Update global encoder pulse accumulator.
-            let enc = G_ENC.borrow(cs);
-            enc.set(enc.get() + spindle_enc_delta);
             // Get global millisecond counter and smoothed RPM value.
             now_ms = G_NOW.borrow(cs).get();
-            smoothed_rpm = G_RPM.borrow(cs).get();
+            smoothed_enc_ppm = G_ENC_PPM.borrow(cs).get();
         });
-
-        // Calculate elapsed ms since last loop iteration.
-        let mut ms_elapsed: u32 = 0;
-        if cold {
-            cold = false;
-        } else {
-            ms_elapsed = (now_ms - last_ms).try_into().unwrap_or(0);
-        }
-        last_ms = now_ms;
+        capture.record(now_ms, spindle_enc_delta as i32);
 
         // Servo OK input is inverted.
         let servo_ok: bool = servo_ok_in.is_low();
+        let smoothed_rpm = control.get_config().encoder_ppm_to_rpm(smoothed_enc_ppm);
+
+        // Knobs and buttons, sampled every pass for debouncing. The knobs'
+        // push switches are active low.
+        input.update(
+            now_ms,
+            button1.is_high(),
+            mode_enc_sw.is_low(),
+            feed_enc_sw.is_low(),
+            mode_enc.count().into(),
+            feed_enc.count().into(),
+        );
+
+        // Handle console input, and send any pending output.
+        let mut console_action = console::Action::None;
+        // Input is only taken while there is room for a response. The rest
+        // waits until the output has drained.
+        while console_out.free() >= console::RESPONSE_MAX {
+            let Ok(byte) = console_rx.read() else {
+                break;
+            };
+            if let Some(line) = console_line.push(byte) {
+                let mut ctx = console::Context {
+                    ui: &mut ui,
+                    control: &mut control,
+                    servo: &servo,
+                    capture,
+                    dumping: capture_dump.is_some(),
+                    timing: &timing_stats,
+                    index: &status.index,
+                    now_ms,
+                    spindle_velocity: status.spindle_velocity,
+                    spindle_acceleration: status.spindle_acceleration,
+                    rpm: smoothed_rpm,
+                    spindle_pos: spindle_enc_count,
+                    motor_pos: motor_position,
+                    #[cfg(feature = "step-loopback")]
+                    step_mismatch: status.step_mismatch,
+                };
+                console_action = console::handle(line, &mut console_out, &mut ctx);
+                match console_action {
+                    console::Action::Telemetry(hz) => {
+                        telemetry.set_rate(hz);
+                        telemetry_port = console::Port::Uart;
+                    }
+                    console::Action::CaptureDump => {
+                        capture_dump = Some((console::Port::Uart, capture.dump()));
+                    }
+                    console::Action::TimingReset => timing_reset = timing_reset.wrapping_add(1),
+                    _ => (),
+                }
+            }
+        }
+        let frame = telemetry::Frame {
+            timestamp_ms: now_ms as u32,
+            spindle_count: spindle_enc_count as i32,
+            rpm: smoothed_rpm.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+            direction: status.direction.into(),
+            servo_ok,
+            motor_enable: last_motor_enable,
+            fractional: status.fractional_pulses_remaining as u32,
+            ..Default::default()
+        };
+        // Binary frames would corrupt a capture dump on the same port.
+        let dumping_to_telemetry_port =
+            matches!(capture_dump, Some((port, _)) if port == telemetry_port);
+        if let Some(bytes) = telemetry.poll(now_ms, frame) {
+            // Drop frames rather than queue partial ones if output is backed up.
+            match telemetry_port {
+                _ if dumping_to_telemetry_port => false,
+                console::Port::Uart => console_out.push_stream(&bytes),
+                #[cfg(feature = "usb")]
+                console::Port::Usb => usb_out.push_stream(&bytes),
+            };
+        }
+        // Send as much of any capture dump as will fit.
+        if let Some((port, dump)) = capture_dump.as_mut() {
+            let out = match port {
+                console::Port::Uart => &mut console_out,
+                #[cfg(feature = "usb")]
+                console::Port::Usb => &mut usb_out,
+            };
+            let mut done = false;
+            while out.free() >= capture::DUMP_LINE_MAX {
+                if let Some((time_ms, delta)) = dump.next(capture) {
+                    writeln!(out, "{},{}", time_ms, delta).ok();
+                } else {
+                    writeln!(out, "ok").ok();
+                    done = true;
+                    break;
+                }
+            }
+            if done {
+                capture_dump = None;
+            }
+        }
+        while let Some(byte) = console_out.peek() {
+            if console_tx.write(byte).is_err() {
+                break;
+            }
+            console_out.pop();
+        }
+        #[cfg(feature = "usb")]
+        if usb_dev.poll(&mut [&mut usb_serial]) && usb_rx_pos == usb_rx_len {
+            usb_rx_len = usb_serial.read(&mut usb_rx).unwrap_or(0);
+            usb_rx_pos = 0;
+        }
+        // As for the serial console. A packet is kept until all of it has
+        // been taken, which holds off the USB host meanwhile.
+        #[cfg(feature = "usb")]
+        while usb_rx_pos < usb_rx_len && usb_out.free() >= console::RESPONSE_MAX {
+            let byte = usb_rx[usb_rx_pos];
+            usb_rx_pos += 1;
+            if let Some(line) = usb_line.push(byte) {
+                let mut ctx = console::Context {
+                    ui: &mut ui,
+                    control: &mut control,
+                    servo: &servo,
+                    capture,
+                    dumping: capture_dump.is_some(),
+                    timing: &timing_stats,
+                    index: &status.index,
+                    now_ms,
+                    spindle_velocity: status.spindle_velocity,
+                    spindle_acceleration: status.spindle_acceleration,
+                    rpm: smoothed_rpm,
+                    spindle_pos: spindle_enc_count,
+                    motor_pos: motor_position,
+                    #[cfg(feature = "step-loopback")]
+                    step_mismatch: status.step_mismatch,
+                };
+                console_action = console::handle(line, &mut usb_out, &mut ctx);
+                match console_action {
+                    console::Action::Telemetry(hz) => {
+                        telemetry.set_rate(hz);
+                        telemetry_port = console::Port::Usb;
+                    }
+                    console::Action::CaptureDump => {
+                        capture_dump = Some((console::Port::Usb, capture.dump()));
+                    }
+                    console::Action::TimingReset => timing_reset = timing_reset.wrapping_add(1),
+                    _ => (),
+                }
+            }
+        }
+        #[cfg(feature = "usb")]
+        while let Some(byte) = usb_out.peek() {
+            if !matches!(usb_serial.write(&[byte]), Ok(1)) {
+                break;
+            }
+            usb_out.pop();
+        }
+        if console_action == console::Action::Bootloader {
+            motor_enable_out.set_low();
+            reset::reboot_to_bootloader();
+        }
+
+        // Apply encoder filter changes made from the console.
+        let encoder_filters = control.get_config().get_encoder_filters();
+        if encoder_filters != encoder_filters_last {
+            qei::set_filters(control.get_config());
+            encoder_filters_last = encoder_filters;
+        }
 
         if next_ui_ms < now_ms {
             ui.update(
                 &mut control,
+                &mut servo,
                 now_ms,
                 smoothed_rpm,
                 servo_ok,
-                button1.is_high(),
-                mode_enc.count() as i16,
-                mode_enc_sw.is_low(),
-                feed_enc.count() as i16,
-                feed_enc_sw.is_low(),
+                &mut input,
                 spindle_enc_count,
+                status.spindle_raw_position,
+                status.spindle_raw_invalid,
                 motor_pulses_since_last_ui,
+                motor_position,
                 last_motor_enable,
-                last_motor_dir,
+                status.direction.into(),
+                status.fractional_pulses_remaining,
+                &timing_stats,
+                &status.index,
             );
             next_ui_ms = now_ms + (1000 / DISPLAY_UPDATE_RATE as i64);
             motor_pulses_since_last_ui = 0;
         }
-        // Call controller for current mode to determine motor commands.
-        let mut motor_enable: bool = false;
-        let mut motor_dir: bool = false;
-        let mut motor_pulses: u32 = 0;
-        match ui.get_mode() {
-            Mode::ServoOff => (),
-            Mode::Feed | Mode::ThreadMetric | Mode::ThreadImperial => {
-                // Prepare to command drive based on spindle motion and/or time.
-                let (direction, pulses) = control.feed_per_rev(spindle_enc_delta, ms_elapsed);
-                motor_dir = direction.into();
-                motor_pulses = pulses;
-                motor_enable = true;
+        // Command the control interrupt based on the current mode.
+        let motor_requested = match ui.get_mode() {
+            Mode::ServoOff => false,
+            Mode::Feed | Mode::ThreadMetric | Mode::ThreadImperial => true,
+        };
+        // Slipped spindle encoder counts would ruin a thread, so stop.
+        if status.index.errors != index_errors_last {
+            index_errors_last = status.index.errors;
+            if matches!(ui.get_mode(), Mode::ThreadMetric | Mode::ThreadImperial) {
+                servo.trip(servo::Fault::EncoderSlip);
             }
         }
-        // Changes in enable and direction require at least 1μs to
-        // be recognised.
+        // Steps that weren't emitted as commanded, or extra ones, have
+        // moved the carriage somewhere unknown.
+        if status.step_mismatch != step_mismatch_last {
+            step_mismatch_last = status.step_mismatch;
+            servo.trip(servo::Fault::StepMismatch);
+        }
+        // The control interrupt withheld pulses because servo OK dropped,
+        // perhaps too briefly to be seen here.
+        if status.servo_dropouts != servo_dropouts_last {
+            servo_dropouts_last = status.servo_dropouts;
+            servo.trip(servo::Fault::ServoLost);
+        }
+        // The servo state machine decides whether the drive is actually
+        // enabled, and latches faults until the operator acknowledges them.
+        let motor_enable = servo.update(now_ms, motor_requested, servo_ok);
+        // Changes in enable require at least 1μs to be recognised.
         if last_motor_enable != motor_enable {
             motor_enable_out.set_state(motor_enable.into());
             delay.delay_us(2);
             last_motor_enable = motor_enable;
         }
-        // Don't bother sending pulses if the drive has alarmed or is disabled.
-        if !servo_ok || !motor_enable {
-            continue;
-        }
-        if motor_dir != last_motor_dir {
-            motor_dir_out.set_state(motor_dir.into());
-            delay.delay_us(2);
-            last_motor_dir = motor_dir;
+        G_COMMAND.write(motion::Command {
+            requested: motor_requested,
+            motion_allowed: servo.motion_allowed(),
+            feed_per_rev_factor: control.get_feed_per_rev_factor(),
+            encoder_ppr: control.get_config().encoder_ppr,
+            timing_reset,
+        });
+        // Pulses the interrupt couldn't send while the drive was faulted.
+        let lost_pulses = status.lost_pulses - lost_pulses_last;
+        lost_pulses_last = status.lost_pulses;
+        if lost_pulses != 0 {
+            let direction = if lost_pulses > 0 {
+                control::Direction::Forward
+            } else {
+                control::Direction::Backwards
+            };
+            servo.record_lost(direction, lost_pulses.unsigned_abs() as u32);
         }
-        // XXX warning if we're not keeping up.
-        pulser.pulse(motor_pulses);
-        motor_pulses_since_last_ui += motor_pulses;
     }
 }
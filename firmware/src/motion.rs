@@ -0,0 +1,177 @@
+//! Fixed-rate spindle following, run from the control interrupt.
+use stm32f4xx_hal::gpio::{Output, PB1};
+
+use crate::control::{Control, Direction};
+use crate::index::{self, IndexMonitor};
+use crate::pulse::Pulser;
+use crate::timing::LoopTimer;
+use crate::tracker::Tracker;
+use crate::velocity::VelocityEstimator;
+
+// Commanded state, handed from the background loop to the interrupt.
+#[derive(Clone, Copy)]
+pub struct Command {
+    // The mode requires the motor to follow the spindle.
+    pub requested: bool,
+    // The servo is ready; otherwise pulses are accounted as lost.
+    pub motion_allowed: bool,
+    pub feed_per_rev_factor: i64,
+    // Spindle encoder counts per encoder revolution, for the index check.
+    pub encoder_ppr: i64,
+    // Changed to reset the timing statistics.
+    pub timing_reset: u32,
+}
+
+impl Command {
+    pub const fn new() -> Self {
+        Command {
+            requested: false,
+            motion_allowed: false,
+            feed_per_rev_factor: 0,
+            encoder_ppr: 0,
+            timing_reset: 0,
+        }
+    }
+}
+
+// Interrupt state, handed back to the background loop. Counts are
+// cumulative, so the background loop can't miss any.
+#[derive(Clone, Copy)]
+pub struct Status {
+    pub spindle_count: i64,
+    pub motor_position: i64,
+    pub lost_pulses: i64,
+    pub direction: Direction,
+    pub fractional_pulses_remaining: i64,
+    // Encoder pulses per minute, and per minute per second.
+    pub spindle_velocity: i64,
+    pub spindle_acceleration: i64,
+    pub index: index::Stats,
+    // Spindle encoder signals decoded in software, unfiltered.
+    pub spindle_raw_position: i64,
+    pub spindle_raw_invalid: u32,
+    // Step pulses emitted, whatever their direction.
+    pub steps_emitted: i64,
+    // Periods where motion was allowed but the drive wasn't ready, so
+    // pulses were withheld.
+    pub servo_dropouts: u32,
+    // Steps emitted less those counted by the step loopback, if fitted.
+    pub step_mismatch: i64,
+}
+
+impl Status {
+    pub const fn new() -> Self {
+        Status {
+            spindle_count: 0,
+            motor_position: 0,
+            lost_pulses: 0,
+            direction: Direction::Forward,
+            fractional_pulses_remaining: 0,
+            spindle_velocity: 0,
+            spindle_acceleration: 0,
+            index: index::Stats::new(),
+            spindle_raw_position: 0,
+            spindle_raw_invalid: 0,
+            steps_emitted: 0,
+            servo_dropouts: 0,
+            step_mismatch: 0,
+        }
+    }
+}
+
+pub struct Motion {
+    control: Control,
+    velocity: VelocityEstimator,
+    tracker: Tracker,
+    index: IndexMonitor,
+    pulser: Pulser,
+    dir_out: PB1<Output>,
+    last_dir: bool,
+    // Direction changes require at least 1μs to be recognised.
+    dir_setup_cycles: u32,
+    status: Status,
+}
+
+impl Motion {
+    pub fn new(
+        pulser: Pulser,
+        dir_out: PB1<Output>,
+        spindle_count: i64,
+        cycles_per_us: u32,
+    ) -> Self {
+        Motion {
+            control: Control::new(),
+            velocity: VelocityEstimator::new(cycles_per_us, spindle_count),
+            tracker: Tracker::new(spindle_count),
+            index: IndexMonitor::new(),
+            pulser,
+            dir_out,
+            last_dir: false,
+            dir_setup_cycles: 2 * cycles_per_us,
+            status: Status {
+                spindle_count,
+                ..Status::new()
+            },
+        }
+    }
+
+    // Follow the spindle encoder for one interrupt period, given its count,
+    // the count at an index pulse if there was one since the last period,
+    // the DWT cycle count when it was sampled, and the drive's servo OK
+    // input.
+    pub fn tick(
+        &mut self,
+        spindle_count: i64,
+        spindle_index: Option<i64>,
+        now_cycles: u32,
+        servo_ok: bool,
+        command: &Command,
+        timer: &mut LoopTimer,
+    ) -> Status {
+        self.status.spindle_count = spindle_count;
+        if let Some(position) = spindle_index {
+            self.index.update(position, command.encoder_ppr);
+            self.status.index = *self.index.get_stats();
+        }
+        // Spindle movement, extrapolated to when steps will be emitted.
+        let spindle_advance = self.tracker.update(spindle_count);
+        self.velocity.update(now_cycles, spindle_count);
+        self.status.spindle_velocity = self.velocity.get_velocity();
+        self.status.spindle_acceleration = self.velocity.get_acceleration();
+        if !command.requested {
+            return self.status;
+        }
+        self.control
+            .load_feed_per_rev_factor(command.feed_per_rev_factor);
+        let (direction, pulses) = self.control.feed_per_rev_fraction(spindle_advance);
+        let signed_pulses = match direction {
+            Direction::Forward => pulses as i64,
+            Direction::Backwards => -(pulses as i64),
+        };
+        self.status.direction = direction;
+        self.status.fractional_pulses_remaining = self.control.get_fractional_pulses_remaining();
+        // Don't send pulses unless the drive is enabled and ready. Pulses
+        // that should have been sent during a fault are accounted for. The
+        // background loop only sees servo OK drop some time later, so it's
+        // checked here too.
+        if command.motion_allowed && !servo_ok {
+            self.status.servo_dropouts = self.status.servo_dropouts.wrapping_add(1);
+        }
+        if !command.motion_allowed || !servo_ok {
+            self.status.lost_pulses += signed_pulses;
+            return self.status;
+        }
+        let dir: bool = direction.into();
+        if dir != self.last_dir {
+            self.dir_out.set_state(dir.into());
+            cortex_m::asm::delay(self.dir_setup_cycles);
+            self.last_dir = dir;
+        }
+        timer.start_pulse();
+        self.pulser.pulse(pulses);
+        timer.end_pulse();
+        self.status.motor_position += signed_pulses;
+        self.status.steps_emitted += pulses as i64;
+        self.status
+    }
+}
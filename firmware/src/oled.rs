@@ -0,0 +1,192 @@
+//! Driver for SSD1306 and SH1106 128x64 OLED displays.
+//!
+//! The screen is drawn in a Bitmap, and flush() sends the parts of each
+//! page that differ from what the display is already showing. Either
+//! controller can be on I2C or, with the oled-spi feature, 4-wire SPI.
+use embedded_hal::delay::DelayNs;
+#[cfg(feature = "oled-spi")]
+use embedded_hal::digital::OutputPin;
+#[cfg(not(feature = "oled-spi"))]
+use embedded_hal::i2c::I2c;
+#[cfg(feature = "oled-spi")]
+use embedded_hal::spi::SpiBus;
+
+use crate::bitmap::{Bitmap, PAGES, WIDTH};
+
+// Time for the display's supply and reset circuit to settle after power on.
+const POWER_ON_DELAY_MS: u32 = 100;
+
+// I2C control bytes, saying whether the rest of the transfer is commands or
+// display data.
+#[cfg(not(feature = "oled-spi"))]
+const I2C_COMMANDS: u8 = 0x00;
+#[cfg(not(feature = "oled-spi"))]
+const I2C_DATA: u8 = 0x40;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Controller {
+    Ssd1306,
+    // 132 columns of display RAM, of which the middle 128 are shown.
+    Sh1106,
+}
+
+impl Controller {
+    fn column_offset(&self) -> u8 {
+        match self {
+            Controller::Ssd1306 => 0,
+            Controller::Sh1106 => 2,
+        }
+    }
+}
+
+// How commands and display data get to the controller. Bus errors are
+// ignored, like the character displays'; a missing display just stays
+// blank.
+pub trait Interface {
+    fn command(&mut self, bytes: &[u8]);
+    fn data(&mut self, bytes: &[u8]);
+}
+
+#[cfg(not(feature = "oled-spi"))]
+pub struct I2cInterface<'a, I2C> {
+    i2c: &'a mut I2C,
+    address: u8,
+}
+
+#[cfg(not(feature = "oled-spi"))]
+impl<'a, I2C: I2c> I2cInterface<'a, I2C> {
+    pub fn new(i2c: &'a mut I2C, address: u8) -> Self {
+        I2cInterface { i2c, address }
+    }
+    // Each transfer starts with the control byte, so data is copied in
+    // behind it.
+    fn write(&mut self, control: u8, bytes: &[u8]) {
+        let mut buffer = [0u8; WIDTH + 1];
+        buffer[0] = control;
+        for chunk in bytes.chunks(WIDTH) {
+            buffer[1..=chunk.len()].copy_from_slice(chunk);
+            self.i2c.write(self.address, &buffer[..=chunk.len()]).ok();
+        }
+    }
+}
+
+#[cfg(not(feature = "oled-spi"))]
+impl<'a, I2C: I2c> Interface for I2cInterface<'a, I2C> {
+    fn command(&mut self, bytes: &[u8]) {
+        self.write(I2C_COMMANDS, bytes);
+    }
+    fn data(&mut self, bytes: &[u8]) {
+        self.write(I2C_DATA, bytes);
+    }
+}
+
+// SPI, with chip select low for each transfer and the data/command pin low
+// for commands.
+#[cfg(feature = "oled-spi")]
+pub struct SpiInterface<'a, SPI, CS, DC> {
+    spi: &'a mut SPI,
+    cs: &'a mut CS,
+    dc: &'a mut DC,
+}
+
+#[cfg(feature = "oled-spi")]
+impl<'a, SPI: SpiBus, CS: OutputPin, DC: OutputPin> SpiInterface<'a, SPI, CS, DC> {
+    pub fn new(spi: &'a mut SPI, cs: &'a mut CS, dc: &'a mut DC) -> Self {
+        cs.set_high().ok();
+        SpiInterface { spi, cs, dc }
+    }
+    fn write(&mut self, data: bool, bytes: &[u8]) {
+        self.dc.set_state(data.into()).ok();
+        self.cs.set_low().ok();
+        self.spi.write(bytes).ok();
+        // Chip select mustn't rise until the last byte is out.
+        self.spi.flush().ok();
+        self.cs.set_high().ok();
+    }
+}
+
+#[cfg(feature = "oled-spi")]
+impl<'a, SPI: SpiBus, CS: OutputPin, DC: OutputPin> Interface for SpiInterface<'a, SPI, CS, DC> {
+    fn command(&mut self, bytes: &[u8]) {
+        self.write(false, bytes);
+    }
+    fn data(&mut self, bytes: &[u8]) {
+        self.write(true, bytes);
+    }
+}
+
+pub struct Oled<IF> {
+    interface: IF,
+    controller: Controller,
+    // What the display is showing, valid unless stale.
+    shown: Bitmap,
+    stale: bool,
+}
+
+impl<IF: Interface> Oled<IF> {
+    pub fn new(interface: IF, controller: Controller) -> Self {
+        Oled {
+            interface,
+            controller,
+            shown: Bitmap::new(),
+            stale: true,
+        }
+    }
+
+    // The display is left blank, and switched on by the first flush.
+    pub fn init(&mut self, delay: &mut impl DelayNs) {
+        delay.delay_ms(POWER_ON_DELAY_MS);
+        // Display off, clock divide ratio, multiplex ratio 1/64, no offset,
+        // start line 0, segments and COM scan remapped for the usual
+        // mounting, alternative COM pins, contrast, precharge, VCOMH
+        // deselect level, show RAM, not inverted.
+        self.interface.command(&[
+            0xae, 0xd5, 0x80, 0xa8, 0x3f, 0xd3, 0x00, 0x40, 0xa1, 0xc8, 0xda, 0x12, 0x81, 0xcf,
+            0xd9, 0xf1, 0xdb, 0x40, 0xa4, 0xa6,
+        ]);
+        match self.controller {
+            // Charge pump on, page addressing.
+            Controller::Ssd1306 => self.interface.command(&[0x8d, 0x14, 0x20, 0x02]),
+            // DC-DC converter on. The SH1106 only has page addressing.
+            Controller::Sh1106 => self.interface.command(&[0xad, 0x8b]),
+        }
+        self.stale = true;
+    }
+
+    // Contrast, which is as near as these displays get to brightness. Zero
+    // is dim rather than off.
+    pub fn contrast(&mut self, percent: u8) {
+        let level = (percent.min(100) as u32 * 255 / 100) as u8;
+        self.interface.command(&[0x81, level]);
+    }
+
+    // Send the changed part of each page, from the first changed column to
+    // the last.
+    pub fn flush(&mut self, bitmap: &Bitmap) {
+        for page in 0..PAGES {
+            let new = bitmap.page(page);
+            let old = self.shown.page(page);
+            let changed = |x: &usize| self.stale || new[*x] != old[*x];
+            let Some(first) = (0..WIDTH).find(changed) else {
+                continue;
+            };
+            let last = (0..WIDTH).rfind(changed).unwrap_or(first);
+            let column = first as u8 + self.controller.column_offset();
+            self.interface
+                .command(&[0xb0 | page as u8, column & 0x0f, 0x10 | column >> 4]);
+            self.interface.data(&new[first..=last]);
+        }
+        if self.stale {
+            // Display on, now it has something to show.
+            self.interface.command(&[0xaf]);
+        }
+        self.shown = bitmap.clone();
+        self.stale = false;
+    }
+
+    // For the host to inspect its model of the display.
+    #[cfg(not(target_os = "none"))]
+    pub fn interface(&mut self) -> &mut IF {
+        &mut self.interface
+    }
+}
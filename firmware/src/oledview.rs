@@ -0,0 +1,208 @@
+//! Graphical layouts for the OLED.
+//!
+//! The mode screens show the carriage position and spindle speed in large
+//! digits, with icons for the mode and directions and a bar for the
+//! distance to a carriage stop. Messages, faults and the debug pages use
+//! the character display layouts, on a grid of text.
+use core::fmt::{self, Write};
+
+use crate::bitmap::{Bitmap, TextGrid, WIDTH};
+use crate::lcd::{self, CharacterDisplay};
+use crate::oled::{Interface, Oled};
+use crate::textview::TextView;
+use crate::userinterface::Mode;
+use crate::view::{Renderer, Screen, Stop, View};
+
+// Icons, eight rows of eight pixels, most significant bit on the left.
+const SERVO_OFF_ICON: [u8; 8] = [
+    0b00011000, 0b01011010, 0b10011001, 0b10011001, 0b10000001, 0b10000001, 0b01000010, 0b00111100,
+];
+const FEED_ICON: [u8; 8] = [
+    0b00000000, 0b00000100, 0b00000110, 0b11111111, 0b11111111, 0b00000110, 0b00000100, 0b00000000,
+];
+// Thread profile over its root.
+const THREAD_ICON: [u8; 8] = [
+    0b00000000, 0b01000100, 0b11101110, 0b10111011, 0b00010001, 0b00000000, 0b11111111, 0b00000000,
+];
+const CLOCKWISE_ICON: [u8; 8] = [
+    0b00111000, 0b01000100, 0b10000010, 0b10000010, 0b10000111, 0b10000010, 0b01000000, 0b00111000,
+];
+const ANTICLOCKWISE_ICON: [u8; 8] = [
+    0b00011100, 0b00100010, 0b01000001, 0b01000001, 0b11100001, 0b01000001, 0b00000010, 0b00011100,
+];
+
+// Layout, in pixels.
+const HEADER_Y: i32 = 0;
+const RULE_Y: i32 = 10;
+const DRO_Y: i32 = 14;
+const DRO_SCALE: i32 = 3;
+const RPM_Y: i32 = 41;
+const RPM_SCALE: i32 = 2;
+const STOP_Y: i32 = 58;
+const STOP_HEIGHT: i32 = 6;
+
+// Short formatted text, cut off if it doesn't fit.
+struct Line {
+    bytes: [u8; 24],
+    len: usize,
+}
+
+impl Line {
+    fn new(args: fmt::Arguments) -> Self {
+        let mut line = Line {
+            bytes: [0; 24],
+            len: 0,
+        };
+        line.write_fmt(args).ok();
+        line
+    }
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let len = c.len_utf8();
+            if self.len + len > self.bytes.len() {
+                return Err(fmt::Error);
+            }
+            c.encode_utf8(&mut self.bytes[self.len..]);
+            self.len += len;
+        }
+        Ok(())
+    }
+}
+
+pub struct OledView {
+    grid: TextGrid,
+    text: TextView,
+}
+
+impl OledView {
+    pub const fn new() -> Self {
+        OledView {
+            grid: TextGrid::new(),
+            text: TextView::new(),
+        }
+    }
+
+    // Draw a view, returning the screen.
+    pub fn render(&mut self, view: &View) -> &Bitmap {
+        match view.screen {
+            Screen::Mode {
+                mode,
+                setting,
+                stop,
+            } => {
+                self.grid.clear();
+                draw_mode(self.grid.bitmap_mut(), view, mode, setting, stop);
+            }
+            // The grid is only memory, so can't fail.
+            _ => {
+                self.text.render(&mut self.grid, view).ok();
+            }
+        }
+        self.grid.bitmap()
+    }
+}
+
+// Draws the View on the OLED.
+pub struct OledRenderer<'a, IF> {
+    oled: &'a mut Oled<IF>,
+    view: OledView,
+}
+
+impl<'a, IF: Interface> OledRenderer<'a, IF> {
+    pub fn new(oled: &'a mut Oled<IF>) -> Self {
+        OledRenderer {
+            oled,
+            view: OledView::new(),
+        }
+    }
+}
+
+impl<'a, IF: Interface> Renderer for OledRenderer<'a, IF> {
+    fn render(&mut self, view: &View) -> Result<(), lcd::Error> {
+        let bitmap = self.view.render(view);
+        self.oled.flush(bitmap);
+        Ok(())
+    }
+    fn brightness(&mut self, percent: u8) {
+        self.oled.contrast(percent);
+    }
+}
+
+// Text right-aligned to end at x.
+fn text_right(bitmap: &mut Bitmap, x: i32, y: i32, s: &str, scale: i32) {
+    bitmap.text(x - Bitmap::text_width(s, scale), y, s, scale);
+}
+
+fn draw_mode(bitmap: &mut Bitmap, view: &View, mode: Mode, setting: i32, stop: Option<Stop>) {
+    // Mode icon, its setting, and the status.
+    let icon = match mode {
+        Mode::ServoOff => &SERVO_OFF_ICON,
+        Mode::Feed => &FEED_ICON,
+        Mode::ThreadMetric | Mode::ThreadImperial => &THREAD_ICON,
+    };
+    bitmap.rows(0, HEADER_Y, 8, icon);
+    let setting = match mode {
+        Mode::ServoOff => Line::new(format_args!("Servo off")),
+        Mode::Feed => Line::new(format_args!("{}μm/r", setting)),
+        Mode::ThreadMetric => Line::new(format_args!(
+            "{}.{:02}mm/r",
+            setting / 1000,
+            (setting.abs() / 10) % 100
+        )),
+        Mode::ThreadImperial => Line::new(format_args!("{}TPI", setting)),
+    };
+    bitmap.text(11, HEADER_Y, setting.as_str(), 1);
+    text_right(bitmap, WIDTH as i32, HEADER_Y, view.status, 1);
+    bitmap.fill(0, RULE_Y, WIDTH as i32, 1, true);
+
+    // Carriage position in mm, to a hundredth. Three digits before the
+    // point fit, and the point is drawn narrower than the digits.
+    bitmap.text(0, DRO_Y, "Z", 1);
+    bitmap.text(0, DRO_Y + 13, "mm", 1);
+    let position = view.carriage_position.clamp(-999_990, 999_990);
+    let whole = Line::new(format_args!(
+        "{}{}",
+        if position < 0 { "-" } else { "" },
+        position.abs() / 1000
+    ));
+    let frac = Line::new(format_args!("{:02}", (position.abs() / 10) % 100));
+    let point_width = 3 * DRO_SCALE;
+    let x = WIDTH as i32 - Bitmap::text_width(frac.as_str(), DRO_SCALE);
+    bitmap.text(x, DRO_Y, frac.as_str(), DRO_SCALE);
+    bitmap.columns(x - point_width, DRO_Y, &[0x00, 0x60, 0x60], DRO_SCALE);
+    text_right(bitmap, x - point_width, DRO_Y, whole.as_str(), DRO_SCALE);
+
+    // Spindle speed and direction, and the carriage direction while the
+    // motor's enabled.
+    let rpm = view.rpm.clamp(-9999, 9999);
+    match rpm.signum() {
+        1 => bitmap.rows(0, RPM_Y + 4, 8, &CLOCKWISE_ICON),
+        -1 => bitmap.rows(0, RPM_Y + 4, 8, &ANTICLOCKWISE_ICON),
+        _ => (),
+    }
+    let digits = Line::new(format_args!("{:>4}", rpm.abs()));
+    let x = bitmap.text(11, RPM_Y, digits.as_str(), RPM_SCALE);
+    bitmap.text(x + 2, RPM_Y + 7, "rpm", 1);
+    if view.motor_enable {
+        let arrow = if view.motor_direction { "→" } else { "←" };
+        text_right(bitmap, WIDTH as i32, RPM_Y, arrow, RPM_SCALE);
+    }
+
+    // Distance travelled towards the stop, full once it's reached.
+    if let Some(stop) = stop {
+        let inside = WIDTH as i32 - 2;
+        let filled = if stop.distance > 0 {
+            (stop.travelled.clamp(0, stop.distance) * inside as i64 / stop.distance) as i32
+        } else {
+            inside
+        };
+        bitmap.frame(0, STOP_Y, WIDTH as i32, STOP_HEIGHT);
+        bitmap.fill(1, STOP_Y + 1, filled, STOP_HEIGHT - 2, true);
+    }
+}
@@ -4,14 +4,14 @@ use stm32f4xx_hal::gpio;
 use stm32f4xx_hal::gpio::PinExt;
 use stm32f4xx_hal::pac;
 
-pub struct Pulser<'a> {
+pub struct Pulser {
     set_addr: usize,
     reset_addr: usize,
-    _pin: &'a mut gpio::PEPin<'B', gpio::Output>,
+    _pin: gpio::PEPin<'B', gpio::Output>,
 }
 
-impl<'a> Pulser<'a> {
-    pub fn new(pin: &'a mut gpio::PEPin<'B', gpio::Output>) -> Self {
+impl Pulser {
+    pub fn new(pin: gpio::PEPin<'B', gpio::Output>) -> Self {
         unsafe {
             let gpiob_bsrr = (*pac::GPIOB::ptr()).bsrr.as_ptr() as usize;
             let (set_addr, reset_addr) = Self::bitband_addrs(gpiob_bsrr, pin.pin_id());
@@ -0,0 +1,29 @@
+//! Input filtering for the quadrature encoder timers.
+use stm32f4xx_hal::pac;
+
+use crate::config::Config;
+
+// Only the filter fields are changed, so the timers keep counting, and
+// this doesn't disturb the control interrupt's use of TIM2.
+macro_rules! set_filter {
+    ($tim:expr, $setting:expr) => {{
+        let filter = $setting.filter as u8;
+        $tim.ccmr1_input()
+            .modify(|_, w| w.ic1f().bits(filter).ic2f().bits(filter));
+        $tim.cr1.modify(|_, w| match $setting.prescaler {
+            4 => w.ckd().div4(),
+            2 => w.ckd().div2(),
+            _ => w.ckd().div1(),
+        });
+    }};
+}
+
+// Apply the encoder input filter settings from the configuration.
+pub fn set_filters(config: &Config) {
+    let [spindle, feed, mode] = config.get_encoder_filters();
+    unsafe {
+        set_filter!(&*pac::TIM2::ptr(), spindle);
+        set_filter!(&*pac::TIM3::ptr(), feed);
+        set_filter!(&*pac::TIM4::ptr(), mode);
+    }
+}
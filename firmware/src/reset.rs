@@ -0,0 +1,537 @@
+//! Reset cause tracking and motor-safe panic handler.
+use core::fmt::{self, Write};
+use core::panic::PanicInfo;
+use embedded_hal::delay::DelayNs;
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+use embedded_hal::digital::InputPin;
+use embedded_hal::digital::{ErrorType, OutputPin};
+#[cfg(any(feature = "lcd-i2c", all(feature = "oled", not(feature = "oled-spi"))))]
+use embedded_hal::i2c;
+#[cfg(feature = "oled-spi")]
+use embedded_hal::spi;
+use stm32f4xx_hal::pac;
+
+#[cfg(not(feature = "oled"))]
+use crate::lcd;
+use crate::lcd::CharacterDisplay;
+
+// Backup register layout used to carry panic details across a reset.
+const BKP_MAGIC: usize = 0;
+const BKP_LINE: usize = 1;
+const BKP_FILE: usize = 2; // 4 registers, 16 bytes of file name.
+const BKP_FILE_LEN: usize = 4;
+const MAGIC_PANIC: u32 = 0x50414e43; // "PANC"
+const MAGIC_DFU: u32 = 0x44465530; // "DFU0"
+
+// STM32F411 system memory, containing the ROM bootloader.
+const SYSTEM_MEMORY: u32 = 0x1fff_0000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Cause {
+    PowerOn,
+    Pin,
+    Software,
+    Watchdog,
+    Panic,
+    Other,
+}
+
+// Short "file:line" panic location, small enough for one display line.
+pub struct Location {
+    buf: [u8; 16],
+    len: usize,
+}
+
+impl Location {
+    const fn new() -> Self {
+        Location {
+            buf: [0; 16],
+            len: 0,
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("?")
+    }
+}
+
+impl Write for Location {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            if self.len >= self.buf.len() {
+                return Err(fmt::Error);
+            }
+            self.buf[self.len] = b;
+            self.len += 1;
+        }
+        Ok(())
+    }
+}
+
+pub struct ResetInfo {
+    pub cause: Cause,
+    pub location: Location,
+}
+
+impl ResetInfo {
+    // Message to show at startup, if the last reset was unexpected.
+    pub fn message(&self) -> Option<(&str, &str)> {
+        match self.cause {
+            // "LAST RESET:" and a five character cause, to fill 16 columns.
+            Cause::Panic => Some(("LAST RESET:PANIC", self.location.as_str())),
+            Cause::Watchdog => Some(("LAST RESET:WDOG ", "")),
+            _ => None,
+        }
+    }
+}
+
+fn enable_backup_access(rcc: &pac::rcc::RegisterBlock, pwr: &pac::pwr::RegisterBlock) {
+    rcc.apb1enr.modify(|_, w| w.pwren().set_bit());
+    pwr.cr.modify(|_, w| w.dbp().set_bit());
+}
+
+// Determine why we were reset and clear the reset flags and any panic
+// record ready for next time. Must be called before the RCC is constrained.
+// If a reboot to the bootloader was requested, this doesn't return.
+pub fn take_reset_info(rcc: &pac::RCC, pwr: &pac::PWR, rtc: &pac::RTC) -> ResetInfo {
+    enable_backup_access(rcc, pwr);
+    if rtc.bkpr[BKP_MAGIC].read().bits() == MAGIC_DFU {
+        rtc.bkpr[BKP_MAGIC].write(|w| w.bits(0));
+        enter_bootloader(rcc);
+    }
+    let mut info = ResetInfo {
+        cause: Cause::Other,
+        location: Location::new(),
+    };
+    let csr = rcc.csr.read();
+    if rtc.bkpr[BKP_MAGIC].read().bits() == MAGIC_PANIC {
+        info.cause = Cause::Panic;
+        let mut file = [0u8; BKP_FILE_LEN * 4];
+        for (i, chunk) in file.chunks_mut(4).enumerate() {
+            let word = rtc.bkpr[BKP_FILE + i].read().bits();
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        let len = file.iter().position(|&b| b == 0).unwrap_or(file.len());
+        let file = core::str::from_utf8(&file[..len]).unwrap_or("?");
+        let line = rtc.bkpr[BKP_LINE].read().bits();
+        write!(info.location, "{}:{}", file, line).ok();
+    } else if csr.wdgrstf().bit_is_set() {
+        info.cause = Cause::Watchdog;
+    } else if csr.porrstf().bit_is_set() || csr.borrstf().bit_is_set() {
+        info.cause = Cause::PowerOn;
+    } else if csr.sftrstf().bit_is_set() {
+        info.cause = Cause::Software;
+    } else if csr.padrstf().bit_is_set() {
+        info.cause = Cause::Pin;
+    }
+    rcc.csr.modify(|_, w| w.rmvf().set_bit());
+    rtc.bkpr[BKP_MAGIC].write(|w| w.bits(0));
+    info
+}
+
+// Reset into the ROM DFU bootloader.
+pub fn reboot_to_bootloader() -> ! {
+    let rcc = unsafe { &*pac::RCC::ptr() };
+    let pwr = unsafe { &*pac::PWR::ptr() };
+    let rtc = unsafe { &*pac::RTC::ptr() };
+    enable_backup_access(rcc, pwr);
+    rtc.bkpr[BKP_MAGIC].write(|w| w.bits(MAGIC_DFU));
+    cortex_m::peripheral::SCB::sys_reset();
+}
+
+// Jump to the ROM bootloader. This is done straight after a reset, while
+// the clocks and peripherals are still in their reset state.
+fn enter_bootloader(rcc: &pac::RCC) -> ! {
+    // Map system memory at address zero, as if booted with BOOT0 high.
+    rcc.apb2enr.modify(|_, w| w.syscfgen().set_bit());
+    let syscfg = unsafe { &*pac::SYSCFG::ptr() };
+    syscfg
+        .memrm
+        .modify(|_, w| unsafe { w.mem_mode().bits(0b01) });
+    unsafe { cortex_m::asm::bootload(SYSTEM_MEMORY as *const u32) }
+}
+
+fn record_panic(file: &str, line: u32) {
+    let rcc = unsafe { &*pac::RCC::ptr() };
+    let pwr = unsafe { &*pac::PWR::ptr() };
+    let rtc = unsafe { &*pac::RTC::ptr() };
+    enable_backup_access(rcc, pwr);
+    // Keep the tail of the file name; it has the interesting part.
+    let name = file.rsplit('/').next().unwrap_or(file).as_bytes();
+    let mut buf = [0u8; BKP_FILE_LEN * 4];
+    let n = name.len().min(buf.len() - 6); // Leave room for ":line".
+    buf[..n].copy_from_slice(&name[..n]);
+    for (i, chunk) in buf.chunks(4).enumerate() {
+        let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        rtc.bkpr[BKP_FILE + i].write(|w| w.bits(word));
+    }
+    rtc.bkpr[BKP_LINE].write(|w| w.bits(line));
+    rtc.bkpr[BKP_MAGIC].write(|w| w.bits(MAGIC_PANIC));
+}
+
+// Output pin driven directly through a GPIO BSRR register. The panic
+// handler can't rely on the HAL pin objects owned by main().
+struct RawPin {
+    bsrr: *mut u32,
+    bit: u8,
+}
+
+impl RawPin {
+    fn new(bsrr: *mut u32, bit: u8) -> Self {
+        RawPin { bsrr, bit }
+    }
+}
+
+impl ErrorType for RawPin {
+    type Error = core::convert::Infallible;
+}
+
+impl OutputPin for RawPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        unsafe { core::ptr::write_volatile(self.bsrr, 1 << (self.bit + 16)) };
+        Ok(())
+    }
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        unsafe { core::ptr::write_volatile(self.bsrr, 1 << self.bit) };
+        Ok(())
+    }
+}
+
+// Pin that can be turned around to read, for the display's data pins,
+// driven directly through the GPIO port's MODER, IDR and BSRR registers.
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+struct RawIoPin {
+    port: *mut u32,
+    bit: u8,
+}
+
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl RawIoPin {
+    const MODER: usize = 0;
+    const IDR: usize = 4;
+    const BSRR: usize = 6;
+
+    fn new(port: *mut u32, bit: u8) -> Self {
+        RawIoPin { port, bit }
+    }
+    fn set_mode(&mut self, mode: u32) {
+        unsafe {
+            let moder = self.port.add(Self::MODER);
+            let shift = self.bit * 2;
+            let bits = core::ptr::read_volatile(moder) & !(0b11 << shift);
+            core::ptr::write_volatile(moder, bits | mode << shift);
+        }
+    }
+}
+
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl ErrorType for RawIoPin {
+    type Error = core::convert::Infallible;
+}
+
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl OutputPin for RawIoPin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        RawPin::new(unsafe { self.port.add(Self::BSRR) }, self.bit).set_low()
+    }
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        RawPin::new(unsafe { self.port.add(Self::BSRR) }, self.bit).set_high()
+    }
+}
+
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl InputPin for RawIoPin {
+    fn is_high(&mut self) -> Result<bool, Self::Error> {
+        let idr = unsafe { core::ptr::read_volatile(self.port.add(Self::IDR)) };
+        Ok(idr & (1 << self.bit) != 0)
+    }
+    fn is_low(&mut self) -> Result<bool, Self::Error> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+#[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+impl lcd::DataPin for RawIoPin {
+    fn set_input(&mut self) {
+        self.set_mode(0b00);
+    }
+    fn set_output(&mut self) {
+        self.set_mode(0b01);
+    }
+}
+
+// Busy-wait delay that doesn't depend on any peripheral setup. Assumes the
+// fastest core clock, so waits are at least as long as requested.
+struct SpinDelay;
+
+impl DelayNs for SpinDelay {
+    fn delay_ns(&mut self, ns: u32) {
+        cortex_m::asm::delay(ns / 10 + 1);
+    }
+}
+
+// Writes on I2C3 driven directly through its registers, relying on main()
+// having configured it. Every wait is bounded, in case the panic left the
+// bus in a state it won't recover from.
+#[cfg(any(feature = "lcd-i2c", all(feature = "oled", not(feature = "oled-spi"))))]
+struct RawI2c;
+
+#[cfg(any(feature = "lcd-i2c", all(feature = "oled", not(feature = "oled-spi"))))]
+const I2C_TIMEOUT_LOOPS: u32 = 100_000;
+
+#[cfg(any(feature = "lcd-i2c", all(feature = "oled", not(feature = "oled-spi"))))]
+impl RawI2c {
+    fn wait(&self, done: impl Fn(&pac::i2c1::RegisterBlock) -> bool) -> Result<(), i2c::ErrorKind> {
+        let regs = unsafe { &*pac::I2C3::ptr() };
+        for _ in 0..I2C_TIMEOUT_LOOPS {
+            if regs.sr1.read().af().bit_is_set() {
+                return Err(i2c::ErrorKind::NoAcknowledge(
+                    i2c::NoAcknowledgeSource::Unknown,
+                ));
+            }
+            if done(regs) {
+                return Ok(());
+            }
+        }
+        Err(i2c::ErrorKind::Other)
+    }
+    fn write_bytes(&mut self, address: u8, bytes: &[u8]) -> Result<(), i2c::ErrorKind> {
+        let regs = unsafe { &*pac::I2C3::ptr() };
+        regs.sr1.write(|w| unsafe { w.bits(0) });
+        regs.cr1.modify(|_, w| w.start().set_bit());
+        self.wait(|r| r.sr1.read().sb().bit_is_set())?;
+        regs.dr.write(|w| unsafe { w.bits((address as u32) << 1) });
+        self.wait(|r| r.sr1.read().addr().bit_is_set())?;
+        regs.sr2.read();
+        for byte in bytes {
+            self.wait(|r| r.sr1.read().tx_e().bit_is_set())?;
+            regs.dr.write(|w| unsafe { w.bits(*byte as u32) });
+        }
+        self.wait(|r| r.sr1.read().btf().bit_is_set())?;
+        regs.cr1.modify(|_, w| w.stop().set_bit());
+        Ok(())
+    }
+}
+
+#[cfg(any(feature = "lcd-i2c", all(feature = "oled", not(feature = "oled-spi"))))]
+impl i2c::ErrorType for RawI2c {
+    type Error = i2c::ErrorKind;
+}
+
+#[cfg(any(feature = "lcd-i2c", all(feature = "oled", not(feature = "oled-spi"))))]
+impl i2c::I2c for RawI2c {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        for operation in operations {
+            match operation {
+                i2c::Operation::Write(bytes) => self.write_bytes(address, bytes)?,
+                i2c::Operation::Read(_) => return Err(i2c::ErrorKind::Other),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Writes on SPI2 driven directly through its registers, relying on main()
+// having configured it. As for I2C, every wait is bounded.
+#[cfg(feature = "oled-spi")]
+struct RawSpi;
+
+#[cfg(feature = "oled-spi")]
+const SPI_TIMEOUT_LOOPS: u32 = 100_000;
+
+#[cfg(feature = "oled-spi")]
+impl RawSpi {
+    fn wait(&self, done: impl Fn(&pac::spi1::RegisterBlock) -> bool) -> Result<(), spi::ErrorKind> {
+        let regs = unsafe { &*pac::SPI2::ptr() };
+        for _ in 0..SPI_TIMEOUT_LOOPS {
+            if done(regs) {
+                return Ok(());
+            }
+        }
+        Err(spi::ErrorKind::Other)
+    }
+}
+
+#[cfg(feature = "oled-spi")]
+impl spi::ErrorType for RawSpi {
+    type Error = spi::ErrorKind;
+}
+
+// Only writes are needed.
+#[cfg(feature = "oled-spi")]
+impl spi::SpiBus for RawSpi {
+    fn read(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+        Err(spi::ErrorKind::Other)
+    }
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        let regs = unsafe { &*pac::SPI2::ptr() };
+        for word in words {
+            self.wait(|r| r.sr.read().txe().bit_is_set())?;
+            regs.dr.write(|w| unsafe { w.bits(*word as u32) });
+        }
+        Ok(())
+    }
+    fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Self::Error> {
+        Err(spi::ErrorKind::Other)
+    }
+    fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Self::Error> {
+        Err(spi::ErrorKind::Other)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.wait(|r| r.sr.read().txe().bit_is_set())?;
+        self.wait(|r| r.sr.read().bsy().bit_is_clear())
+    }
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+    let gpiob = unsafe { (*pac::GPIOB::ptr()).bsrr.as_ptr() };
+
+    // Make the motor safe first: drop enable (PB10) and step (PB0).
+    RawPin::new(gpiob, 10).set_low().ok();
+    RawPin::new(gpiob, 0).set_low().ok();
+
+    let (file, line) = match info.location() {
+        Some(loc) => (loc.file(), loc.line()),
+        None => ("?", 0),
+    };
+    record_panic(file, line);
+
+    // Report the panic on the display. Pin assignments must match main().
+    let name = file.rsplit('/').next().unwrap_or(file);
+    let mut delay = SpinDelay;
+    #[cfg(not(any(feature = "lcd-4bit", feature = "lcd-i2c", feature = "oled")))]
+    {
+        let porta = pac::GPIOA::ptr() as *mut u32;
+        let portb = pac::GPIOB::ptr() as *mut u32;
+        let mut rs = RawPin::new(gpiob, 3);
+        let mut rw = RawPin::new(gpiob, 9);
+        let mut e = RawPin::new(gpiob, 4);
+        let mut db0 = RawIoPin::new(porta, 15);
+        #[cfg(not(feature = "usb"))]
+        let mut db1 = RawIoPin::new(porta, 12);
+        #[cfg(feature = "usb")]
+        let mut db1 = RawIoPin::new(portb, 5);
+        let mut db2 = RawIoPin::new(porta, 8);
+        let mut db3 = RawIoPin::new(portb, 14);
+        let mut db4 = RawIoPin::new(portb, 13);
+        let mut db5 = RawIoPin::new(portb, 12);
+        let mut db6 = RawIoPin::new(portb, 15);
+        #[cfg(not(feature = "usb"))]
+        let mut db7 = RawIoPin::new(porta, 11);
+        #[cfg(feature = "usb")]
+        let mut db7 = RawIoPin::new(portb, 2);
+        let mut display = lcd::Display8Bit::new(
+            lcd::Geometry::<{ crate::DISPLAY_COLS }, { crate::DISPLAY_ROWS }>,
+            crate::DISPLAY_CHARMAP,
+            &mut rs,
+            &mut rw,
+            &mut e,
+            &mut db0,
+            &mut db1,
+            &mut db2,
+            &mut db3,
+            &mut db4,
+            &mut db5,
+            &mut db6,
+            &mut db7,
+            &mut delay,
+        );
+        show_panic(&mut display, name, line);
+    }
+    // The 4-bit interface may have been half way through a byte, so these
+    // displays are initialised again first.
+    #[cfg(feature = "lcd-4bit")]
+    {
+        #[cfg(not(feature = "usb"))]
+        let gpioa = unsafe { (*pac::GPIOA::ptr()).bsrr.as_ptr() };
+        let mut rs = RawPin::new(gpiob, 3);
+        let mut rw = RawPin::new(gpiob, 9);
+        let mut e = RawPin::new(gpiob, 4);
+        let mut db4 = RawPin::new(gpiob, 13);
+        let mut db5 = RawPin::new(gpiob, 12);
+        let mut db6 = RawPin::new(gpiob, 15);
+        #[cfg(not(feature = "usb"))]
+        let mut db7 = RawPin::new(gpioa, 11);
+        #[cfg(feature = "usb")]
+        let mut db7 = RawPin::new(gpiob, 2);
+        let mut display = lcd::Display4Bit::new(
+            lcd::Geometry::<{ crate::DISPLAY_COLS }, { crate::DISPLAY_ROWS }>,
+            crate::DISPLAY_CHARMAP,
+            &mut rs,
+            &mut rw,
+            &mut e,
+            &mut db4,
+            &mut db5,
+            &mut db6,
+            &mut db7,
+            &mut delay,
+        );
+        display.init();
+        show_panic(&mut display, name, line);
+    }
+    #[cfg(feature = "lcd-i2c")]
+    {
+        let mut i2c = RawI2c;
+        let mut display = lcd::DisplayI2c::new(
+            lcd::Geometry::<{ crate::DISPLAY_COLS }, { crate::DISPLAY_ROWS }>,
+            crate::DISPLAY_CHARMAP,
+            &mut i2c,
+            crate::LCD_I2C_ADDRESS,
+            &mut delay,
+        );
+        display.init();
+        show_panic(&mut display, name, line);
+    }
+    #[cfg(all(feature = "oled", not(feature = "oled-spi")))]
+    {
+        let mut i2c = RawI2c;
+        let mut display = crate::oled::Oled::new(
+            crate::oled::I2cInterface::new(&mut i2c, crate::OLED_I2C_ADDRESS),
+            crate::OLED_CONTROLLER,
+        );
+        show_panic_oled(&mut display, &mut delay, name, line);
+    }
+    // Chip select on PB12, data/command on PB14.
+    #[cfg(feature = "oled-spi")]
+    {
+        let mut spi = RawSpi;
+        let mut cs = RawPin::new(gpiob, 12);
+        let mut dc = RawPin::new(gpiob, 14);
+        let mut display = crate::oled::Oled::new(
+            crate::oled::SpiInterface::new(&mut spi, &mut cs, &mut dc),
+            crate::OLED_CONTROLLER,
+        );
+        show_panic_oled(&mut display, &mut delay, name, line);
+    }
+
+    // Wait for the watchdog to reset us. Start it in case it wasn't already
+    // running; the reset default timeout is about half a second.
+    unsafe { (*pac::IWDG::ptr()).kr.write(|w| w.key().bits(0xcccc)) };
+    loop {
+        cortex_m::asm::nop();
+    }
+}
+
+#[cfg(feature = "oled")]
+fn show_panic_oled<IF: crate::oled::Interface>(
+    display: &mut crate::oled::Oled<IF>,
+    delay: &mut impl DelayNs,
+    name: &str,
+    line: u32,
+) {
+    display.init(delay);
+    let mut grid = crate::bitmap::TextGrid::new();
+    show_panic(&mut grid, name, line);
+    display.flush(grid.bitmap());
+}
+
+fn show_panic<D: CharacterDisplay + Write>(display: &mut D, name: &str, line: u32) {
+    write!(display.at(0, 0), "{:<16}", "PANIC").ok();
+    write!(display.at(0, 1), "{:<16}", "").ok();
+    write!(display.at(0, 1), "{:.10}:{}", name, line).ok();
+}
@@ -0,0 +1,162 @@
+//! Servo enable sequencing and latched fault handling.
+use crate::control::Direction;
+
+// Time allowed for the drive to report ready after being enabled.
+const ENABLE_TIMEOUT: i64 = 1000; // ms.
+
+// ClearPath drives clear an alarm when enable is deasserted then asserted.
+// Hold enable low for at least this long before reasserting it.
+const ALARM_CLEAR_LOW_TIME: i64 = 250; // ms.
+
+// Time allowed for the drive to report ready after an alarm clear.
+const ALARM_CLEAR_TIMEOUT: i64 = 1500; // ms.
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum State {
+    // Motor not requested; enable deasserted.
+    Disabled,
+    // Enable asserted, waiting for the drive to report ready.
+    Enabling { deadline: i64 },
+    // Drive ready; motion allowed.
+    Running,
+    // Fault latched; enable deasserted until acknowledged.
+    Faulted,
+    // Acknowledged; holding enable low to clear the drive alarm.
+    ClearLow { until: i64 },
+    // Enable reasserted, waiting for the drive to report ready.
+    ClearHigh { deadline: i64 },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fault {
+    // Drive dropped servo OK while running.
+    ServoLost,
+    // Drive never reported ready after being enabled.
+    EnableTimeout,
+    // Drive did not report ready after the alarm clear sequence.
+    ClearFailed,
+    // Spindle encoder counts slipped between index pulses while threading.
+    EncoderSlip,
+    // The step loopback counted a different number of pulses to those
+    // emitted.
+    StepMismatch,
+}
+
+impl Fault {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fault::ServoLost => "servo-lost",
+            Fault::EnableTimeout => "enable-timeout",
+            Fault::ClearFailed => "clear-failed",
+            Fault::EncoderSlip => "encoder-slip",
+            Fault::StepMismatch => "step-mismatch",
+        }
+    }
+}
+
+pub struct Servo {
+    state: State,
+    fault: Option<Fault>,
+    // Motor pulses commanded while faulted that were not sent.
+    lost_pulses: i64,
+}
+
+impl Servo {
+    pub fn new() -> Self {
+        Servo {
+            state: State::Disabled,
+            fault: None,
+            lost_pulses: 0,
+        }
+    }
+
+    // Advance the enable/fault state machine. Returns the desired state
+    // of the motor enable output.
+    pub fn update(&mut self, now_ms: i64, requested: bool, servo_ok: bool) -> bool {
+        self.state = match self.state {
+            State::Disabled | State::Enabling { .. } | State::Running if !requested => {
+                State::Disabled
+            }
+            State::Disabled => State::Enabling {
+                deadline: now_ms + ENABLE_TIMEOUT,
+            },
+            State::Enabling { .. } if servo_ok => State::Running,
+            State::Enabling { deadline } if deadline < now_ms => self.latch(Fault::EnableTimeout),
+            State::Running if !servo_ok => self.latch(Fault::ServoLost),
+            State::ClearLow { until } if until < now_ms => {
+                if requested {
+                    State::ClearHigh {
+                        deadline: now_ms + ALARM_CLEAR_TIMEOUT,
+                    }
+                } else {
+                    self.fault = None;
+                    State::Disabled
+                }
+            }
+            State::ClearHigh { .. } if servo_ok || !requested => {
+                self.fault = None;
+                if requested {
+                    State::Running
+                } else {
+                    State::Disabled
+                }
+            }
+            State::ClearHigh { deadline } if deadline < now_ms => self.latch(Fault::ClearFailed),
+            state => state,
+        };
+        matches!(
+            self.state,
+            State::Enabling { .. } | State::Running | State::ClearHigh { .. }
+        )
+    }
+
+    fn latch(&mut self, fault: Fault) -> State {
+        // Keep the lost position across a failed alarm clear.
+        if self.fault.is_none() {
+            self.lost_pulses = 0;
+        }
+        self.fault = Some(fault);
+        State::Faulted
+    }
+
+    // Latch a fault detected outside the drive, if the motor is enabled.
+    pub fn trip(&mut self, fault: Fault) {
+        if matches!(self.state, State::Enabling { .. } | State::Running) {
+            self.state = self.latch(fault);
+        }
+    }
+
+    // Operator acknowledgement of a latched fault. Starts the alarm clear
+    // sequence; motion is not allowed until it completes.
+    pub fn acknowledge(&mut self, now_ms: i64) {
+        if self.state == State::Faulted {
+            self.state = State::ClearLow {
+                until: now_ms + ALARM_CLEAR_LOW_TIME,
+            };
+        }
+    }
+
+    // Account for pulses that the controller wanted to send but couldn't.
+    pub fn record_lost(&mut self, direction: Direction, pulses: u32) {
+        if self.fault.is_none() {
+            return;
+        }
+        match direction {
+            Direction::Forward => self.lost_pulses += pulses as i64,
+            Direction::Backwards => self.lost_pulses -= pulses as i64,
+        }
+    }
+
+    pub fn motion_allowed(&self) -> bool {
+        self.state == State::Running
+    }
+    pub fn get_state(&self) -> State {
+        self.state
+    }
+    pub fn get_fault(&self) -> Option<Fault> {
+        self.fault
+    }
+    pub fn get_lost_pulses(&self) -> i64 {
+        self.lost_pulses
+    }
+}
@@ -0,0 +1,202 @@
+//! Binary telemetry frames.
+//!
+//! Frames are a fixed 26 bytes, little-endian:
+//!
+//! | offset | size | field                                          |
+//! |--------|------|------------------------------------------------|
+//! | 0      | 2    | sync, 0xa5 0x5a                                |
+//! | 2      | 1    | frame version                                  |
+//! | 3      | 4    | timestamp (ms, u32)                            |
+//! | 7      | 4    | spindle encoder position (i32, low 32 bits)    |
+//! | 11     | 2    | smoothed spindle RPM (i16)                     |
+//! | 13     | 4    | motor pulses since previous frame (i32, net)   |
+//! | 17     | 1    | flags: direction, servo OK, motor enable       |
+//! | 18     | 4    | fractional pulses remaining (u32, 0.32)        |
+//! | 22     | 2    | longest main loop pass since last frame (µs)   |
+//! | 24     | 2    | CRC-16/CCITT-FALSE of bytes 2..24              |
+//!
+//! Frames may be interleaved with console text; the sync bytes never appear
+//! in console output and the CRC rejects false matches.
+pub const FRAME_LEN: usize = 26;
+const SYNC: [u8; 2] = [0xa5, 0x5a];
+const VERSION: u8 = 1;
+
+const FLAG_DIRECTION: u8 = 0x01;
+const FLAG_SERVO_OK: u8 = 0x02;
+const FLAG_MOTOR_ENABLE: u8 = 0x04;
+
+#[derive(Clone, Copy, Default)]
+pub struct Frame {
+    pub timestamp_ms: u32,
+    pub spindle_count: i32,
+    pub rpm: i16,
+    pub motor_pulses: i32,
+    pub direction: bool,
+    pub servo_ok: bool,
+    pub motor_enable: bool,
+    pub fractional: u32,
+    // The main loop runs the user interface and console. The control
+    // interrupt's own times are reported by the console's timing command.
+    pub main_loop_us: u16,
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+impl Frame {
+    pub fn encode(&self) -> [u8; FRAME_LEN] {
+        let mut buf = [0u8; FRAME_LEN];
+        buf[0..2].copy_from_slice(&SYNC);
+        buf[2] = VERSION;
+        buf[3..7].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        buf[7..11].copy_from_slice(&self.spindle_count.to_le_bytes());
+        buf[11..13].copy_from_slice(&self.rpm.to_le_bytes());
+        buf[13..17].copy_from_slice(&self.motor_pulses.to_le_bytes());
+        buf[17] = (if self.direction { FLAG_DIRECTION } else { 0 })
+            | (if self.servo_ok { FLAG_SERVO_OK } else { 0 })
+            | (if self.motor_enable {
+                FLAG_MOTOR_ENABLE
+            } else {
+                0
+            });
+        buf[18..22].copy_from_slice(&self.fractional.to_le_bytes());
+        buf[22..24].copy_from_slice(&self.main_loop_us.to_le_bytes());
+        let crc = crc16(&buf[2..24]);
+        buf[24..26].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    // Only the host decodes frames.
+    #[cfg(not(target_os = "none"))]
+    pub fn decode(buf: &[u8; FRAME_LEN]) -> Option<Frame> {
+        if buf[0..2] != SYNC || buf[2] != VERSION {
+            return None;
+        }
+        if crc16(&buf[2..24]) != u16::from_le_bytes([buf[24], buf[25]]) {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        Some(Frame {
+            timestamp_ms: u32_at(3),
+            spindle_count: u32_at(7) as i32,
+            rpm: i16::from_le_bytes([buf[11], buf[12]]),
+            motor_pulses: u32_at(13) as i32,
+            direction: buf[17] & FLAG_DIRECTION != 0,
+            servo_ok: buf[17] & FLAG_SERVO_OK != 0,
+            motor_enable: buf[17] & FLAG_MOTOR_ENABLE != 0,
+            fractional: u32_at(18),
+            main_loop_us: u16::from_le_bytes([buf[22], buf[23]]),
+        })
+    }
+}
+
+// Schedules frames at a configurable rate, accumulating the per-frame
+// motor pulse count and worst case main loop time in between.
+pub struct Stream {
+    interval_ms: i64,
+    next_ms: i64,
+    motor_pulses: i32,
+    main_loop_max_us: u16,
+}
+
+impl Stream {
+    pub const MAX_RATE: u32 = 500; // Hz
+
+    pub const fn new() -> Self {
+        Stream {
+            interval_ms: 0,
+            next_ms: 0,
+            motor_pulses: 0,
+            main_loop_max_us: 0,
+        }
+    }
+
+    // Set frame rate in Hz; zero disables the stream. The console only
+    // passes rates that divide 1000, so the interval is exact.
+    pub fn set_rate(&mut self, hz: u32) {
+        self.interval_ms = if hz == 0 {
+            0
+        } else {
+            1000 / hz.min(Self::MAX_RATE) as i64
+        };
+        self.next_ms = 0;
+    }
+
+    pub fn record_motor_pulses(&mut self, motor_pulses: i32) {
+        self.motor_pulses = self.motor_pulses.wrapping_add(motor_pulses);
+    }
+    pub fn record_main_loop_time(&mut self, main_loop_us: u32) {
+        self.main_loop_max_us = self
+            .main_loop_max_us
+            .max(main_loop_us.min(u16::MAX as u32) as u16);
+    }
+
+    // If a frame is due, complete it with the accumulated values and
+    // return its encoding.
+    pub fn poll(&mut self, now_ms: i64, mut frame: Frame) -> Option<[u8; FRAME_LEN]> {
+        if self.interval_ms == 0 || now_ms < self.next_ms {
+            return None;
+        }
+        self.next_ms = now_ms + self.interval_ms;
+        frame.motor_pulses = core::mem::take(&mut self.motor_pulses);
+        frame.main_loop_us = core::mem::take(&mut self.main_loop_max_us);
+        Some(frame.encode())
+    }
+}
+
+// Finds frames in a byte stream that may also contain console text, on the
+// host.
+#[cfg(not(target_os = "none"))]
+pub struct Decoder {
+    buf: [u8; FRAME_LEN],
+    len: usize,
+}
+
+#[cfg(not(target_os = "none"))]
+impl Decoder {
+    pub const fn new() -> Self {
+        Decoder {
+            buf: [0; FRAME_LEN],
+            len: 0,
+        }
+    }
+
+    // Add a received byte. Returns a frame once a valid one is complete.
+    pub fn push(&mut self, byte: u8) -> Option<Frame> {
+        if self.len < SYNC.len() && byte != SYNC[self.len] {
+            // Not a frame start; this may be the first sync byte though.
+            self.len = 0;
+            if byte != SYNC[0] {
+                return None;
+            }
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < FRAME_LEN {
+            return None;
+        }
+        self.len = 0;
+        let frame = Frame::decode(&self.buf);
+        if frame.is_none() {
+            // Resynchronise on any sync sequence within the bad frame.
+            if let Some(i) = self.buf[1..].windows(2).position(|w| w == SYNC) {
+                let start = i + 1;
+                self.len = FRAME_LEN - start;
+                self.buf.copy_within(start.., 0);
+            }
+        }
+        frame
+    }
+}
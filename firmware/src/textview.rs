@@ -0,0 +1,357 @@
+//! Text layouts for character displays.
+//!
+//! Draws the user interface's View in 16x2 text, using the extra space on
+//! larger displays where there is some.
+use core::fmt::Write;
+
+use crate::lcd::{self, CharacterDisplay};
+use crate::servo::Fault;
+use crate::timing;
+use crate::userinterface::{DebugPage, Mode};
+#[cfg(not(feature = "oled"))]
+use crate::view::Renderer;
+use crate::view::{Debug, Screen, View};
+use crate::widgets;
+
+pub struct TextView {
+    cold: bool,
+    spinner: widgets::Spinner,
+}
+
+impl TextView {
+    pub const fn new() -> Self {
+        TextView {
+            cold: true,
+            spinner: widgets::Spinner::new(),
+        }
+    }
+
+    pub fn render<D>(&mut self, display: &mut D, view: &View) -> Result<(), lcd::Error>
+    where
+        D: CharacterDisplay + Write,
+    {
+        if self.cold {
+            widgets::program_glyphs(display);
+            self.cold = false;
+        }
+        self.spinner.update(display, view.rpm);
+
+        // Layouts needn't fill displays larger than 16x2, so start from a
+        // blank screen. Drawn through a FrameBuffer, this costs nothing.
+        display.clear();
+        match &view.screen {
+            Screen::Message(message1, message2) => {
+                write!(display.at(0, 0), "{:^w$}", message1, w = D::COLS).ok();
+                write!(display.at(0, 1), "{:^w$}", message2, w = D::COLS).ok();
+            }
+            Screen::Mode { mode, setting, .. } => {
+                display_mode(display, *mode, *setting);
+                display_rpm(display, view);
+            }
+            Screen::Fault {
+                fault,
+                lost,
+                clearing,
+            } => display_fault(display, view.now_ms, *fault, *lost, *clearing),
+            Screen::Debug(debug) => display_debug(display, view, debug),
+        }
+        display.flush()
+    }
+}
+
+// Draws the View on a character display. The OLED draws text on its own
+// grid instead.
+#[cfg(not(feature = "oled"))]
+pub struct TextRenderer<'a, D> {
+    display: &'a mut D,
+    view: TextView,
+}
+
+#[cfg(not(feature = "oled"))]
+impl<'a, D> TextRenderer<'a, D>
+where
+    D: CharacterDisplay + Write,
+{
+    pub fn new(display: &'a mut D) -> Self {
+        TextRenderer {
+            display,
+            view: TextView::new(),
+        }
+    }
+}
+
+#[cfg(not(feature = "oled"))]
+impl<'a, D> Renderer for TextRenderer<'a, D>
+where
+    D: CharacterDisplay + Write,
+{
+    fn render(&mut self, view: &View) -> Result<(), lcd::Error> {
+        self.view.render(self.display, view)
+    }
+    fn brightness(&mut self, percent: u8) {
+        self.display.brightness(percent);
+    }
+}
+
+// Spindle speed and status, with the spindle indicator. Four line displays
+// show the carriage position on the third line and the status on the
+// fourth, and wide ones show it all on the second.
+fn display_rpm<D>(display: &mut D, view: &View)
+where
+    D: CharacterDisplay + Write,
+{
+    // Four digits fit on the narrowest layout.
+    let rpm = view.rpm.clamp(-9999, 9999);
+    write!(display.at(0, 1), "RPM").ok();
+    display.char(widgets::SPINNER);
+    if D::ROWS >= 4 {
+        write!(display, "{:<+w$}", rpm, w = D::COLS - 4).ok();
+        write!(
+            display.at(0, 2),
+            "Z {:>+w$}μm",
+            view.carriage_position,
+            w = D::COLS - 4
+        )
+        .ok();
+        write!(
+            display.at(0, 3),
+            "Status {:<w$}",
+            view.status,
+            w = D::COLS - 7
+        )
+        .ok();
+    } else if D::COLS >= 32 {
+        write!(
+            display,
+            "{:<+5} Z{:>+11}μm{:>w$}",
+            rpm,
+            view.carriage_position,
+            view.status,
+            w = D::COLS - 24
+        )
+        .ok();
+    } else {
+        write!(display, "{:<+5}{:>w$}", rpm, view.status, w = D::COLS - 9).ok();
+    }
+}
+
+// The mode and its setting.
+fn display_mode<D>(display: &mut D, mode: Mode, setting: i32)
+where
+    D: CharacterDisplay + Write,
+{
+    match mode {
+        Mode::ServoOff => {
+            write!(display.at(0, 0), "{:<16}", "Servo off").ok();
+        }
+        Mode::Feed => {
+            write!(display.at(0, 0), "Feed {:>+w$}", setting, w = D::COLS - 6).ok();
+            display.char(widgets::MICRON_PER_REV);
+        }
+        Mode::ThreadMetric => {
+            let whole = setting / 1000;
+            let frac = (setting.abs() / 10) % 100;
+            write!(
+                display.at(0, 0),
+                "Thread{:>+w$}.{:02}mm/r",
+                whole,
+                frac,
+                w = D::COLS - 13
+            )
+            .ok();
+        }
+        Mode::ThreadImperial => {
+            write!(
+                display.at(0, 0),
+                "Thread Im {:>+w$}",
+                setting,
+                w = D::COLS - 11
+            )
+            .ok();
+            display.char(widgets::TPI);
+        }
+    }
+}
+
+// A latched servo fault.
+fn display_fault<D>(display: &mut D, now_ms: i64, fault: Fault, lost: i64, clearing: bool)
+where
+    D: CharacterDisplay + Write,
+{
+    let name = match fault {
+        Fault::ServoLost => "SERVO FAULT",
+        Fault::EnableTimeout => "SERVO NOT READY",
+        Fault::ClearFailed => "ALARM CLR FAILED",
+        Fault::EncoderSlip => "ENC ERR",
+        Fault::StepMismatch => "STEP MISMATCH",
+    };
+    write!(display.at(0, 0), "{:^w$}", name, w = D::COLS).ok();
+    // Four line displays have room to show the lost travel all the time,
+    // rather than alternating it with the instructions.
+    if D::ROWS >= 4 {
+        write!(display.at(0, 2), "Lost {:>+7}μm", lost).ok();
+    }
+    if clearing {
+        write!(display.at(0, 1), "{:^w$}", "CLEARING ALARM", w = D::COLS).ok();
+    } else if D::ROWS < 4 && (now_ms / 1000) % 2 == 0 {
+        write!(display.at(0, 1), "Lost {:>+7}μm  ", lost).ok();
+    } else {
+        write!(display.at(0, 1), "{:^w$}", "BUTTON TO CLEAR", w = D::COLS).ok();
+    }
+}
+
+fn onoff(v: bool) -> char {
+    if v {
+        '●'
+    } else {
+        '○'
+    }
+}
+
+// Debug display.
+fn display_debug<D>(display: &mut D, view: &View, debug: &Debug)
+where
+    D: CharacterDisplay + Write,
+{
+    let timing = debug.timing;
+    let index = debug.index;
+    match debug.page {
+        DebugPage::Help => {
+            write!(display.at(0, 0), "{:<16}", "Debug0: help").ok();
+            write!(display.at(0, 1), "{:<16}", "↑↓ w/ mode dial").ok();
+        }
+        DebugPage::Status => {
+            write!(display.at(0, 0), "{:<16}", "Debug1: Status").ok();
+            display_rpm(display, view);
+        }
+        DebugPage::UIControls => {
+            write!(display.at(0, 0), "{:<16}", "Debug2: UI input").ok();
+            write!(
+                display.at(0, 1),
+                "{}{}{:<+6} {}{:<+6}",
+                onoff(debug.button1),
+                onoff(debug.mode_enc_button),
+                debug.mode_enc_pos,
+                onoff(debug.feed_enc_button),
+                debug.feed_enc_pos
+            )
+            .ok();
+        }
+        DebugPage::Motor => {
+            write!(display.at(0, 0), "{:<16}", "Debug3: motor").ok();
+            write!(
+                display.at(0, 1),
+                "{:>3} {:>3} {}{:>7}",
+                if debug.servo_ok { "OK" } else { "ERR" },
+                if view.motor_enable { "EN" } else { "DIS" },
+                if view.motor_direction { "+" } else { "-" },
+                debug.motor_pulses,
+            )
+            .ok();
+        }
+        DebugPage::Spindle => {
+            write!(display.at(0, 0), "{:<16}", "Debug4: spindle").ok();
+            write!(
+                display.at(0, 1),
+                "{:>08x} {:>+7}",
+                debug.spindle_enc_pos as u32,
+                debug.spindle_enc_delta,
+            )
+            .ok();
+        }
+        DebugPage::Control => {
+            write!(display.at(0, 0), "{:<16}", "Debug5: control").ok();
+            write!(
+                display.at(0, 1),
+                "F{:>+5} R{:>+8}",
+                debug.feed,
+                debug.fractional_pulses,
+            )
+            .ok();
+        }
+        DebugPage::Time => {
+            write!(display.at(0, 0), "{:<16}", "Debug6: time").ok();
+            write!(
+                display.at(0, 1),
+                "N{:>8x} L{:>5}",
+                view.now_ms,
+                debug.since_update,
+            )
+            .ok();
+        }
+        DebugPage::LoopTime => {
+            write!(display.at(0, 0), "{:<16}", "Debug7: loop μs").ok();
+            write!(
+                display.at(0, 1),
+                "{:>2}-{:<3}P{:<3}{:>5}",
+                timing.min_us.min(99),
+                timing.max_us.min(999),
+                timing.pulse_max_us.min(999),
+                if timing.slow_count > 0 { "SLOW" } else { "OK" },
+            )
+            .ok();
+        }
+        DebugPage::LoopHistogram => {
+            // Each digit is the number of decimal digits in a bucket's
+            // count, so the whole range fits on one line.
+            write!(display.at(0, 0), "{:<16}", "Debug8: μs hist").ok();
+            let mut line = [b' '; 16];
+            for (c, count) in line.iter_mut().zip(timing.histogram) {
+                *c = b'0' + (count.checked_ilog10().map_or(0, |d| d + 1)).min(9) as u8;
+            }
+            let line =
+                core::str::from_utf8(&line[..timing::HISTOGRAM_BUCKETS.min(12)]).unwrap_or("");
+            write!(
+                display.at(0, 1),
+                "{:<12}{:>2}μs",
+                line,
+                timing::HISTOGRAM_BUCKET_US
+            )
+            .ok();
+        }
+        DebugPage::EncoderIndex => {
+            // Index intervals checked, errors, and the last error in encoder
+            // counts.
+            write!(display.at(0, 0), "{:<16}", "Debug9: enc idx").ok();
+            write!(
+                display.at(0, 1),
+                "N{:<6}E{:<3} {:>+4}",
+                index.checked.min(999_999),
+                index.errors.min(999),
+                index.last_error.clamp(-999, 999),
+            )
+            .ok();
+        }
+        DebugPage::EncoderRaw => {
+            // Spindle encoder count decoded from the raw signals, and the
+            // filtered hardware count, with the number of raw samples that
+            // couldn't be decoded.
+            write!(
+                display.at(0, 0),
+                "Debug10:raw{:>5}",
+                debug.spindle_raw_invalid.min(99_999)
+            )
+            .ok();
+            write!(
+                display.at(0, 1),
+                "R{:>+7}F{:>+7}",
+                debug.spindle_raw_pos % 1_000_000,
+                debug.spindle_enc_pos % 1_000_000,
+            )
+            .ok();
+        }
+        DebugPage::Load => {
+            // Longest control interrupt as a proportion of the time
+            // available for it.
+            write!(display.at(0, 0), "{:<16}", "Debug11: load").ok();
+            display.at(0, 1);
+            widgets::bar(display, D::COLS as u8 - 4, timing.max_us, timing::BUDGET_US);
+            write!(
+                display,
+                "{:>3}%",
+                (timing.max_us * 100 / timing::BUDGET_US).min(999)
+            )
+            .ok();
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! Control interrupt timing statistics.
+use cortex_m::peripheral::DWT;
+
+// Time available for each control interrupt.
+pub const BUDGET_US: u32 = 1_000_000 / crate::CONTROL_RATE;
+// Pulse output taking longer than this is a warning that the control loop
+// is close to not keeping up.
+pub const PULSE_WARNING_US: u32 = BUDGET_US * 3 / 4;
+pub const HISTOGRAM_BUCKET_US: u32 = 5;
+// The last bucket counts interrupts that overran the budget.
+pub const HISTOGRAM_BUCKETS: usize = (BUDGET_US / HISTOGRAM_BUCKET_US) as usize + 1;
+
+#[derive(Clone, Copy)]
+pub struct Stats {
+    pub min_us: u32,
+    pub max_us: u32,
+    pub pulse_max_us: u32,
+    // Interrupts where pulse output exceeded PULSE_WARNING_US.
+    pub slow_count: u32,
+    pub histogram: [u32; HISTOGRAM_BUCKETS],
+}
+
+impl Stats {
+    pub const fn new() -> Self {
+        Stats {
+            min_us: u32::MAX,
+            max_us: 0,
+            pulse_max_us: 0,
+            slow_count: 0,
+            histogram: [0; HISTOGRAM_BUCKETS],
+        }
+    }
+
+    pub fn get_overruns(&self) -> u32 {
+        self.histogram[HISTOGRAM_BUCKETS - 1]
+    }
+}
+
+// Measures control interrupt and pulse output times with the DWT cycle
+// counter.
+pub struct LoopTimer {
+    cycles_per_us: u32,
+    start: u32,
+    pulse_start: u32,
+    pulse_us: u32,
+    stats: Stats,
+}
+
+impl LoopTimer {
+    pub fn new(cycles_per_us: u32) -> Self {
+        LoopTimer {
+            cycles_per_us,
+            start: 0,
+            pulse_start: 0,
+            pulse_us: 0,
+            stats: Stats::new(),
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.start = DWT::cycle_count();
+        self.pulse_us = 0;
+    }
+
+    pub fn start_pulse(&mut self) {
+        self.pulse_start = DWT::cycle_count();
+    }
+    pub fn end_pulse(&mut self) {
+        self.pulse_us = DWT::cycle_count().wrapping_sub(self.pulse_start) / self.cycles_per_us;
+    }
+
+    // Record the interrupt time, returning the statistics so far.
+    pub fn end(&mut self) -> &Stats {
+        let us = DWT::cycle_count().wrapping_sub(self.start) / self.cycles_per_us;
+        let s = &mut self.stats;
+        s.min_us = s.min_us.min(us);
+        s.max_us = s.max_us.max(us);
+        s.pulse_max_us = s.pulse_max_us.max(self.pulse_us);
+        if self.pulse_us >= PULSE_WARNING_US {
+            s.slow_count = s.slow_count.saturating_add(1);
+        }
+        let bucket = ((us / HISTOGRAM_BUCKET_US) as usize).min(HISTOGRAM_BUCKETS - 1);
+        s.histogram[bucket] = s.histogram[bucket].saturating_add(1);
+        &self.stats
+    }
+
+    pub fn reset(&mut self) {
+        self.stats = Stats::new();
+    }
+}
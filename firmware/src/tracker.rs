@@ -0,0 +1,61 @@
+//! Spindle phase tracker.
+//!
+//! An alpha-beta observer over the sampled spindle encoder count, in the
+//! style of a second order PLL. It estimates spindle position and velocity
+//! between encoder pulses, so the position can be extrapolated to the time
+//! that steps are actually emitted rather than when the encoder was
+//! sampled. Positions are encoder pulses in 16.16 fixed point, velocities
+//! are per control interrupt period.
+
+pub const FRAC_BITS: u32 = 16;
+// Observer gains, as shifts: alpha = 1/4, beta = 1/32 (roughly critically
+// damped, beta = alpha^2 / (2 - alpha)).
+const ALPHA_SHIFT: u32 = 2;
+const BETA_SHIFT: u32 = 5;
+// Extrapolate by this many control interrupt periods (16.16). Sampling
+// adds half a period of delay on average, and steps are emitted over the
+// following part of the period.
+const LEAD: i64 = 3 << (FRAC_BITS - 2);
+// If the estimate is this far off (encoder pulses) it's reset to the
+// measurement rather than slewing towards it.
+const RELOCK_THRESHOLD: i64 = 64 << FRAC_BITS;
+
+pub struct Tracker {
+    count: i64,
+    measured: i64,
+    position: i64,
+    velocity: i64,
+    output: i64,
+}
+
+impl Tracker {
+    pub fn new(count: i64) -> Self {
+        Tracker {
+            count,
+            measured: 0,
+            position: 0,
+            velocity: 0,
+            output: 0,
+        }
+    }
+
+    // Add an encoder sample, returning the change in extrapolated spindle
+    // position since the last sample (encoder pulses, 16.16).
+    pub fn update(&mut self, count: i64) -> i64 {
+        self.measured += (count - self.count) << FRAC_BITS;
+        self.count = count;
+        self.position += self.velocity;
+        let residual = self.measured - self.position;
+        if residual.abs() > RELOCK_THRESHOLD {
+            self.position = self.measured;
+            self.velocity = 0;
+        } else {
+            self.position += residual >> ALPHA_SHIFT;
+            self.velocity += residual >> BETA_SHIFT;
+        }
+        let output = self.position + ((self.velocity * LEAD) >> FRAC_BITS);
+        let delta = output - self.output;
+        self.output = output;
+        delta
+    }
+}
@@ -1,10 +1,18 @@
 //! User interface code
-use crate::control::{Control, Direction};
+use crate::control::Control;
+use crate::index;
+use crate::input::{Button, Event, Input, Knob};
 use crate::lcd;
+use crate::servo::{Servo, State};
+use crate::timing;
+use crate::view::{self, Renderer, Screen, View};
 
 const WELCOME_MESSAGE_TIMEOUT: i64 = 2500; // ms.
+const BOOT_MESSAGE_TIMEOUT: i64 = 5000; // ms.
 const WARN_MESSAGE_TIMEOUT: i64 = 500; // ms.
-const BUTTON_HOLD_DEBUG_TIME: i64 = 1000; // ms.
+const SLOW_WARNING_TIME: i64 = 2000; // ms.
+const ENCODER_ERROR_WARNING_TIME: i64 = 5000; // ms.
+const IDLE_BRIGHTNESS: u8 = 25; // %.
 
 #[derive(Clone, Copy, PartialEq)]
 pub enum Mode {
@@ -24,10 +32,28 @@ impl Mode {
             _ => panic!(),
         }
     }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::ServoOff => "off",
+            Mode::Feed => "feed",
+            Mode::ThreadMetric => "thread",
+            Mode::ThreadImperial => "thread-imperial",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<Mode> {
+        [
+            Mode::ServoOff,
+            Mode::Feed,
+            Mode::ThreadMetric,
+            Mode::ThreadImperial,
+        ]
+        .into_iter()
+        .find(|mode| mode.name() == name)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
-enum DebugPage {
+pub enum DebugPage {
     Help = 0,
     Status,
     UIControls,
@@ -35,11 +61,16 @@ enum DebugPage {
     Spindle,
     Control,
     Time,
+    LoopTime,
+    LoopHistogram,
+    EncoderIndex,
+    EncoderRaw,
+    Load,
 }
 
 impl DebugPage {
     pub fn add(&self, n: i16) -> DebugPage {
-        match ((*self as i16) + n).clamp(0, 6) {
+        match ((*self as i16) + n).clamp(0, 11) {
             0 => DebugPage::Help,
             1 => DebugPage::Status,
             2 => DebugPage::UIControls,
@@ -47,13 +78,18 @@ impl DebugPage {
             4 => DebugPage::Spindle,
             5 => DebugPage::Control,
             6 => DebugPage::Time,
+            7 => DebugPage::LoopTime,
+            8 => DebugPage::LoopHistogram,
+            9 => DebugPage::EncoderIndex,
+            10 => DebugPage::EncoderRaw,
+            11 => DebugPage::Load,
             _ => panic!(),
         }
     }
 }
 
-pub struct UI<'a, DISPLAY> {
-    display: &'a mut DISPLAY,
+pub struct UI<'a, R> {
+    renderer: &'a mut R,
     mode: Mode,
     last_update_ms: i64,
     debug_mode: bool,
@@ -61,19 +97,28 @@ pub struct UI<'a, DISPLAY> {
     message1: &'a str,
     message2: &'a str,
     message_timeout: i64,
-    mode_enc_pos_last: i16,
-    feed_enc_pos_last: i16,
     feed_rate_index: usize,
     metric_thread_pitch_index: usize,
     imperial_thread_pitch_index: usize,
-    debug_hold: i64,
-    spindle_enc_last: i32,
+    spindle_enc_last: i64,
+    slow_count_last: u32,
+    slow_warning_until: i64,
+    index_errors_last: u32,
+    encoder_error_until: i64,
+    boot_message: Option<(&'a str, &'a str)>,
     cold: bool,
+    idle_since: i64,
+    dimmed: bool,
+    brightness: Option<u8>,
+    // The first error the display reported, kept for the console.
+    display_error: Option<lcd::Error>,
+    // Carriage stop and the position it was set at, in μm.
+    stop: Option<(i64, i64)>,
 }
 
-impl<'a, DISPLAY> UI<'a, DISPLAY>
+impl<'a, R> UI<'a, R>
 where
-    DISPLAY: lcd::CharacterDisplay + core::fmt::Write,
+    R: Renderer,
 {
     const FEED_RATES: [i32; 58] = [
         0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 15, 20, 25, 30, 35, 40, 45, 50, 55, 60, 65, 70, 75, 80,
@@ -90,9 +135,9 @@ where
         80, 72, 64, 56, 48, 40, 32, 28, 24, 20, 18, 16, 14, 13, 12, 11, 10, 9, 8, 7, 6,
     ];
     const DEFAULT_IMPERIAL_THREAD_PITCH: usize = 9;
-    pub fn new(display: &'a mut DISPLAY) -> UI<'a, DISPLAY> {
+    pub fn new(renderer: &'a mut R) -> UI<'a, R> {
         UI {
-            display,
+            renderer,
             mode: Mode::ServoOff,
             last_update_ms: 0,
             debug_mode: false,
@@ -100,167 +145,377 @@ where
             message1: "",
             message2: "",
             message_timeout: -1,
-            mode_enc_pos_last: 0,
-            feed_enc_pos_last: 0,
             feed_rate_index: Self::DEFAULT_FEED_RATE_INDEX,
             metric_thread_pitch_index: Self::DEFAULT_METRIC_THREAD_PITCH,
             imperial_thread_pitch_index: Self::DEFAULT_IMPERIAL_THREAD_PITCH,
-            debug_hold: 0,
             spindle_enc_last: 0,
+            slow_count_last: 0,
+            slow_warning_until: 0,
+            index_errors_last: 0,
+            encoder_error_until: 0,
+            boot_message: None,
             cold: true,
+            idle_since: 0,
+            dimmed: false,
+            brightness: None,
+            display_error: None,
+            stop: None,
         }
     }
+    // Show a message instead of the welcome banner at startup.
+    pub fn set_boot_message(&mut self, message1: &'a str, message2: &'a str) {
+        self.boot_message = Some((message1, message2));
+    }
     #[allow(clippy::too_many_arguments)]
     pub fn update(
         &mut self,
         control: &mut Control,
+        servo: &mut Servo,
         now_ms: i64,
         rpm: i32,
         servo_ok: bool,
-        button1: bool,
-        mode_enc_pos: i16,
-        mode_enc_button: bool,
-        feed_enc_pos: i16,
-        feed_enc_button: bool,
-        spindle_enc_pos: i32,
+        input: &mut Input,
+        spindle_enc_pos: i64,
+        spindle_raw_pos: i64,
+        spindle_raw_invalid: u32,
         motor_pulses: u32,
+        motor_position: i64,
         motor_enable: bool,
         motor_direction: bool,
+        fractional_pulses: i64,
+        timing: &timing::Stats,
+        index: &index::Stats,
     ) {
         if self.cold {
             self.last_update_ms = now_ms;
-            self.mode_enc_pos_last = mode_enc_pos;
-            self.feed_enc_pos_last = feed_enc_pos;
+            self.idle_since = now_ms;
             control.set_feed_rate_micron_per_rev(Self::FEED_RATES[self.feed_rate_index]);
-            self.message1 = "TU-2506V-ELS";
-            self.message2 = "djm 20241117";
-            self.message_timeout = now_ms + WELCOME_MESSAGE_TIMEOUT;
+            if let Some((message1, message2)) = self.boot_message {
+                self.message1 = message1;
+                self.message2 = message2;
+                self.message_timeout = now_ms + BOOT_MESSAGE_TIMEOUT;
+            } else {
+                self.message1 = "TU-2506V-ELS";
+                self.message2 = "djm 20241117";
+                self.message_timeout = now_ms + WELCOME_MESSAGE_TIMEOUT;
+            }
             self.cold = false;
         }
-        let mode_enc_pulses: i16 =
-            (mode_enc_pos - self.mode_enc_pos_last) / crate::UI_ENCODER_PULSE_PER_DETENT as i16;
-        let feed_enc_pulses: i16 =
-            (feed_enc_pos - self.feed_enc_pos_last) / crate::UI_ENCODER_PULSE_PER_DETENT as i16;
-        self.mode_enc_pos_last = mode_enc_pos;
-        self.feed_enc_pos_last = feed_enc_pos;
-        let spindle_moving = rpm > 3;
+        let spindle_moving = Self::spindle_moving(rpm);
+        let carriage_position = control.pulses_to_micron(motor_position);
 
-        let mut status: &str = "OK";
-        if self.mode == Mode::ServoOff {
-            status = "OFF";
-        } else if !servo_ok {
-            status = "!SERVO";
-        }
-
-        // Hold mode+feed encoder buttons down to toggle debug display.
-        // While button is held, ignore rotation of the encoder.
-        if mode_enc_button && feed_enc_button {
-            if self.debug_hold == 0 {
-                // Just started pressing mode enc button for debug mode.
-                // Start timeout.
-                self.debug_hold = now_ms + BUTTON_HOLD_DEBUG_TIME;
-            } else if self.debug_hold < now_ms {
-                // Held for long enough; enter/exit debug display.
-                self.debug_mode = !self.debug_mode;
-                self.debug_page = DebugPage::Help;
-                self.debug_hold = 0;
+        // Dim the display after a while idle with the servo off and the
+        // spindle stopped, to save the VFD's filament. Any knob or button
+        // input wakes it, and turning a knob to do so does nothing else.
+        let mut active = input.any_down();
+        while let Some(event) = input.next_event() {
+            active = true;
+            if self.dimmed && matches!(event, Event::Turn(..) | Event::PressedTurn(..)) {
+                continue;
             }
+            self.handle(event, control, servo, now_ms, spindle_moving);
+        }
+        if active || self.mode != Mode::ServoOff || spindle_moving || servo.get_fault().is_some() {
+            self.idle_since = now_ms;
+        }
+        let config = control.get_config();
+        let dim_timeout = config.display_dim_timeout * 1000;
+        self.dimmed = dim_timeout > 0 && now_ms - self.idle_since >= dim_timeout;
+        let brightness = if self.dimmed {
+            IDLE_BRIGHTNESS.min(config.display_brightness as u8)
         } else {
-            self.debug_hold = 0;
+            config.display_brightness as u8
+        };
+        if self.brightness != Some(brightness) {
+            self.renderer.brightness(brightness);
+            self.brightness = Some(brightness);
         }
 
-        if mode_enc_pulses != 0 && self.debug_hold == 0 {
-            if self.debug_mode {
-                self.debug_page = self.debug_page.add(mode_enc_pulses);
-            } else if spindle_moving {
-                // Don't allow mode changes while the spindle is running.
-                self.message1 = "STOP SPINDLE";
-                self.message2 = "TO CHANGE MODE";
-                self.message_timeout = now_ms + WARN_MESSAGE_TIMEOUT;
-            } else if !mode_enc_button {
-                // Require button to be pressed too.
-                self.message1 = "PRESS KNOB TO";
-                self.message2 = "CHANGE MODE";
-                self.message_timeout = now_ms + WARN_MESSAGE_TIMEOUT;
-            }
+        // Pulse output came close to overrunning the control interrupt.
+        if timing.slow_count != self.slow_count_last {
+            self.slow_count_last = timing.slow_count;
+            self.slow_warning_until = now_ms + SLOW_WARNING_TIME;
+        }
+        // Spindle encoder counts slipped between index pulses.
+        if index.errors != self.index_errors_last {
+            self.index_errors_last = index.errors;
+            self.encoder_error_until = now_ms + ENCODER_ERROR_WARNING_TIME;
         }
 
-        // Special handling for threading modes: don't allow pitch changes
-        // while spindle is moving. Also require button be pressed.
-        if !self.debug_mode && feed_enc_pulses != 0 && self.debug_hold == 0 {
-            match self.mode {
-                Mode::ThreadMetric | Mode::ThreadImperial => {
-                    if spindle_moving {
-                        self.message1 = "STOP SPINDLE TO";
-                        self.message2 = "CHANGE PITCH";
-                        self.message_timeout = now_ms + WARN_MESSAGE_TIMEOUT;
-                    } else if !feed_enc_button {
-                        self.message1 = "PRESS KNOB TO";
-                        self.message2 = "CHANGE PITCH";
-                        self.message_timeout = now_ms + WARN_MESSAGE_TIMEOUT;
-                    }
-                }
-                _ => (),
-            }
+        let mut status: &str = "OK";
+        if servo.get_fault().is_some() {
+            status = "FAULT";
+        } else if self.mode == Mode::ServoOff {
+            status = "OFF";
+        } else if !servo_ok {
+            status = "!SERVO";
+        } else if self.encoder_error_until > now_ms {
+            status = "ENC ERR";
+        } else if self.slow_warning_until > now_ms {
+            status = "SLOW";
         }
 
+        let mut view = View {
+            now_ms,
+            rpm,
+            status,
+            carriage_position,
+            motor_enable,
+            motor_direction,
+            screen: Screen::Message(self.message1, self.message2),
+        };
+
         // If there's an active warning message then display that.
         if self.message_timeout > now_ms {
-            write!(self.display.at(0, 0), "{:^16}", self.message1).ok();
-            write!(self.display.at(0, 1), "{:^16}", self.message2).ok();
+            self.render(&view);
             return;
         }
 
-        // If spindle stopped and mode wheel moved, then change mode.
-        let mut mode_changed: bool = false;
-        if !self.debug_mode && self.debug_hold == 0 && mode_enc_pulses != 0 {
-            let new_mode: Mode = self.mode.add(mode_enc_pulses.into());
-            mode_changed = new_mode != self.mode;
-            self.mode = new_mode;
-        }
-
-        // If feed changed, update (unless in debug mode).
-        if !self.debug_mode && self.debug_hold == 0 && (mode_changed || feed_enc_pulses != 0) {
-            match self.mode {
-                Mode::Feed => self.update_feed(control, feed_enc_pulses),
-                Mode::ThreadMetric => self.update_thread_metric(control, feed_enc_pulses),
-                Mode::ThreadImperial => self.update_thread_imperial(control, feed_enc_pulses),
-                Mode::ServoOff => (),
-            }
-        }
-
         // Update display.
-        if self.debug_mode {
-            self.display_debug(
-                control,
-                now_ms,
-                rpm,
-                status,
-                mode_enc_pos,
-                mode_enc_button,
-                feed_enc_pos,
-                feed_enc_button,
-                button1,
+        view.screen = if self.debug_mode {
+            let mut feed = control.get_feed_rate_micron_per_rev();
+            if !motor_direction {
+                feed = -feed;
+            }
+            Screen::Debug(view::Debug {
+                page: self.debug_page,
+                button1: input.is_down(Button::Button1),
+                mode_enc_pos: input.get_position(Knob::Mode),
+                mode_enc_button: input.is_down(Button::ModeKnob),
+                feed_enc_pos: input.get_position(Knob::Feed),
+                feed_enc_button: input.is_down(Button::FeedKnob),
                 spindle_enc_pos,
+                spindle_enc_delta: spindle_enc_pos - self.spindle_enc_last,
+                spindle_raw_pos,
+                spindle_raw_invalid,
                 motor_pulses,
-                motor_enable,
-                motor_direction,
+                feed,
+                fractional_pulses,
                 servo_ok,
-            );
-            self.spindle_enc_last = spindle_enc_pos;
+                since_update: now_ms - self.last_update_ms,
+                timing,
+                index,
+            })
+        } else if let Some(fault) = servo.get_fault() {
+            Screen::Fault {
+                fault,
+                lost: control.pulses_to_micron(servo.get_lost_pulses()),
+                clearing: matches!(
+                    servo.get_state(),
+                    State::ClearLow { .. } | State::ClearHigh { .. }
+                ),
+            }
         } else {
-            match self.mode {
-                Mode::ServoOff => self.display_servo_off(rpm, status),
-                Mode::Feed => self.display_feed(rpm, status),
-                Mode::ThreadMetric => self.display_thread_metric(rpm, status),
-                Mode::ThreadImperial => self.display_thread_imperial(rpm, status),
+            Screen::Mode {
+                mode: self.mode,
+                setting: self.get_setting(),
+                stop: self.stop.map(|(stop, from)| view::Stop {
+                    travelled: (carriage_position - from) * (stop - from).signum(),
+                    distance: (stop - from).abs(),
+                }),
             }
+        };
+        self.render(&view);
+        if self.debug_mode {
+            self.spindle_enc_last = spindle_enc_pos;
         }
         self.last_update_ms = now_ms;
     }
+    // Act on a knob or button event.
+    fn handle(
+        &mut self,
+        event: Event,
+        control: &mut Control,
+        servo: &mut Servo,
+        now_ms: i64,
+        spindle_moving: bool,
+    ) {
+        // Changes are ignored while a warning is showing.
+        let warning = self.message_timeout > now_ms;
+        match event {
+            // Button 1 acknowledges a latched servo fault.
+            Event::Press(Button::Button1) => servo.acknowledge(now_ms),
+            // Hold both knobs' buttons down to toggle the debug display.
+            Event::Chord(Button::ModeKnob, Button::FeedKnob)
+            | Event::Chord(Button::FeedKnob, Button::ModeKnob) => {
+                self.debug_mode = !self.debug_mode;
+                self.debug_page = DebugPage::Help;
+            }
+            Event::Turn(Knob::Mode, detents) | Event::PressedTurn(Knob::Mode, detents)
+                if self.debug_mode =>
+            {
+                self.debug_page = self.debug_page.add(detents as i16);
+            }
+            // Don't allow mode changes while the spindle is running, and
+            // require the knob to be pressed too.
+            Event::Turn(Knob::Mode, _) | Event::PressedTurn(Knob::Mode, _) if spindle_moving => {
+                self.warn("STOP SPINDLE", "TO CHANGE MODE", now_ms);
+            }
+            Event::Turn(Knob::Mode, _) => self.warn("PRESS KNOB TO", "CHANGE MODE", now_ms),
+            Event::PressedTurn(Knob::Mode, detents) if !warning => {
+                let new_mode = self.mode.add(detents);
+                if new_mode != self.mode {
+                    self.mode = new_mode;
+                    self.apply_mode(control);
+                }
+            }
+            Event::Turn(Knob::Feed, detents) | Event::PressedTurn(Knob::Feed, detents)
+                if !self.debug_mode =>
+            {
+                let pressed = matches!(event, Event::PressedTurn(..));
+                let detents = detents as i16;
+                match self.mode {
+                    // Special handling for threading modes: don't allow
+                    // pitch changes while the spindle is moving, and
+                    // require the knob to be pressed.
+                    Mode::ThreadMetric | Mode::ThreadImperial if spindle_moving => {
+                        self.warn("STOP SPINDLE TO", "CHANGE PITCH", now_ms);
+                    }
+                    Mode::ThreadMetric | Mode::ThreadImperial if !pressed => {
+                        self.warn("PRESS KNOB TO", "CHANGE PITCH", now_ms);
+                    }
+                    _ if warning => (),
+                    Mode::Feed => self.update_feed(control, detents),
+                    Mode::ThreadMetric => self.update_thread_metric(control, detents),
+                    Mode::ThreadImperial => self.update_thread_imperial(control, detents),
+                    Mode::ServoOff => (),
+                }
+            }
+            _ => (),
+        }
+    }
+    // Show a warning briefly.
+    fn warn(&mut self, message1: &'a str, message2: &'a str, now_ms: i64) {
+        self.message1 = message1;
+        self.message2 = message2;
+        self.message_timeout = now_ms + WARN_MESSAGE_TIMEOUT;
+    }
+    fn render(&mut self, view: &View) {
+        if let Err(e) = self.renderer.render(view) {
+            self.display_error.get_or_insert(e);
+        }
+    }
+    pub fn get_display_error(&self) -> Option<lcd::Error> {
+        self.display_error
+    }
+    // Set or clear the carriage stop on behalf of a remote command. The
+    // bar shows the travel towards it from where the carriage is now. The
+    // carriage isn't stopped there.
+    pub fn set_stop(&mut self, stop: Option<i64>, carriage_position: i64) {
+        self.stop = stop.map(|stop| (stop, carriage_position));
+    }
+    pub fn get_stop(&self) -> Option<i64> {
+        self.stop.map(|(stop, _)| stop)
+    }
     pub fn get_mode(&self) -> Mode {
         self.mode
     }
+    pub fn get_feed_rate(&self) -> i32 {
+        Self::FEED_RATES[self.feed_rate_index]
+    }
+    pub fn get_metric_thread_pitch(&self) -> i32 {
+        Self::METRIC_THREAD_PITCHES[self.metric_thread_pitch_index]
+    }
+    pub fn get_imperial_thread_pitch(&self) -> i32 {
+        Self::IMPERIAL_THREAD_PITCHES[self.imperial_thread_pitch_index]
+    }
+    // The current mode's feed rate or pitch.
+    fn get_setting(&self) -> i32 {
+        match self.mode {
+            Mode::ServoOff => 0,
+            Mode::Feed => self.get_feed_rate(),
+            Mode::ThreadMetric => self.get_metric_thread_pitch(),
+            Mode::ThreadImperial => self.get_imperial_thread_pitch(),
+        }
+    }
+
+    fn spindle_moving(rpm: i32) -> bool {
+        rpm > 3
+    }
+
+    // Change mode on behalf of a remote command, following the same rules
+    // as the mode knob.
+    pub fn set_mode(
+        &mut self,
+        control: &mut Control,
+        mode: Mode,
+        rpm: i32,
+    ) -> Result<(), &'static str> {
+        if mode == self.mode {
+            return Ok(());
+        }
+        if Self::spindle_moving(rpm) {
+            return Err("stop spindle to change mode");
+        }
+        self.mode = mode;
+        self.apply_mode(control);
+        Ok(())
+    }
+
+    // Set feed rate on behalf of a remote command. The rate must be one
+    // that can be selected with the feed knob.
+    pub fn set_feed_rate(&mut self, control: &mut Control, feed: i32) -> Result<(), &'static str> {
+        self.feed_rate_index = Self::FEED_RATES
+            .iter()
+            .position(|&f| f == feed)
+            .ok_or("unsupported feed rate")?;
+        if self.mode == Mode::Feed {
+            self.apply_mode(control);
+        }
+        Ok(())
+    }
+
+    // Set metric thread pitch on behalf of a remote command.
+    pub fn set_metric_thread_pitch(
+        &mut self,
+        control: &mut Control,
+        pitch: i32,
+        rpm: i32,
+    ) -> Result<(), &'static str> {
+        let index = Self::METRIC_THREAD_PITCHES
+            .iter()
+            .position(|&p| p == pitch)
+            .ok_or("unsupported pitch")?;
+        if self.mode == Mode::ThreadMetric && Self::spindle_moving(rpm) {
+            return Err("stop spindle to change pitch");
+        }
+        self.metric_thread_pitch_index = index;
+        if self.mode == Mode::ThreadMetric {
+            self.apply_mode(control);
+        }
+        Ok(())
+    }
+
+    // Set imperial thread pitch on behalf of a remote command.
+    pub fn set_imperial_thread_pitch(
+        &mut self,
+        control: &mut Control,
+        tpi: i32,
+        rpm: i32,
+    ) -> Result<(), &'static str> {
+        let index = Self::IMPERIAL_THREAD_PITCHES
+            .iter()
+            .position(|&p| p == tpi)
+            .ok_or("unsupported pitch")?;
+        if self.mode == Mode::ThreadImperial && Self::spindle_moving(rpm) {
+            return Err("stop spindle to change pitch");
+        }
+        self.imperial_thread_pitch_index = index;
+        if self.mode == Mode::ThreadImperial {
+            self.apply_mode(control);
+        }
+        Ok(())
+    }
+
+    // Program the controller with the parameters for the current mode.
+    fn apply_mode(&mut self, control: &mut Control) {
+        match self.mode {
+            Mode::Feed => self.update_feed(control, 0),
+            Mode::ThreadMetric => self.update_thread_metric(control, 0),
+            Mode::ThreadImperial => self.update_thread_imperial(control, 0),
+            Mode::ServoOff => (),
+        }
+    }
 
     // Update feed mode parameters based on user input.
     fn update_feed(&mut self, control: &mut Control, feed_enc_pulses: i16) {
@@ -287,139 +542,4 @@ where
         let tpi = Self::IMPERIAL_THREAD_PITCHES[self.imperial_thread_pitch_index];
         control.set_feed_rate_tpi(tpi);
     }
-
-    // Display for servo off mode.
-    fn display_servo_off(&mut self, rpm: i32, status: &str) {
-        write!(self.display.at(0, 0), "{:<16}", "Servo off").ok();
-        write!(self.display.at(0, 1), "RPM {:<+5} {:>6}", rpm, status).ok();
-    }
-
-    // Display for feed mode.
-    fn display_feed(&mut self, rpm: i32, status: &str) {
-        write!(
-            self.display.at(0, 0),
-            "Feed {:>+7}μm/r",
-            Self::FEED_RATES[self.feed_rate_index]
-        )
-        .ok();
-        write!(self.display.at(0, 1), "RPM {:<+5} {:>6}", rpm, status).ok();
-    }
-
-    // Display for metric thread mode.
-    fn display_thread_metric(&mut self, rpm: i32, status: &str) {
-        let pitch = Self::METRIC_THREAD_PITCHES[self.metric_thread_pitch_index];
-        let whole = pitch / 1000;
-        let frac = (pitch.abs() / 10) % 100;
-        write!(self.display.at(0, 0), "Thread{:>+3}.{:02}mm/r", whole, frac).ok();
-        write!(self.display.at(0, 1), "RPM {:<+5} {:>6}", rpm, status).ok();
-    }
-
-    // Display for imperial thread mode.
-    fn display_thread_imperial(&mut self, rpm: i32, status: &str) {
-        let tpi = Self::IMPERIAL_THREAD_PITCHES[self.imperial_thread_pitch_index];
-        write!(self.display.at(0, 0), "Thread Im {:>+3}TPI", tpi).ok();
-        write!(self.display.at(0, 1), "RPM {:<+5} {:>6}", rpm, status).ok();
-    }
-
-    fn onoff(v: bool) -> char {
-        if v {
-            '●'
-        } else {
-            '○'
-        }
-    }
-
-    // Debug display.
-    #[allow(clippy::too_many_arguments)]
-    fn display_debug(
-        &mut self,
-        control: &mut Control,
-        now_ms: i64,
-        rpm: i32,
-        status: &str,
-        mode_enc_pos: i16,
-        mode_enc_button: bool,
-        feed_enc_pos: i16,
-        feed_enc_button: bool,
-        button1: bool,
-        spindle_encoder_pos: i32,
-        motor_pulses: u32,
-        motor_enable: bool,
-        motor_direction: bool,
-        servo_ok: bool,
-    ) {
-        match self.debug_page {
-            DebugPage::Help => {
-                write!(self.display.at(0, 0), "{:<16}", "Debug0: help").ok();
-                write!(self.display.at(0, 1), "{:<16}", "↑↓ w/ mode dial").ok();
-            }
-            DebugPage::Status => {
-                write!(self.display.at(0, 0), "{:<16}", "Debug1: Status").ok();
-                write!(self.display.at(0, 1), "RPM {:<+5} {:>6}", rpm, status).ok();
-            }
-            DebugPage::UIControls => {
-                write!(self.display.at(0, 0), "{:<16}", "Debug2: UI input").ok();
-                write!(
-                    self.display.at(0, 1),
-                    "{}{}{:<+6} {}{:<+6}",
-                    Self::onoff(button1),
-                    Self::onoff(mode_enc_button),
-                    mode_enc_pos,
-                    Self::onoff(feed_enc_button),
-                    feed_enc_pos
-                )
-                .ok();
-            }
-            DebugPage::Motor => {
-                write!(self.display.at(0, 0), "{:<16}", "Debug3: motor").ok();
-                write!(
-                    self.display.at(0, 1),
-                    "{:>3} {:>3} {}{:>7}",
-                    if servo_ok { "OK" } else { "ERR" },
-                    if motor_enable { "EN" } else { "DIS" },
-                    if motor_direction { "+" } else { "-" },
-                    motor_pulses,
-                )
-                .ok();
-            }
-            DebugPage::Spindle => {
-                write!(self.display.at(0, 0), "{:<16}", "Debug4: spindle").ok();
-                write!(
-                    self.display.at(0, 1),
-                    "{:>08x} {:>+7}",
-                    spindle_encoder_pos,
-                    spindle_encoder_pos - self.spindle_enc_last,
-                )
-                .ok();
-            }
-            DebugPage::Control => {
-                let mut feed = control.get_feed_rate_micron_per_rev();
-                match control.get_last_direction() {
-                    Direction::Backwards => {
-                        feed = -feed;
-                    }
-                    Direction::Forward => (),
-                }
-
-                write!(self.display.at(0, 0), "{:<16}", "Debug5: control").ok();
-                write!(
-                    self.display.at(0, 1),
-                    "F{:>+5} R{:>+8}",
-                    feed,
-                    control.get_fractional_pulses_remaining(),
-                )
-                .ok();
-            }
-            DebugPage::Time => {
-                write!(self.display.at(0, 0), "{:<16}", "Debug6: time").ok();
-                write!(
-                    self.display.at(0, 1),
-                    "N{:>8x} L{:>5}",
-                    now_ms,
-                    now_ms - self.last_update_ms,
-                )
-                .ok();
-            }
-        }
-    }
 }
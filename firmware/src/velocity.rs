@@ -0,0 +1,98 @@
+//! Spindle velocity estimation from encoder edge timing.
+//!
+//! The control interrupt samples the encoder with a DWT timestamp. Velocity
+//! is the count change between two samples at which the count changed,
+//! divided by the time between them, over a window of at least
+//! MIN_WINDOW_US. At high speed this averages many pulses; at low speed the
+//! window stretches to the time between pulses, so resolution doesn't
+//! depend on a fixed sampling period.
+use crate::filter::{Filter, Iir};
+
+// Shortest time to measure velocity over.
+const MIN_WINDOW_US: i64 = 10_000;
+// With no encoder pulses for this long the spindle is considered stopped.
+const STOP_TIMEOUT_US: i64 = 1_000_000;
+// Smoothing for the acceleration estimate, 1/8 new value.
+type AccelerationFilter = Iir<3>;
+
+pub struct VelocityEstimator {
+    cycles_per_us: i64,
+    cycles_per_min: i64,
+    // Most recent sample at which the count changed.
+    edge_cycles: u32,
+    edge_count: i64,
+    // Start of the current measurement window.
+    window_cycles: u32,
+    window_count: i64,
+    stopped: bool,
+    velocity: i64,
+    acceleration: AccelerationFilter,
+}
+
+impl VelocityEstimator {
+    pub fn new(cycles_per_us: u32, count: i64) -> Self {
+        VelocityEstimator {
+            cycles_per_us: cycles_per_us as i64,
+            cycles_per_min: cycles_per_us as i64 * 60_000_000,
+            edge_cycles: 0,
+            edge_count: count,
+            window_cycles: 0,
+            window_count: count,
+            stopped: true,
+            velocity: 0,
+            acceleration: AccelerationFilter::new(),
+        }
+    }
+
+    // Add an encoder sample, taken at the given DWT cycle count.
+    pub fn update(&mut self, now_cycles: u32, count: i64) {
+        if count == self.edge_count {
+            if self.stopped {
+                return;
+            }
+            let since = now_cycles.wrapping_sub(self.edge_cycles) as i64;
+            if since > STOP_TIMEOUT_US * self.cycles_per_us {
+                self.stopped = true;
+                self.velocity = 0;
+                self.acceleration = AccelerationFilter::new();
+            } else if since > 0 {
+                // The spindle can't be going faster than one pulse in the
+                // time since the last, so slow the estimate as it slows.
+                let bound = self.cycles_per_min / since;
+                self.velocity = self.velocity.clamp(-bound, bound);
+            }
+            return;
+        }
+        self.edge_cycles = now_cycles;
+        self.edge_count = count;
+        if self.stopped {
+            // First pulse after stopping only starts a window.
+            self.stopped = false;
+            self.window_cycles = now_cycles;
+            self.window_count = count;
+            return;
+        }
+        let window = now_cycles.wrapping_sub(self.window_cycles) as i64;
+        if window < MIN_WINDOW_US * self.cycles_per_us {
+            return;
+        }
+        let pulses = count - self.window_count;
+        let velocity = pulses * self.cycles_per_min / window;
+        let acceleration = (velocity - self.velocity) * self.cycles_per_min / 60 / window;
+        self.acceleration
+            .update(acceleration.clamp(i32::MIN as i64, i32::MAX as i64) as i32);
+        self.velocity = velocity;
+        self.window_cycles = now_cycles;
+        self.window_count = count;
+    }
+
+    // Encoder pulses per minute.
+    pub fn get_velocity(&self) -> i64 {
+        self.velocity
+    }
+
+    // Encoder pulses per minute per second.
+    pub fn get_acceleration(&self) -> i64 {
+        self.acceleration.value() as i64
+    }
+}
@@ -0,0 +1,83 @@
+//! What the user interface shows, independent of the display.
+//!
+//! The user interface decides what to show and describes it in a View each
+//! update. A Renderer draws that on whatever display there is: as text on a
+//! character display (textview.rs), or with large digits and icons on the
+//! OLED (oledview.rs).
+use crate::index;
+use crate::lcd;
+use crate::servo::Fault;
+use crate::timing;
+use crate::userinterface::{DebugPage, Mode};
+
+pub struct View<'a> {
+    pub now_ms: i64,
+    pub rpm: i32,
+    // Worst condition, e.g. "OK", "SLOW" or "FAULT".
+    pub status: &'a str,
+    // Carriage position, in μm.
+    pub carriage_position: i64,
+    pub motor_enable: bool,
+    pub motor_direction: bool,
+    pub screen: Screen<'a>,
+}
+
+pub enum Screen<'a> {
+    // A two line message, shown instead of everything else.
+    Message(&'a str, &'a str),
+    // The mode and its setting: feed rate in μm/rev, metric pitch in μm or
+    // imperial pitch in TPI.
+    Mode {
+        mode: Mode,
+        setting: i32,
+        // Only drawn on the OLED, so unread in character display builds.
+        #[allow(dead_code)]
+        stop: Option<Stop>,
+    },
+    // A latched servo fault, with the travel it lost, in μm.
+    Fault {
+        fault: Fault,
+        lost: i64,
+        clearing: bool,
+    },
+    Debug(Debug<'a>),
+}
+
+// Progress towards a carriage stop, in μm.
+#[derive(Clone, Copy)]
+#[allow(dead_code)]
+pub struct Stop {
+    pub travelled: i64,
+    pub distance: i64,
+}
+
+// Everything the debug pages show.
+pub struct Debug<'a> {
+    pub page: DebugPage,
+    pub button1: bool,
+    pub mode_enc_pos: i64,
+    pub mode_enc_button: bool,
+    pub feed_enc_pos: i64,
+    pub feed_enc_button: bool,
+    pub spindle_enc_pos: i64,
+    // Spindle encoder counts since the last update.
+    pub spindle_enc_delta: i64,
+    pub spindle_raw_pos: i64,
+    pub spindle_raw_invalid: u32,
+    pub motor_pulses: u32,
+    // Feed rate in μm/rev, negative when the motor is reversed.
+    pub feed: i32,
+    pub fractional_pulses: i64,
+    pub servo_ok: bool,
+    // Time since the last update, in ms.
+    pub since_update: i64,
+    pub timing: &'a timing::Stats,
+    pub index: &'a index::Stats,
+}
+
+pub trait Renderer {
+    // Returns any error the display reported.
+    fn render(&mut self, view: &View) -> Result<(), lcd::Error>;
+    // Set the display brightness, as a percentage.
+    fn brightness(&mut self, percent: u8);
+}
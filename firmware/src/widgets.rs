@@ -0,0 +1,94 @@
+//! User-defined characters and the display widgets built from them.
+//!
+//! The display has eight user-defined characters, allocated here: a
+//! spindle indicator whose glyph is reprogrammed as it turns, compact unit
+//! symbols, and bar graph cells one to five pixels wide.
+use crate::lcd::CharacterDisplay;
+
+// Character codes.
+pub const SPINNER: u8 = 0;
+pub const MICRON_PER_REV: u8 = 1;
+pub const TPI: u8 = 2;
+// Bar cells with one to five columns filled are BAR_CELLS..BAR_CELLS + 4.
+const BAR_CELLS: u8 = 3;
+const BAR_CELL_PIXELS: u32 = 5;
+
+// "μm/r" squeezed into one cell, as μ over r.
+const MICRON_PER_REV_GLYPH: [u8; 8] = [
+    0b01010, 0b01010, 0b01111, 0b01000, 0b11111, 0b01011, 0b01100, 0b01000,
+];
+// "TPI", as T over PI.
+const TPI_GLYPH: [u8; 8] = [
+    0b01110, 0b00100, 0b00100, 0b00000, 0b11001, 0b10101, 0b11001, 0b10001,
+];
+// A spoke turning through half a revolution, which repeats.
+const SPINNER_GLYPHS: [[u8; 8]; 4] = [
+    [
+        0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000,
+    ],
+    [
+        0b00001, 0b00010, 0b00010, 0b00100, 0b01000, 0b01000, 0b10000, 0b00000,
+    ],
+    [
+        0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000, 0b00000,
+    ],
+    [
+        0b10000, 0b01000, 0b01000, 0b00100, 0b00010, 0b00010, 0b00001, 0b00000,
+    ],
+];
+
+// Bar cell with the left `pixels` columns filled, leaving the top and
+// bottom rows clear so adjacent lines don't merge.
+const fn bar_glyph(pixels: u32) -> [u8; 8] {
+    let row = (0b11111 << (BAR_CELL_PIXELS - pixels)) as u8 & 0b11111;
+    [0, row, row, row, row, row, row, 0]
+}
+
+// Program all the user-defined characters. They're kept by the display
+// until it loses power.
+pub fn program_glyphs<D: CharacterDisplay>(display: &mut D) {
+    display.program_character(SPINNER, &SPINNER_GLYPHS[0]);
+    display.program_character(MICRON_PER_REV, &MICRON_PER_REV_GLYPH);
+    display.program_character(TPI, &TPI_GLYPH);
+    for pixels in 1..=BAR_CELL_PIXELS {
+        display.program_character(BAR_CELLS + pixels as u8 - 1, &bar_glyph(pixels));
+    }
+}
+
+// Draw a bar graph `width` characters wide at the display's position,
+// filled in proportion to value / max, to a fifth of a character.
+pub fn bar<D: CharacterDisplay>(display: &mut D, width: u8, value: u32, max: u32) {
+    let pixels =
+        (value.min(max) as u64 * width as u64 * BAR_CELL_PIXELS as u64 / max.max(1) as u64) as u32;
+    for i in 0..width as u32 {
+        let fill = pixels
+            .saturating_sub(i * BAR_CELL_PIXELS)
+            .min(BAR_CELL_PIXELS);
+        display.char(match fill {
+            0 => b' ',
+            _ => BAR_CELLS + fill as u8 - 1,
+        });
+    }
+}
+
+// Spindle rotation indicator. Shown as the SPINNER character, which turns a
+// step each update in the direction the spindle is turning.
+pub struct Spinner {
+    frame: usize,
+}
+
+impl Spinner {
+    pub const fn new() -> Self {
+        Spinner { frame: 0 }
+    }
+
+    pub fn update<D: CharacterDisplay>(&mut self, display: &mut D, direction: i32) {
+        let frames = SPINNER_GLYPHS.len();
+        self.frame = match direction.signum() {
+            1 => (self.frame + 1) % frames,
+            -1 => (self.frame + frames - 1) % frames,
+            _ => return,
+        };
+        display.program_character(SPINNER, &SPINNER_GLYPHS[self.frame]);
+    }
+}
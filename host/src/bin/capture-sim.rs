@@ -0,0 +1,58 @@
+//! Synthesise spindle encoder captures, for replay fixtures where no real
+//! capture of the behaviour is available.
+//!
+//! `capture-sim <ripple|slip|reverse>` writes a one second capture to
+//! stdout, using the default machine configuration:
+//!
+//! * ripple: 1400RPM with VFD speed ripple and noise, as modelled by
+//!   firmware/doc/rpm_ui_sim.py.
+//! * slip: 600RPM with the spindle briefly losing a third of its speed, as
+//!   when a belt slips under a heavy cut.
+//! * reverse: 300RPM decelerating through zero to -300RPM.
+use std::f64::consts::PI;
+use std::process::ExitCode;
+
+use els_host::config::Config;
+use els_host::harness::Noise;
+use els_host::replay::CaptureFile;
+
+const DURATION_MS: i64 = 1000;
+
+fn main() -> ExitCode {
+    let scenario = std::env::args().nth(1).unwrap_or_default();
+    let mut noise = Noise(0x2506);
+    let rpm_at = |t: f64, noise: &mut Noise| -> Option<f64> {
+        match scenario.as_str() {
+            "ripple" => Some(
+                1400.0
+                    + (2.0 * PI * 4.0 * t).sin() * 80.0
+                    + (2.0 * PI * 20.0 * t).sin() * 40.0
+                    + noise.normal(10.0),
+            ),
+            "slip" => Some(600.0 * if (0.4..0.5).contains(&t) { 0.67 } else { 1.0 }),
+            "reverse" => Some(300.0 - 600.0 * t),
+            _ => None,
+        }
+    };
+    let config = Config::new();
+    // Encoder pulses per spindle revolution.
+    let ppr = (config.encoder_ppr * config.encoder_ratio_encoder) as f64
+        / config.encoder_ratio_spindle as f64;
+    let mut samples = Vec::new();
+    let mut position = 0.0;
+    let mut count: i64 = 0;
+    for ms in 1..=DURATION_MS {
+        let Some(rpm) = rpm_at(ms as f64 / 1000.0, &mut noise) else {
+            eprintln!("usage: capture-sim <ripple|slip|reverse>");
+            return ExitCode::FAILURE;
+        };
+        position += rpm * ppr / 60_000.0;
+        let delta = position.floor() as i64 - count;
+        count += delta;
+        if delta != 0 {
+            samples.push((ms, delta as i32));
+        }
+    }
+    print!("{}", CaptureFile { config, samples });
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,38 @@
+//! Display character ROM tables.
+//!
+//! Prints the firmware's table for a character ROM (vfd, a00, a02 or oled)
+//! as a grid of codes.
+use std::process::ExitCode;
+
+use els_host::charmap::{CharMap, HD44780_A00, HD44780_A02, OLED, VFD};
+
+const ROMS: [(&str, &CharMap); 4] = [
+    ("vfd", &VFD),
+    ("a00", &HD44780_A00),
+    ("a02", &HD44780_A02),
+    ("oled", &OLED),
+];
+
+fn print(map: &CharMap) {
+    println!("    0 1 2 3 4 5 6 7 8 9 a b c d e f");
+    for row in 0..16u8 {
+        let cells: Vec<String> = (0..16u8)
+            .map(|col| map.decode(row << 4 | col).unwrap_or('░').to_string())
+            .collect();
+        println!("{:x}0  {}", row, cells.join(" "));
+    }
+}
+
+fn main() -> ExitCode {
+    let arg = std::env::args().nth(1);
+    match ROMS.iter().find(|(name, _)| Some(*name) == arg.as_deref()) {
+        Some((_, map)) => {
+            print(map);
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("usage: charmap vfd|a00|a02|oled");
+            ExitCode::FAILURE
+        }
+    }
+}
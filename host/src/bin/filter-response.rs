@@ -0,0 +1,38 @@
+//! Step and noise response of the firmware's signal filters.
+//!
+//! Prints, for each filter, the step response and how much Gaussian noise
+//! and single-sample glitches get through.
+use els_host::filter::{Fir, Iir, Median};
+use els_host::response::{response, settling, Response};
+
+fn filters() -> [(&'static str, Response); 4] {
+    let triangle = {
+        let mut taps = [0; 9];
+        for (i, tap) in taps.iter_mut().enumerate() {
+            *tap = 5 - (i as i32 - 4).abs();
+        }
+        taps
+    };
+    [
+        ("iir 1/8", response(Iir::<3>::new)),
+        ("median 3", response(Median::<3>::new)),
+        ("boxcar 20", response(Fir::<20>::boxcar)),
+        ("triangle 9", response(|| Fir::new(triangle))),
+    ]
+}
+
+fn main() {
+    println!("filter      settle  noise sd  glitch  step response");
+    for (name, r) in filters() {
+        let settle = settling(&r.step).map_or("-".to_string(), |n| n.to_string());
+        let step: Vec<String> = r.step.iter().take(12).map(i32::to_string).collect();
+        println!(
+            "{:<10}  {:>6}  {:>8.1}  {:>6}  {}",
+            name,
+            settle,
+            r.noise_sd,
+            r.glitch,
+            step.join(" ")
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! Replay spindle encoder captures through the feed calculations.
+//!
+//! `replay <capture> <feed|pitch|tpi> <value>` writes the spindle and motor
+//! positions after each sample as CSV.
+use std::path::Path;
+use std::process::ExitCode;
+
+use els_host::replay::{replay, CaptureFile, Feed};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args[..] {
+        [path, setting, value] => match Feed::parse(setting, value) {
+            Some(feed) => CaptureFile::load(Path::new(path))
+                .map(|capture| print!("{}", replay(&capture, feed))),
+            None => Err(format!("bad feed setting: {} {}", setting, value)),
+        },
+        _ => Err("usage: replay <capture> <feed|pitch|tpi> <value>".into()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,20 @@
+//! Golden screens for the firmware's user interface.
+//!
+//! Prints the screen for every mode, debug page, warning and fault, for
+//! each supported character display size and for the OLED, and any
+//! problems drawing them.
+use els_host::screens::{oled_screens, screens};
+
+fn main() {
+    for (size, (screens, problems)) in [
+        ("16x2", screens::<16, 2>()),
+        ("20x4", screens::<20, 4>()),
+        ("40x2", screens::<40, 2>()),
+        ("oled", oled_screens()),
+    ] {
+        println!("## {}\n{}", size, screens);
+        for problem in problems {
+            eprintln!("{}", problem);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Decode telemetry frames from the firmware and write them as CSV.
+//!
+//! Reads from the named file or serial device (or stdin), skipping any
+//! console text mixed in with the frames.
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+
+use els_host::telemetry::Decoder;
+
+fn main() -> io::Result<()> {
+    let mut input: Box<dyn Read> = match std::env::args().nth(1) {
+        Some(path) if path != "-" => Box::new(File::open(path)?),
+        _ => Box::new(io::stdin()),
+    };
+    let mut out = BufWriter::new(io::stdout().lock());
+    writeln!(
+        out,
+        "timestamp_ms,spindle_count,rpm,motor_pulses,direction,servo_ok,motor_enable,fractional,main_loop_us"
+    )?;
+    let mut decoder = Decoder::new();
+    let mut buf = [0u8; 256];
+    loop {
+        let n = input.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        for f in buf[..n].iter().filter_map(|&b| decoder.push(b)) {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{}",
+                f.timestamp_ms,
+                f.spindle_count,
+                f.rpm,
+                f.motor_pulses,
+                f.direction as u8,
+                f.servo_ok as u8,
+                f.motor_enable as u8,
+                f.fractional,
+                f.main_loop_us,
+            )?;
+        }
+        // Keep the output current when following a live stream.
+        out.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,22 @@
+//! Compare carriage following error with and without the spindle phase
+//! tracker, against a simulated spindle with speed ripple, for a range of
+//! spindle speeds and feeds.
+use els_host::config::Config;
+use els_host::following::{follow, spindle};
+
+fn main() {
+    let config = Config::new();
+    println!("                       following error (μm)");
+    println!("  RPM  feed    untracked rms/max    tracked rms/max");
+    for rpm in [300.0, 1400.0, 3000.0] {
+        let angle = spindle(rpm);
+        for feed in [100, 1000, 4000] {
+            let raw = follow(&angle, &config, feed, false);
+            let tracked = follow(&angle, &config, feed, true);
+            println!(
+                "{:>5} {:>5} {:>10.3} {:>9.3} {:>10.3} {:>9.3}",
+                rpm, feed, raw.rms, raw.max, tracked.rms, tracked.max
+            );
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! In-memory models of the displays.
+//!
+//! MockDisplay behaves like an HD44780-style controller: display data RAM
+//! with an auto-incrementing address counter, character generator RAM,
+//! cursor and brightness state. The screen can be read back as Unicode
+//! text. Writes that land outside the visible screen are recorded as
+//! errors, since on a real display they silently disappear.
+//!
+//! MockOled models an OLED controller's display RAM, so what the OLED driver
+//! sends can be checked against the bitmap it was drawing.
+use std::fmt::{self, Write};
+
+use crate::bitmap::PAGES;
+use crate::charmap::CharMap;
+use crate::lcd::{self, CharacterDisplay, Error};
+use crate::oled::{Controller, Interface};
+
+// Display data RAM is 0x00-0x27 for the first line and 0x40-0x67 for the
+// second.
+const DDRAM_SIZE: usize = 0x68;
+
+// How user-defined characters (codes 0-7, repeated at 8-15) read back.
+const USER_CHARS: [char; 8] = ['⓪', '①', '②', '③', '④', '⑤', '⑥', '⑦'];
+
+pub struct MockDisplay<const COLS: usize, const ROWS: usize> {
+    charmap: &'static CharMap,
+    ddram: [u8; DDRAM_SIZE],
+    cgram: [[u8; 8]; 8],
+    // Address counter, in character generator RAM after programming a
+    // character until an address is set.
+    address: u8,
+    in_cgram: bool,
+    show_cursor: bool,
+    blink_cursor: bool,
+    brightness: u8,
+    errors: Vec<String>,
+}
+
+impl<const COLS: usize, const ROWS: usize> MockDisplay<COLS, ROWS> {
+    pub fn new(_geometry: lcd::Geometry<COLS, ROWS>, charmap: &'static CharMap) -> Self {
+        MockDisplay {
+            charmap,
+            ddram: [b' '; DDRAM_SIZE],
+            cgram: [[0; 8]; 8],
+            address: 0,
+            in_cgram: false,
+            show_cursor: false,
+            blink_cursor: false,
+            brightness: 100,
+            errors: Vec::new(),
+        }
+    }
+
+    // Screen position of a display data RAM address, if it's visible.
+    fn cell(address: u8) -> Option<(usize, usize)> {
+        (0..ROWS).find_map(|y| {
+            let start = lcd::ddram_addr(COLS, 0, y as u8);
+            (start..start + COLS as u8)
+                .contains(&address)
+                .then(|| ((address - start) as usize, y))
+        })
+    }
+
+    fn decode(&self, code: u8) -> char {
+        match code {
+            0..=15 => USER_CHARS[code as usize & 7],
+            _ => self.charmap.decode(code).unwrap_or('\u{fffd}'),
+        }
+    }
+
+    // Cell the address counter is on, if it's on the screen.
+    fn current_cell(&self) -> Option<(usize, usize)> {
+        match self.in_cgram {
+            true => None,
+            false => Self::cell(self.address),
+        }
+    }
+
+    // Write a code at the address counter, returning whether it was on the
+    // screen.
+    fn write(&mut self, c: u8) -> bool {
+        if self.in_cgram {
+            let index = (self.address >> 3) as usize & 7;
+            self.cgram[index][self.address as usize & 7] = c;
+            self.address = (self.address + 1) & 0x3f;
+            return false;
+        }
+        let visible = Self::cell(self.address).is_some();
+        if let Some(cell) = self.ddram.get_mut(self.address as usize) {
+            *cell = c;
+        }
+        self.address = match self.address {
+            0x27 => 0x40,
+            0x67.. => 0x00,
+            address => address + 1,
+        };
+        visible
+    }
+
+    // The screen, one string per line.
+    pub fn text(&self) -> Vec<String> {
+        (0..ROWS)
+            .map(|y| {
+                let start = lcd::ddram_addr(COLS, 0, y as u8) as usize;
+                self.ddram[start..start + COLS]
+                    .iter()
+                    .map(|&code| self.decode(code))
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Cursor cell, and whether it's shown and blinking. The cell is None if
+    // the address counter isn't on the screen.
+    pub fn cursor(&self) -> (Option<(usize, usize)>, bool, bool) {
+        (self.current_cell(), self.show_cursor, self.blink_cursor)
+    }
+
+    // Rows of a user-defined character.
+    pub fn glyph(&self, index: u8) -> [u8; 8] {
+        self.cgram[index as usize & 7]
+    }
+
+    pub fn get_brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    // Writes that went off the screen since the last call.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+impl<const COLS: usize, const ROWS: usize> CharacterDisplay for MockDisplay<COLS, ROWS> {
+    const COLS: usize = COLS;
+    const ROWS: usize = ROWS;
+
+    fn init(&mut self) {
+        self.clear();
+        self.cursor(false, false);
+    }
+    fn charmap(&self) -> &'static CharMap {
+        self.charmap
+    }
+    fn cursor(&mut self, show_cursor: bool, blink_cursor: bool) {
+        self.show_cursor = show_cursor;
+        self.blink_cursor = blink_cursor;
+    }
+    fn addr(&mut self, addr: u8) {
+        self.address = addr & 0x7f;
+        self.in_cgram = false;
+    }
+    fn char(&mut self, c: u8) {
+        let what = self.decode(c);
+        if !self.write(c) {
+            self.errors
+                .push(format!("{:?} written off the screen", what));
+        }
+    }
+    fn clear(&mut self) {
+        self.ddram = [b' '; DDRAM_SIZE];
+        self.address = 0;
+        self.in_cgram = false;
+    }
+    fn program_character(&mut self, index: u8, glyph: &[u8; 8]) {
+        self.cgram[index as usize & 7] = *glyph;
+        self.address = (index & 7) << 3;
+        self.in_cgram = true;
+    }
+    fn brightness(&mut self, percent: u8) {
+        self.brightness = percent;
+    }
+    fn position(&mut self, x: u8, y: u8) -> Result<(), Error> {
+        if x as usize > COLS || y as usize >= ROWS {
+            self.errors
+                .push(format!("position ({}, {}) off the screen", x, y));
+            return Err(Error::BoundsError);
+        }
+        self.addr(lcd::ddram_addr(COLS, x, y));
+        Ok(())
+    }
+    // Written like the display itself, so text that runs off the end of
+    // the line is lost, but recorded.
+    fn string(&mut self, s: &str) -> Result<u8, Error> {
+        let len = s.chars().count();
+        match self.current_cell() {
+            Some((x, y)) if x + len > COLS => {
+                self.errors
+                    .push(format!("{:?} at ({}, {}) runs off the line", s, x, y));
+            }
+            None => self.errors.push(format!("{:?} written off the screen", s)),
+            _ => (),
+        }
+        for c in s.chars() {
+            self.write(self.charmap.encode(c));
+        }
+        Ok(len as u8)
+    }
+}
+
+impl<const COLS: usize, const ROWS: usize> Write for MockDisplay<COLS, ROWS> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.string(s).map_err(|_| fmt::Error)?;
+        Ok(())
+    }
+}
+
+// Columns of display RAM in either OLED controller.
+const OLED_RAM_COLUMNS: usize = 132;
+
+// Model of an SSD1306 or SH1106 OLED controller, from the commands and
+// data it's sent. Only page addressing is modelled, which is all the
+// driver uses.
+pub struct MockOled {
+    controller: Controller,
+    ram: [[u8; OLED_RAM_COLUMNS]; PAGES],
+    page: usize,
+    column: usize,
+    on: bool,
+    contrast: u8,
+    errors: Vec<String>,
+}
+
+impl MockOled {
+    pub fn new(controller: Controller) -> Self {
+        MockOled {
+            controller,
+            ram: [[0; OLED_RAM_COLUMNS]; PAGES],
+            page: 0,
+            column: 0,
+            on: false,
+            contrast: 0x7f,
+            errors: Vec::new(),
+        }
+    }
+
+    // Whether a pixel of the screen is lit. The SH1106 shows columns 2-129
+    // of its RAM.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let offset = match self.controller {
+            Controller::Ssd1306 => 0,
+            Controller::Sh1106 => 2,
+        };
+        self.on && self.ram[y / 8][x + offset] & 1 << (y % 8) != 0
+    }
+
+    pub fn get_contrast(&self) -> u8 {
+        self.contrast
+    }
+
+    // Commands and writes that weren't understood since the last call.
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+impl Interface for MockOled {
+    fn command(&mut self, bytes: &[u8]) {
+        let mut bytes = bytes.iter();
+        while let Some(&command) = bytes.next() {
+            // Commands followed by one byte of argument.
+            let mut argument = || match bytes.next() {
+                Some(&argument) => Some(argument),
+                None => {
+                    self.errors
+                        .push(format!("command {:#04x} without its argument", command));
+                    None
+                }
+            };
+            match command {
+                0x00..=0x0f => self.column = self.column & 0xf0 | command as usize & 0x0f,
+                0x10..=0x1f => self.column = self.column & 0x0f | (command as usize & 0x0f) << 4,
+                0x40..=0x7f | 0xa0..=0xa1 | 0xa4 | 0xa6 | 0xc0 | 0xc8 => (),
+                0xae => self.on = false,
+                0xaf => self.on = true,
+                0xb0..=0xb7 => self.page = command as usize & 0x07,
+                0x81 => {
+                    if let Some(contrast) = argument() {
+                        self.contrast = contrast;
+                    }
+                }
+                0x20 | 0x8d if self.controller == Controller::Ssd1306 => {
+                    argument();
+                }
+                0xad if self.controller == Controller::Sh1106 => {
+                    argument();
+                }
+                0xa8 | 0xd3 | 0xd5 | 0xd9 | 0xda | 0xdb => {
+                    argument();
+                }
+                _ => self
+                    .errors
+                    .push(format!("unknown command {:#04x}", command)),
+            }
+        }
+    }
+    fn data(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match self.ram[self.page].get_mut(self.column) {
+                Some(column) => *column = byte,
+                None => self
+                    .errors
+                    .push(format!("data written past column {}", self.column)),
+            }
+            self.column += 1;
+        }
+    }
+}
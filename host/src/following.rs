@@ -0,0 +1,106 @@
+//! Carriage following error with and without the spindle phase tracker,
+//! against a simulated spindle with speed ripple.
+//!
+//! The spindle runs at a given speed with VFD speed ripple and noise as
+//! modelled by firmware/doc/rpm_ui_sim.py. Its encoder is sampled at the
+//! control interrupt rate, and the steps for each sample are emitted over
+//! the following microseconds as the pulse generator would. Following error
+//! is the difference between where the carriage is and the last whole step
+//! before where the spindle says it should be, every microsecond, as the
+//! feed calculations round steps down.
+use std::f64::consts::PI;
+
+use crate::config::Config;
+use crate::control::{Control, Direction};
+use crate::harness::Noise;
+use crate::tracker::Tracker;
+
+const DURATION: f64 = 2.0; // s
+const SETTLE: f64 = 0.2; // s
+
+// Time from encoder sample to first step, and between steps.
+const STEP_DELAY: f64 = 5e-6; // s
+const STEP_PERIOD: f64 = 1.6e-6; // s
+
+// Spindle angle (revolutions) over time, at 1μs resolution.
+pub fn spindle(rpm: f64) -> Vec<f64> {
+    let mut noise = Noise(0x2506);
+    let mut angle = 0.0;
+    let mut noise_rpm = 0.0;
+    (0..(DURATION * 1e6) as usize)
+        .map(|us| {
+            let t = us as f64 * 1e-6;
+            if us % 1000 == 0 {
+                noise_rpm = noise.normal(rpm / 140.0);
+            }
+            let ripple = (rpm / 1400.0)
+                * ((2.0 * PI * 4.0 * t).sin() * 80.0 + (2.0 * PI * 20.0 * t).sin() * 40.0);
+            angle += (rpm + ripple + noise_rpm) / 60.0 * 1e-6;
+            angle
+        })
+        .collect()
+}
+
+pub struct Error {
+    pub rms: f64,
+    pub max: f64,
+}
+
+// Following error of the carriage over the spindle's movement, with steps
+// following the phase tracker or the sampled encoder count directly.
+pub fn follow(angle: &[f64], config: &Config, feed: i32, tracked: bool) -> Error {
+    let mut control = Control::new();
+    control.set_config(*config);
+    control.set_feed_rate_micron_per_rev(feed);
+    // Encoder pulses per spindle revolution, and carriage travel per motor
+    // pulse.
+    let ppr = (config.encoder_ppr * config.encoder_ratio_encoder) as f64
+        / config.encoder_ratio_spindle as f64;
+    let um_per_pulse = control.pulses_to_micron(1_000_000) as f64 / 1e6;
+    let count_at = |us: usize| (angle[us] * ppr).floor() as i64;
+    let mut tracker = Tracker::new(count_at(0));
+    let mut last_count = count_at(0);
+    // Net motor pulses emitted during each μs.
+    let mut steps = vec![0i64; angle.len()];
+    let tick_us = 1e6 / crate::CONTROL_RATE as f64;
+    for tick in 0..(angle.len() as f64 / tick_us) as usize {
+        let t = tick as f64 * tick_us * 1e-6;
+        let count = count_at((t * 1e6) as usize);
+        let advance = if tracked {
+            tracker.update(count)
+        } else {
+            (count - last_count) << 16
+        };
+        let (direction, pulses) = control.feed_per_rev_fraction(advance);
+        last_count = count;
+        for i in 0..pulses {
+            let emitted = t + STEP_DELAY + i as f64 * STEP_PERIOD;
+            if let Some(s) = steps.get_mut((emitted * 1e6) as usize) {
+                *s += match direction {
+                    Direction::Forward => 1,
+                    Direction::Backwards => -1,
+                };
+            }
+        }
+    }
+    // Compare carriage and spindle positions every μs.
+    let (mut sum_sq, mut max, mut n) = (0.0, 0.0f64, 0);
+    let mut motor_position: i64 = 0;
+    for (us, (&a, &s)) in angle.iter().zip(&steps).enumerate() {
+        motor_position += s;
+        if (us as f64) < SETTLE * 1e6 {
+            continue;
+        }
+        // Steps are rounded down, so the carriage can be no closer than
+        // the last whole step behind the spindle.
+        let ideal = (a * feed as f64 / um_per_pulse).floor();
+        let error = (motor_position as f64 - ideal) * um_per_pulse;
+        sum_sq += error * error;
+        max = max.max(error.abs());
+        n += 1;
+    }
+    Error {
+        rms: (sum_sq / n as f64).sqrt(),
+        max,
+    }
+}
@@ -0,0 +1,45 @@
+//! Helpers shared by the simulations and their tests.
+use std::fs;
+use std::path::Path;
+
+// Deterministic noise, so that simulations and fixtures repeat exactly.
+pub struct Noise(pub u32);
+
+impl Noise {
+    pub fn uniform(&mut self) -> f64 {
+        // xorshift32.
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f64 / u32::MAX as f64
+    }
+    // Approximately normal, with the given standard deviation.
+    pub fn normal(&mut self, sd: f64) -> f64 {
+        ((0..12).map(|_| self.uniform()).sum::<f64>() - 6.0) * sd
+    }
+}
+
+// Compare output with its expected contents in a golden file. With the
+// BLESS environment variable set, as after an intended change, the file is
+// rewritten instead.
+pub fn golden(path: &Path, actual: &str) -> Result<(), String> {
+    if std::env::var_os("BLESS").is_some() {
+        return fs::write(path, actual).map_err(|e| format!("{}: {}", path.display(), e));
+    }
+    let expected = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if expected == actual {
+        return Ok(());
+    }
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .unwrap_or(expected.lines().count().min(actual.lines().count()));
+    Err(format!(
+        "{}:{}: expected {:?}, got {:?} (BLESS=1 to accept)",
+        path.display(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or("end of file"),
+        actual.lines().nth(line).unwrap_or("end of file"),
+    ))
+}
@@ -0,0 +1,102 @@
+//! Host-side tools for the electronic leadscrew firmware.
+//!
+//! Firmware modules that don't depend on the hardware are built directly
+//! from the firmware source tree, so the host and firmware always agree.
+//! The firmware is a binary crate, so lints for public library APIs are
+//! relaxed for these modules.
+
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/bitmap.rs"]
+pub mod bitmap;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/capture.rs"]
+pub mod capture;
+// The firmware builds the character ROM tables for its display, chosen by
+// features the host doesn't have; the host builds them all.
+#[allow(unexpected_cfgs)]
+#[path = "../../firmware/src/charmap.rs"]
+pub mod charmap;
+// The USB console port is a firmware feature, which the host doesn't have.
+#[allow(clippy::new_without_default, unexpected_cfgs)]
+#[path = "../../firmware/src/console.rs"]
+pub mod console;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/config.rs"]
+pub mod config;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/control.rs"]
+pub mod control;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/encoder.rs"]
+pub mod encoder;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/filter.rs"]
+pub mod filter;
+#[path = "../../firmware/src/font.rs"]
+pub mod font;
+#[path = "../../firmware/src/framebuffer.rs"]
+pub mod framebuffer;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/index.rs"]
+pub mod index;
+#[path = "../../firmware/src/input.rs"]
+pub mod input;
+// The display drivers are chosen by firmware features, which the host
+// doesn't have, so only the default driver is built.
+#[allow(unexpected_cfgs)]
+#[path = "../../firmware/src/lcd.rs"]
+pub mod lcd;
+#[allow(unexpected_cfgs)]
+#[path = "../../firmware/src/oled.rs"]
+pub mod oled;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/oledview.rs"]
+pub mod oledview;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/servo.rs"]
+pub mod servo;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/telemetry.rs"]
+pub mod telemetry;
+// The character display renderer isn't built for the OLED feature.
+#[allow(clippy::new_without_default, unexpected_cfgs)]
+#[path = "../../firmware/src/textview.rs"]
+pub mod textview;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/timing.rs"]
+pub mod timing;
+#[path = "../../firmware/src/tracker.rs"]
+pub mod tracker;
+#[path = "../../firmware/src/userinterface.rs"]
+pub mod userinterface;
+#[path = "../../firmware/src/view.rs"]
+pub mod view;
+#[allow(clippy::new_without_default)]
+#[path = "../../firmware/src/widgets.rs"]
+pub mod widgets;
+
+// Machine configuration defaults, as in the firmware's main.rs. Captures
+// carry the configuration they were recorded with, so replay doesn't
+// depend on these.
+const ENCODER_PPR: i64 = 2000;
+const ENCODER_RATIO_SPINDLE: i64 = -40;
+const ENCODER_RATIO_ENCODER: i64 = 80;
+const LEADSCREW_PITCH: i64 = 3000; // µm
+const DRIVE_RATIO_MOTOR: i64 = -20;
+const DRIVE_RATIO_LEADSCREW: i64 = 80;
+const MOTOR_PPR: i64 = 3200;
+const SPINDLE_ENC_FILTER: i64 = 0;
+const SPINDLE_ENC_PRESCALER: i64 = 1;
+const UI_ENC_FILTER: i64 = 0;
+const UI_ENC_PRESCALER: i64 = 1;
+const UI_ENCODER_PULSE_PER_DETENT: u32 = 2;
+const CONTROL_RATE: u32 = 20_000; // Hz
+const DISPLAY_BRIGHTNESS: i64 = 100; // %
+const DISPLAY_DIM_TIMEOUT: i64 = 600; // s
+
+pub mod display;
+pub mod following;
+pub mod harness;
+pub mod replay;
+pub mod response;
+pub mod screens;
@@ -0,0 +1,125 @@
+//! Spindle encoder capture files, and replaying them through the feed
+//! calculations.
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::Path;
+
+use crate::capture;
+use crate::config::Config;
+use crate::control::{Control, Direction};
+use crate::tracker::Tracker;
+
+// A capture as dumped by the firmware's "capture dump" command.
+pub struct CaptureFile {
+    pub config: Config,
+    // (ms since start of capture, encoder delta).
+    pub samples: Vec<(i64, i32)>,
+}
+
+impl CaptureFile {
+    pub fn parse(text: &str) -> Result<CaptureFile, String> {
+        let mut lines = text.lines().map(str::trim).enumerate();
+        match lines.next() {
+            Some((_, line)) if line == format!("# els-capture {}", capture::VERSION) => (),
+            _ => return Err(format!("not a version {} capture", capture::VERSION)),
+        }
+        let mut config = Config::new();
+        let mut samples = Vec::new();
+        for (n, line) in lines {
+            let error = |e: &str| format!("line {}: {}", n + 1, e);
+            if let Some(setting) = line.strip_prefix('#') {
+                let (key, value) = setting
+                    .trim()
+                    .split_once('=')
+                    .ok_or_else(|| error("expected key=value"))?;
+                let value = value.parse().map_err(|_| error("bad value"))?;
+                config.set(key, value).map_err(|e| error(&e.to_string()))?;
+            } else if line == "time_ms,delta" || line.is_empty() || line == "ok" {
+                // Header, and the end of a dump copied from a terminal.
+            } else {
+                let (time, delta) = line
+                    .split_once(',')
+                    .ok_or_else(|| error("expected time_ms,delta"))?;
+                samples.push((
+                    time.parse().map_err(|_| error("bad time"))?,
+                    delta.parse().map_err(|_| error("bad delta"))?,
+                ));
+            }
+        }
+        Ok(CaptureFile { config, samples })
+    }
+
+    pub fn load(path: &Path) -> Result<CaptureFile, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        CaptureFile::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+impl fmt::Display for CaptureFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "# els-capture {}", capture::VERSION)?;
+        for key in Config::KEYS {
+            writeln!(f, "# {}={}", key, self.config.get(key).unwrap_or(0))?;
+        }
+        writeln!(f, "time_ms,delta")?;
+        for (time_ms, delta) in &self.samples {
+            writeln!(f, "{},{}", time_ms, delta)?;
+        }
+        Ok(())
+    }
+}
+
+// Feed setting to replay a capture with, named as the console commands.
+#[derive(Clone, Copy)]
+pub enum Feed {
+    Feed(i32),
+    Pitch(i32),
+    Tpi(i32),
+}
+
+impl Feed {
+    pub fn parse(name: &str, value: &str) -> Option<Feed> {
+        let value = value.parse().ok().filter(|&v| v > 0)?;
+        match name {
+            "feed" => Some(Feed::Feed(value)),
+            "pitch" => Some(Feed::Pitch(value)),
+            "tpi" => Some(Feed::Tpi(value)),
+            _ => None,
+        }
+    }
+}
+
+// Feed a capture through the spindle phase tracker and feed calculations as
+// the control interrupt would, returning CSV of the spindle and motor
+// positions after each sample. Captures have millisecond resolution, so
+// each sample's movement is spread evenly over the interrupt periods since
+// the last.
+pub fn replay(capture: &CaptureFile, feed: Feed) -> String {
+    let mut control = Control::new();
+    control.set_config(capture.config);
+    match feed {
+        Feed::Feed(v) | Feed::Pitch(v) => control.set_feed_rate_micron_per_rev(v),
+        Feed::Tpi(v) => control.set_feed_rate_tpi(v),
+    }
+    let periods_per_ms = (crate::CONTROL_RATE / 1000) as i64;
+    let mut tracker = Tracker::new(0);
+    let mut out = String::from("time_ms,spindle_count,motor_position\n");
+    let mut last_ms = 0;
+    let mut spindle_count: i64 = 0;
+    let mut motor_position: i64 = 0;
+    for &(time_ms, delta) in &capture.samples {
+        let periods = (time_ms - last_ms).max(1) * periods_per_ms;
+        last_ms = time_ms;
+        for period in 1..=periods {
+            let count = spindle_count + (delta as i64 * period).div_euclid(periods);
+            let (direction, pulses) = control.feed_per_rev_fraction(tracker.update(count));
+            match direction {
+                Direction::Forward => motor_position += pulses as i64,
+                Direction::Backwards => motor_position -= pulses as i64,
+            }
+        }
+        spindle_count += delta as i64;
+        writeln!(out, "{},{},{}", time_ms, spindle_count, motor_position).ok();
+    }
+    out
+}
@@ -0,0 +1,68 @@
+//! Step and noise response of the firmware's signal filters.
+use crate::filter::Filter;
+use crate::harness::Noise;
+
+pub const STEP: i32 = 1000;
+pub const NOISE_SD: f64 = 100.0;
+const NOISE_SAMPLES: usize = 10_000;
+pub const GLITCH: i32 = 100_000;
+
+pub struct Response {
+    // Output for each sample after a step from zero to STEP.
+    pub step: Vec<i32>,
+    // Standard deviation of the output with Gaussian noise on STEP.
+    pub noise_sd: f64,
+    // Largest deviation from STEP caused by a one-sample glitch.
+    pub glitch: i32,
+}
+
+pub fn response<F: Filter>(new: impl Fn() -> F) -> Response {
+    let mut filter = new();
+    let step = (0..64)
+        .map(|_| {
+            filter.update(STEP);
+            filter.value()
+        })
+        .collect();
+
+    let mut filter = new();
+    let mut noise = Noise(0x0036);
+    for _ in 0..100 {
+        filter.update(STEP);
+    }
+    let out: Vec<f64> = (0..NOISE_SAMPLES)
+        .map(|_| {
+            filter.update(STEP + noise.normal(NOISE_SD).round() as i32);
+            filter.value() as f64
+        })
+        .collect();
+    let mean = out.iter().sum::<f64>() / out.len() as f64;
+    let noise_sd = (out.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / out.len() as f64).sqrt();
+
+    let mut filter = new();
+    for _ in 0..100 {
+        filter.update(STEP);
+    }
+    filter.update(STEP + GLITCH);
+    let mut glitch = (filter.value() - STEP).abs();
+    for _ in 0..100 {
+        filter.update(STEP);
+        glitch = glitch.max((filter.value() - STEP).abs());
+    }
+
+    Response {
+        step,
+        noise_sd,
+        glitch,
+    }
+}
+
+// Samples until the step response is within 1% of the step, and stays there.
+pub fn settling(step: &[i32]) -> Option<usize> {
+    let settled = |v: &i32| (v - STEP).abs() * 100 <= STEP;
+    match step.iter().rposition(|v| !settled(v)) {
+        None => Some(1),
+        Some(n) if n + 1 < step.len() => Some(n + 2),
+        Some(_) => None,
+    }
+}
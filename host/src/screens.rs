@@ -0,0 +1,471 @@
+//! Golden screens for the firmware's user interface.
+//!
+//! Runs the user interface against an in-memory display through scripted
+//! timelines of knob, button and machine inputs, and draws the resulting
+//! screen for every mode, debug page, warning and fault, for each supported
+//! character display size and for the OLED. Alongside, any problems: text
+//! written off the screen, or a screen that came out differently through
+//! the firmware's framebuffer or OLED driver.
+use std::fmt::Write as _;
+
+use embedded_hal::delay::DelayNs;
+
+use crate::bitmap::{Bitmap, HEIGHT, WIDTH};
+use crate::charmap::VFD;
+use crate::control::{Control, Direction};
+use crate::display::{MockDisplay, MockOled};
+use crate::framebuffer::FrameBuffer;
+use crate::index;
+use crate::input::Input;
+use crate::lcd::{self, Geometry};
+use crate::oled::{Controller, Oled};
+use crate::oledview::{OledRenderer, OledView};
+use crate::servo::{Fault, Servo};
+use crate::textview::TextRenderer;
+use crate::timing;
+use crate::userinterface::{Mode, UI};
+use crate::view::{Renderer, View};
+
+// Encoder pulses per knob detent, as in the firmware's main.rs.
+const PULSES_PER_DETENT: i32 = 2;
+// Long enough for the welcome message to clear.
+const WELCOME_MS: i64 = 3000;
+// Long enough to toggle debug mode by holding both knobs.
+const DEBUG_HOLD_MS: i64 = 1100;
+
+#[derive(Clone, Copy)]
+pub enum Step {
+    // Advance the clock, sampling the knobs and buttons every millisecond,
+    // then update the user interface as the main loop does.
+    Wait(i64),
+    BootMessage(&'static str, &'static str),
+    Rpm(i32),
+    ServoOk(bool),
+    Button1(bool),
+    ModeKnob(i32),
+    ModeButton(bool),
+    FeedKnob(i32),
+    FeedButton(bool),
+    // Change mode from the console.
+    SetMode(Mode),
+    // Carriage position, in motor pulses.
+    Carriage(i64),
+    // Set a carriage stop from the console, in μm.
+    SetStop(i64),
+    Trip(Fault),
+    Lost(u32),
+    SlowPulse,
+    IndexError,
+    LoopTime(u32, u32),
+}
+
+use self::Step::*;
+
+// Everything else the main loop passes to the user interface.
+struct Machine {
+    control: Control,
+    servo: Servo,
+    now_ms: i64,
+    rpm: i32,
+    servo_ok: bool,
+    button1: bool,
+    input: Input,
+    mode_count: u32,
+    mode_button: bool,
+    feed_count: u32,
+    feed_button: bool,
+    motor_position: i64,
+    timing: timing::Stats,
+    index: index::Stats,
+}
+
+impl Machine {
+    fn new() -> Self {
+        let mut timing = timing::Stats::new();
+        timing.min_us = 4;
+        timing.max_us = 12;
+        timing.pulse_max_us = 3;
+        timing.histogram[0] = 10;
+        timing.histogram[1] = 52_000;
+        timing.histogram[2] = 310;
+        Machine {
+            control: Control::new(),
+            servo: Servo::new(),
+            now_ms: 0,
+            rpm: 0,
+            servo_ok: true,
+            button1: false,
+            input: Input::new(0, 0),
+            mode_count: 0,
+            mode_button: false,
+            feed_count: 0,
+            feed_button: false,
+            motor_position: 0,
+            timing,
+            index: index::Stats::new(),
+        }
+    }
+
+    fn step<R: Renderer>(&mut self, ui: &mut UI<R>, step: Step) {
+        match step {
+            Wait(ms) => {
+                for _ in 0..ms {
+                    self.now_ms += 1;
+                    self.input.update(
+                        self.now_ms,
+                        self.button1,
+                        self.mode_button,
+                        self.feed_button,
+                        self.mode_count,
+                        self.feed_count,
+                    );
+                }
+                let requested = ui.get_mode() != Mode::ServoOff;
+                self.servo.update(self.now_ms, requested, self.servo_ok);
+                ui.update(
+                    &mut self.control,
+                    &mut self.servo,
+                    self.now_ms,
+                    self.rpm,
+                    self.servo_ok,
+                    &mut self.input,
+                    123_456,
+                    123_458,
+                    7,
+                    160,
+                    self.motor_position,
+                    requested,
+                    true,
+                    -1234,
+                    &self.timing,
+                    &self.index,
+                );
+            }
+            BootMessage(message1, message2) => ui.set_boot_message(message1, message2),
+            Rpm(rpm) => self.rpm = rpm,
+            ServoOk(ok) => self.servo_ok = ok,
+            Button1(pressed) => self.button1 = pressed,
+            ModeKnob(detents) => {
+                self.mode_count = self
+                    .mode_count
+                    .wrapping_add_signed(detents * PULSES_PER_DETENT)
+                    & 0xffff
+            }
+            ModeButton(pressed) => self.mode_button = pressed,
+            FeedKnob(detents) => {
+                self.feed_count = self
+                    .feed_count
+                    .wrapping_add_signed(detents * PULSES_PER_DETENT)
+                    & 0xffff
+            }
+            FeedButton(pressed) => self.feed_button = pressed,
+            SetMode(mode) => ui.set_mode(&mut self.control, mode, self.rpm).unwrap(),
+            Carriage(pulses) => self.motor_position = pulses,
+            SetStop(stop) => {
+                let position = self.control.pulses_to_micron(self.motor_position);
+                ui.set_stop(Some(stop), position);
+            }
+            Trip(fault) => self.servo.trip(fault),
+            Lost(pulses) => self.servo.record_lost(Direction::Forward, pulses),
+            SlowPulse => self.timing.slow_count += 1,
+            IndexError => {
+                self.index.errors += 1;
+                self.index.last_error = -3;
+            }
+            LoopTime(min_us, max_us) => {
+                self.timing.min_us = min_us;
+                self.timing.max_us = max_us;
+            }
+        }
+    }
+}
+
+// The welcome message, then a mode.
+fn in_mode(mode: Mode) -> Vec<Step> {
+    vec![Wait(0), Wait(WELCOME_MS), SetMode(mode), Wait(100)]
+}
+
+fn debug_page(page: i32) -> Vec<Step> {
+    let mut steps = vec![
+        Wait(0),
+        Wait(WELCOME_MS),
+        ModeButton(true),
+        FeedButton(true),
+        Wait(100),
+        Wait(DEBUG_HOLD_MS),
+        ModeButton(false),
+        FeedButton(false),
+        Wait(100),
+        ModeKnob(page),
+        Rpm(250),
+        Wait(100),
+    ];
+    if page == 11 {
+        steps.extend([LoopTime(4, 31), Wait(100)]);
+    }
+    steps
+}
+
+fn fault(fault: Fault) -> Vec<Step> {
+    let mut steps = in_mode(Mode::Feed);
+    steps.extend([Trip(fault), Lost(160), Wait(100)]);
+    steps
+}
+
+pub fn scenarios() -> Vec<(String, Vec<Step>)> {
+    let mut scenarios: Vec<(String, Vec<Step>)> = vec![
+        ("welcome".into(), vec![Wait(0)]),
+        (
+            "boot message".into(),
+            vec![BootMessage("LAST RESET:PANIC", "main.rs:123"), Wait(0)],
+        ),
+        ("servo off".into(), in_mode(Mode::ServoOff)),
+        ("feed".into(), in_mode(Mode::Feed)),
+        ("thread".into(), in_mode(Mode::ThreadMetric)),
+        ("thread imperial".into(), in_mode(Mode::ThreadImperial)),
+    ];
+    // Widest values, turning.
+    let mut widest = vec![SetMode(Mode::Feed), FeedKnob(100), Wait(100)];
+    widest.extend([Rpm(-12000), Carriage(-99_999_999), Wait(100)]);
+    scenarios.push((
+        "feed widest".into(),
+        [in_mode(Mode::ServoOff), widest].concat(),
+    ));
+    let widest = vec![
+        SetMode(Mode::ThreadMetric),
+        FeedButton(true),
+        Wait(100),
+        FeedKnob(100),
+        Wait(100),
+    ];
+    scenarios.push((
+        "thread widest".into(),
+        [in_mode(Mode::ServoOff), widest].concat(),
+    ));
+    let widest = vec![
+        SetMode(Mode::ThreadImperial),
+        FeedButton(true),
+        Wait(100),
+        FeedKnob(-100),
+        Wait(100),
+    ];
+    scenarios.push((
+        "thread imperial widest".into(),
+        [in_mode(Mode::ServoOff), widest].concat(),
+    ));
+    // Part of the way to a carriage stop.
+    let stop = vec![
+        SetMode(Mode::Feed),
+        Carriage(-20_000),
+        SetStop(10_000),
+        Carriage(-35_000),
+        Wait(100),
+    ];
+    scenarios.push((
+        "carriage stop".into(),
+        [in_mode(Mode::ServoOff), stop].concat(),
+    ));
+    // Mode chosen by turning the mode knob while it's pressed.
+    let knob = vec![
+        ModeButton(true),
+        Wait(100),
+        ModeKnob(2),
+        Wait(100),
+        ModeButton(false),
+    ];
+    scenarios.push((
+        "mode knob".into(),
+        [in_mode(Mode::ServoOff), knob, vec![Wait(100)]].concat(),
+    ));
+
+    let warnings = [
+        ("stop spindle to change mode", vec![Rpm(500), ModeKnob(1)]),
+        ("press knob to change mode", vec![ModeKnob(1)]),
+        (
+            "stop spindle to change pitch",
+            vec![SetMode(Mode::ThreadMetric), Rpm(500), FeedKnob(1)],
+        ),
+        (
+            "press knob to change pitch",
+            vec![SetMode(Mode::ThreadMetric), FeedKnob(1)],
+        ),
+        (
+            "servo not ok",
+            vec![SetMode(Mode::Feed), Wait(100), ServoOk(false)],
+        ),
+        ("slow", vec![SetMode(Mode::Feed), SlowPulse]),
+        ("encoder error", vec![SetMode(Mode::Feed), IndexError]),
+    ];
+    for (name, steps) in warnings {
+        let mut steps = [in_mode(Mode::ServoOff), steps].concat();
+        steps.push(Wait(100));
+        scenarios.push((name.into(), steps));
+    }
+
+    for (name, f) in [
+        ("servo lost", Fault::ServoLost),
+        ("enable timeout", Fault::EnableTimeout),
+        ("clear failed", Fault::ClearFailed),
+        ("encoder slip", Fault::EncoderSlip),
+        ("step mismatch", Fault::StepMismatch),
+    ] {
+        scenarios.push((format!("fault {}", name), fault(f)));
+    }
+    let mut steps = fault(Fault::ServoLost);
+    steps.push(Wait(1000));
+    scenarios.push(("fault alternate".into(), steps));
+    let mut steps = fault(Fault::ServoLost);
+    steps.extend([Button1(true), Wait(100)]);
+    scenarios.push(("fault clearing".into(), steps));
+
+    for page in 0..=11 {
+        scenarios.push((format!("debug {}", page), debug_page(page)));
+    }
+    // Holding both knobs again leaves the debug display.
+    let mut steps = debug_page(3);
+    steps.extend([
+        ModeButton(true),
+        FeedButton(true),
+        Wait(DEBUG_HOLD_MS),
+        ModeButton(false),
+        FeedButton(false),
+        Wait(100),
+    ]);
+    scenarios.push(("debug off".into(), steps));
+
+    let mut steps = in_mode(Mode::ServoOff);
+    steps.push(Wait(600_000));
+    scenarios.push(("idle dimmed".into(), steps.clone()));
+    steps.extend([FeedKnob(1), Wait(100)]);
+    scenarios.push(("idle woken".into(), steps));
+    scenarios
+}
+
+fn run<R: Renderer>(renderer: &mut R, steps: &[Step]) {
+    let mut machine = Machine::new();
+    let mut ui = UI::new(renderer);
+    for &step in steps {
+        machine.step(&mut ui, step);
+    }
+}
+
+// All the screens for one display size, and any problems with them.
+pub fn screens<const COLS: usize, const ROWS: usize>() -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut problems = Vec::new();
+    for (name, steps) in scenarios() {
+        let mut display = MockDisplay::new(Geometry::<COLS, ROWS>, &VFD);
+        run(&mut TextRenderer::new(&mut display), &steps);
+        let mut buffered = MockDisplay::new(Geometry::<COLS, ROWS>, &VFD);
+        let mut frame_buffer: FrameBuffer<_, COLS, ROWS> = FrameBuffer::new(&mut buffered);
+        run(&mut TextRenderer::new(&mut frame_buffer), &steps);
+
+        for error in display.take_errors() {
+            problems.push(format!("{}x{} {}: {}", COLS, ROWS, name, error));
+        }
+        if buffered.text() != display.text() {
+            problems.push(format!(
+                "{}x{} {}: different through the framebuffer",
+                COLS, ROWS, name
+            ));
+        }
+
+        writeln!(out, "# {}", name).ok();
+        if display.get_brightness() != 100 {
+            writeln!(out, "brightness {}%", display.get_brightness()).ok();
+        }
+        writeln!(out, "┌{}┐", "─".repeat(COLS)).ok();
+        for line in display.text() {
+            writeln!(out, "│{}│", line).ok();
+        }
+        writeln!(out, "└{}┘", "─".repeat(COLS)).ok();
+    }
+    (out, problems)
+}
+
+// Draws the OLED's layouts straight into a bitmap, as the OLED driver is
+// given them.
+struct BitmapRenderer {
+    view: OledView,
+    bitmap: Bitmap,
+    brightness: u8,
+}
+
+impl Renderer for BitmapRenderer {
+    fn render(&mut self, view: &View) -> Result<(), lcd::Error> {
+        self.bitmap = self.view.render(view).clone();
+        Ok(())
+    }
+    fn brightness(&mut self, percent: u8) {
+        self.brightness = percent;
+    }
+}
+
+struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+// All the OLED screens, drawn two pixel rows to a line, and any problems
+// with them.
+pub fn oled_screens() -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut problems = Vec::new();
+    for (name, steps) in scenarios() {
+        let mut direct = BitmapRenderer {
+            view: OledView::new(),
+            bitmap: Bitmap::new(),
+            brightness: 100,
+        };
+        run(&mut direct, &steps);
+        for (controller_name, controller) in [
+            ("SSD1306", Controller::Ssd1306),
+            ("SH1106", Controller::Sh1106),
+        ] {
+            let mut oled = Oled::new(MockOled::new(controller), controller);
+            oled.init(&mut NoDelay);
+            run(&mut OledRenderer::new(&mut oled), &steps);
+            let mock = oled.interface();
+            for error in mock.take_errors() {
+                problems.push(format!("oled {} {}: {}", controller_name, name, error));
+            }
+            let same = (0..HEIGHT)
+                .all(|y| (0..WIDTH).all(|x| mock.pixel(x, y) == direct.bitmap.get(x, y)));
+            if !same {
+                problems.push(format!(
+                    "oled {}: different through the {} driver",
+                    name, controller_name
+                ));
+            }
+            let contrast = (direct.brightness as u32 * 255 / 100) as u8;
+            if mock.get_contrast() != contrast {
+                problems.push(format!(
+                    "oled {}: {} contrast not set",
+                    name, controller_name
+                ));
+            }
+        }
+
+        writeln!(out, "# {}", name).ok();
+        if direct.brightness != 100 {
+            writeln!(out, "brightness {}%", direct.brightness).ok();
+        }
+        writeln!(out, "┌{}┐", "─".repeat(WIDTH)).ok();
+        for y in (0..HEIGHT).step_by(2) {
+            let line: String = (0..WIDTH)
+                .map(
+                    |x| match (direct.bitmap.get(x, y), direct.bitmap.get(x, y + 1)) {
+                        (false, false) => ' ',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (true, true) => '█',
+                    },
+                )
+                .collect();
+            writeln!(out, "│{}│", line).ok();
+        }
+        writeln!(out, "└{}┘", "─".repeat(WIDTH)).ok();
+    }
+    (out, problems)
+}
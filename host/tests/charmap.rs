@@ -0,0 +1,134 @@
+//! Display character ROM tables: each is consistent, covers printable ASCII
+//! and the characters the user interface uses, and substitutes a visible
+//! glyph for anything else.
+use els_host::charmap::{CharMap, HD44780_A00, HD44780_A02, OLED, VFD};
+use els_host::font;
+
+// Non-ASCII characters in the user interface's text.
+const UI_CHARS: &str = "μ●○↑↓";
+
+fn codes(name: &str, map: &CharMap) {
+    let mut seen = Vec::new();
+    for code in 0..=255u8 {
+        let Some(c) = map.decode(code) else {
+            continue;
+        };
+        assert!(
+            code >= 8,
+            "{}: user-defined code {:#04x} has a character",
+            name,
+            code
+        );
+        assert!(
+            !seen.contains(&c),
+            "{}: {:?} at more than one code",
+            name,
+            c
+        );
+        seen.push(c);
+        assert_eq!(
+            map.get(c),
+            Some(code),
+            "{}: {:?} doesn't encode to its code",
+            name,
+            c
+        );
+    }
+    for &(alias, code) in map.aliases() {
+        assert!(
+            !seen.contains(&alias),
+            "{}: alias {:?} also has a code",
+            name,
+            alias
+        );
+        assert_eq!(
+            map.get(alias),
+            Some(code),
+            "{}: alias {:?} doesn't encode to its code",
+            name,
+            alias
+        );
+    }
+}
+
+// Printable ASCII, except the characters the ROM has something else in
+// place of, and the user interface's characters, except those the ROM shows
+// substituted.
+fn coverage(name: &str, map: &CharMap, missing: &[char], substituted: &str) {
+    for c in (' '..='~').filter(|c| !missing.contains(c)) {
+        assert!(
+            map.get(c).is_some(),
+            "{}: printable ASCII {:?} not mapped",
+            name,
+            c
+        );
+    }
+    for c in UI_CHARS.chars() {
+        assert_eq!(
+            map.get(c).is_some(),
+            !substituted.contains(c),
+            "{}: user interface character {:?} mapping changed",
+            name,
+            c
+        );
+    }
+}
+
+fn replacement(name: &str, map: &CharMap) {
+    let replacement = map.replacement();
+    assert!(
+        replacement >= 8 && map.decode(replacement).is_some_and(|c| c != ' '),
+        "{}: replacement glyph isn't visible",
+        name
+    );
+    for c in ['\0', '\u{fffd}', '€', '😀'] {
+        assert_eq!(map.encode(c), replacement, "{}: {:?} not replaced", name, c);
+    }
+}
+
+#[test]
+fn vfd() {
+    codes("vfd", &VFD);
+    coverage("vfd", &VFD, &[], "");
+    replacement("vfd", &VFD);
+    // Characters the firmware has always written to the VFD.
+    for (c, code) in [
+        ('\\', 0x8c),
+        ('~', 0x8e),
+        ('°', 0xdf),
+        ('μ', 0xe4),
+        ('A', 0x41),
+    ] {
+        assert_eq!(VFD.get(c), Some(code), "vfd: {:?} moved", c);
+    }
+}
+
+#[test]
+fn a00() {
+    codes("a00", &HD44780_A00);
+    // It has a yen sign and arrow in place of these.
+    coverage("a00", &HD44780_A00, &['\\', '~'], "●○↑↓");
+    replacement("a00", &HD44780_A00);
+}
+
+#[test]
+fn a02() {
+    codes("a02", &HD44780_A02);
+    coverage("a02", &HD44780_A02, &[], "○");
+    replacement("a02", &HD44780_A02);
+}
+
+#[test]
+fn oled() {
+    codes("oled", &OLED);
+    coverage("oled", &OLED, &[], "");
+    replacement("oled", &OLED);
+    for code in 0..=255u8 {
+        assert_eq!(
+            OLED.decode(code).is_some(),
+            font::glyph(code).is_some(),
+            "oled: font and table differ at {:#04x}",
+            code
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Machine configuration limits, and the feed calculations they protect.
+use els_host::config::Config;
+use els_host::control::Control;
+
+const MACHINE_KEYS: [&str; 7] = [
+    "encoder_ppr",
+    "encoder_ratio_spindle",
+    "encoder_ratio_encoder",
+    "leadscrew_pitch",
+    "drive_ratio_motor",
+    "drive_ratio_leadscrew",
+    "motor_ppr",
+];
+
+// Smallest and largest accepted value of each machine setting.
+const EXTREMES: [(i64, i64); 7] = [
+    (1, 100_000),
+    (1, 1000),
+    (1, 1000),
+    (1, 100_000),
+    (1, 1000),
+    (1, 1000),
+    (1, 100_000),
+];
+
+// Run the feed calculations at the coarsest and finest feeds, with as many
+// encoder pulses per control period as a spindle could give. The finest
+// feeds may round to nothing with some configurations.
+fn feed(config: Config) {
+    let mut control = Control::new();
+    control.set_config(config);
+    for set in [
+        |c: &mut Control| c.set_feed_rate_micron_per_rev(1000),
+        |c: &mut Control| c.set_feed_rate_micron_per_rev(1),
+        |c: &mut Control| c.set_feed_rate_tpi(6),
+        |c: &mut Control| c.set_feed_rate_tpi(80),
+    ] {
+        set(&mut control);
+        control.feed_per_rev_fraction((1000 << 16) | 0xffff);
+        control.feed_per_rev_fraction(-(1000 << 16) - 0xffff);
+    }
+}
+
+#[test]
+fn defaults() {
+    let config = Config::new();
+    assert!(config.validate().is_ok());
+    feed(config);
+}
+
+#[test]
+fn overflow_rejected() {
+    // Motor pulses per encoder pulse of about 1.7e19 in 32.32 fixed point.
+    let mut config = Config::new();
+    config.encoder_ppr = 1;
+    config.encoder_ratio_spindle = 1000;
+    config.encoder_ratio_encoder = 1;
+    config.motor_ppr = 4000;
+    config.leadscrew_pitch = 1000;
+    assert!(config.validate().is_err());
+
+    // Each setting is in range, but not all together.
+    let mut config = Config::new();
+    config.set("encoder_ppr", 1).unwrap();
+    for (key, value) in [
+        ("encoder_ratio_spindle", 1000),
+        ("encoder_ratio_spindle", -1000),
+        ("encoder_ratio_encoder", 1),
+        ("motor_ppr", 100_000),
+    ] {
+        assert!(config.set(key, value).is_err(), "{}={}", key, value);
+    }
+    assert_eq!(config.get("encoder_ratio_spindle").ok(), Some(-40));
+    assert_eq!(config.get("encoder_ratio_encoder").ok(), Some(80));
+    assert_eq!(config.get("motor_ppr").ok(), Some(3200));
+    feed(config);
+}
+
+// Every combination of the machine settings at their limits is either
+// rejected or computes feeds without overflow, which would panic here.
+#[test]
+fn limits() {
+    let mut accepted = 0;
+    for combination in 0..1 << MACHINE_KEYS.len() {
+        for sign in [1, -1] {
+            let mut config = Config::new();
+            let mut valid = true;
+            // Settings that can only get smaller first, so that reaching the
+            // combination doesn't depend on the order they are set in.
+            let mut settings: Vec<(&str, i64)> = (0..MACHINE_KEYS.len())
+                .map(|i| {
+                    let (min, max) = EXTREMES[i];
+                    let value = if combination & (1 << i) != 0 {
+                        max
+                    } else {
+                        min
+                    };
+                    let value = if MACHINE_KEYS[i].contains("ratio") {
+                        value * sign
+                    } else {
+                        value
+                    };
+                    (MACHINE_KEYS[i], value)
+                })
+                .collect();
+            settings.sort_by_key(|&(key, value)| config.get(key).unwrap() < value.abs());
+            for (key, value) in settings {
+                valid &= config.set(key, value).is_ok();
+            }
+            if valid {
+                accepted += 1;
+                assert!(config.validate().is_ok());
+                feed(config);
+            }
+        }
+    }
+    assert!(accepted > 0);
+}
+
+#[test]
+fn out_of_range() {
+    let mut config = Config::new();
+    for (key, value) in [
+        ("encoder_ppr", 0),
+        ("encoder_ppr", 100_001),
+        ("encoder_ratio_spindle", 0),
+        ("encoder_ratio_spindle", -1001),
+        ("leadscrew_pitch", -3000),
+        ("motor_ppr", 0),
+        ("spindle_enc_filter", 16),
+        ("spindle_enc_prescaler", 3),
+        ("display_brightness", 101),
+        ("display_dim_timeout", -1),
+    ] {
+        assert!(config.set(key, value).is_err(), "{}={}", key, value);
+    }
+    assert!(config.set("motor_pulses", 1).is_err());
+}
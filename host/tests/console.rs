@@ -0,0 +1,513 @@
+//! The firmware's command console: parsing, line assembly and responses.
+use els_host::capture::Capture;
+use els_host::console::{
+    self, Action, CaptureOp, Command, Context, LineBuffer, ParseError, StopOp, TxBuffer,
+    RESPONSE_MAX,
+};
+use els_host::control::Control;
+use els_host::index;
+use els_host::input::Input;
+use els_host::lcd;
+use els_host::servo::Servo;
+use els_host::timing;
+use els_host::userinterface::{Mode, UI};
+use els_host::view::{Renderer, View};
+
+struct NoDisplay;
+
+impl Renderer for NoDisplay {
+    fn render(&mut self, _view: &View) -> Result<(), lcd::Error> {
+        Ok(())
+    }
+    fn brightness(&mut self, _percent: u8) {}
+}
+
+// A display whose busy flag never clears.
+struct StuckDisplay;
+
+impl Renderer for StuckDisplay {
+    fn render(&mut self, _view: &View) -> Result<(), lcd::Error> {
+        Err(lcd::Error::BusyTimeout)
+    }
+    fn brightness(&mut self, _percent: u8) {}
+}
+
+// Everything a console command can reach.
+struct Machine {
+    control: Control,
+    servo: Servo,
+    capture: Box<Capture>,
+    dumping: bool,
+    timing: timing::Stats,
+    index: index::Stats,
+    rpm: i32,
+    spindle_velocity: i64,
+    spindle_acceleration: i64,
+    spindle_pos: i64,
+    motor_pos: i64,
+    out: TxBuffer,
+}
+
+impl Machine {
+    fn new() -> Self {
+        Machine {
+            control: Control::new(),
+            servo: Servo::new(),
+            capture: Box::new(Capture::new()),
+            dumping: false,
+            timing: timing::Stats::new(),
+            index: index::Stats::new(),
+            rpm: 0,
+            spindle_velocity: 0,
+            spindle_acceleration: 0,
+            spindle_pos: 0,
+            motor_pos: 0,
+            out: TxBuffer::new(),
+        }
+    }
+
+    // Update the user interface once, as the main loop does.
+    fn update<R: Renderer>(&mut self, ui: &mut UI<R>) {
+        let mut input = Input::new(0, 0);
+        let (timing, index) = (self.timing, self.index);
+        ui.update(
+            &mut self.control,
+            &mut self.servo,
+            1000,
+            self.rpm,
+            true,
+            &mut input,
+            self.spindle_pos,
+            self.spindle_pos,
+            0,
+            0,
+            self.motor_pos,
+            false,
+            true,
+            0,
+            &timing,
+            &index,
+        );
+    }
+
+    // Answer a line as the main loop does, returning the action and all
+    // the output queued.
+    fn handle<R: Renderer>(
+        &mut self,
+        ui: &mut UI<R>,
+        line: Result<&str, ParseError>,
+    ) -> (Action, String) {
+        let mut ctx = Context {
+            ui,
+            control: &mut self.control,
+            servo: &self.servo,
+            capture: &mut self.capture,
+            dumping: self.dumping,
+            timing: &self.timing,
+            index: &self.index,
+            now_ms: 0,
+            spindle_velocity: self.spindle_velocity,
+            spindle_acceleration: self.spindle_acceleration,
+            rpm: self.rpm,
+            spindle_pos: self.spindle_pos,
+            motor_pos: self.motor_pos,
+        };
+        let action = console::handle(line, &mut self.out, &mut ctx);
+        let mut out = Vec::new();
+        while let Some(byte) = self.out.peek() {
+            out.push(byte);
+            self.out.pop();
+        }
+        (action, String::from_utf8(out).unwrap())
+    }
+}
+
+fn lines(buffer: &mut LineBuffer, bytes: &[u8]) -> Vec<Result<String, String>> {
+    let mut lines = Vec::new();
+    for &byte in bytes {
+        if let Some(line) = buffer.push(byte) {
+            lines.push(line.map(str::to_string).map_err(|e| e.to_string()));
+        }
+    }
+    lines
+}
+
+#[test]
+fn parse_commands() {
+    assert!(matches!(console::parse("help"), Ok(Command::Help)));
+    assert!(matches!(console::parse("?"), Ok(Command::Help)));
+    assert!(matches!(console::parse("status"), Ok(Command::Status)));
+    assert!(matches!(console::parse("mode"), Ok(Command::Mode(None))));
+    assert!(matches!(
+        console::parse("mode thread-imperial"),
+        Ok(Command::Mode(Some(Mode::ThreadImperial)))
+    ));
+    assert!(matches!(console::parse("feed"), Ok(Command::Feed(None))));
+    assert!(matches!(
+        console::parse("feed 100"),
+        Ok(Command::Feed(Some(100)))
+    ));
+    assert!(matches!(
+        console::parse("pitch 1500"),
+        Ok(Command::Pitch(Some(1500)))
+    ));
+    assert!(matches!(
+        console::parse("tpi 20"),
+        Ok(Command::Tpi(Some(20)))
+    ));
+    assert!(matches!(console::parse("stop"), Ok(Command::Stop(None))));
+    assert!(matches!(
+        console::parse("stop -40000"),
+        Ok(Command::Stop(Some(StopOp::Set(-40000))))
+    ));
+    assert!(matches!(
+        console::parse("stop clear"),
+        Ok(Command::Stop(Some(StopOp::Clear)))
+    ));
+    assert!(matches!(
+        console::parse("config"),
+        Ok(Command::Config(None, None))
+    ));
+    assert!(matches!(
+        console::parse("config motor_ppr"),
+        Ok(Command::Config(Some("motor_ppr"), None))
+    ));
+    assert!(matches!(
+        console::parse("config motor_ppr -4000"),
+        Ok(Command::Config(Some("motor_ppr"), Some(-4000)))
+    ));
+    assert!(matches!(
+        console::parse("telemetry 50"),
+        Ok(Command::Telemetry(50))
+    ));
+    assert!(matches!(
+        console::parse("capture"),
+        Ok(Command::Capture(None))
+    ));
+    assert!(matches!(
+        console::parse("capture start"),
+        Ok(Command::Capture(Some(CaptureOp::Start)))
+    ));
+    assert!(matches!(
+        console::parse("capture stop"),
+        Ok(Command::Capture(Some(CaptureOp::Stop)))
+    ));
+    assert!(matches!(
+        console::parse("capture dump"),
+        Ok(Command::Capture(Some(CaptureOp::Dump)))
+    ));
+    assert!(matches!(
+        console::parse("timing"),
+        Ok(Command::Timing(false))
+    ));
+    assert!(matches!(
+        console::parse("timing reset"),
+        Ok(Command::Timing(true))
+    ));
+    assert!(matches!(console::parse("encoder"), Ok(Command::Encoder)));
+    assert!(matches!(console::parse("dfu"), Ok(Command::Dfu)));
+    assert!(matches!(
+        console::parse("  feed \t 50  "),
+        Ok(Command::Feed(Some(50)))
+    ));
+}
+
+#[test]
+fn parse_errors() {
+    assert!(matches!(console::parse(""), Err(ParseError::Empty)));
+    assert!(matches!(console::parse("   "), Err(ParseError::Empty)));
+    assert!(matches!(
+        console::parse("fed 100"),
+        Err(ParseError::UnknownCommand)
+    ));
+    assert!(matches!(
+        console::parse("Status"),
+        Err(ParseError::UnknownCommand)
+    ));
+    assert!(matches!(
+        console::parse("mode fast"),
+        Err(ParseError::BadArgument)
+    ));
+    assert!(matches!(
+        console::parse("capture pause"),
+        Err(ParseError::BadArgument)
+    ));
+    assert!(matches!(
+        console::parse("timing clear"),
+        Err(ParseError::BadArgument)
+    ));
+    assert!(matches!(
+        console::parse("status now"),
+        Err(ParseError::TooManyArguments)
+    ));
+    assert!(matches!(
+        console::parse("config motor_ppr 4000 1"),
+        Err(ParseError::TooManyArguments)
+    ));
+}
+
+#[test]
+fn parse_bad_numbers() {
+    for line in [
+        "feed 1.5",
+        "feed x",
+        "feed 0x10",
+        "pitch 99999999999",
+        "tpi -",
+        "stop here",
+        "stop 99999999999",
+        "config motor_ppr 1e3",
+        "config motor_ppr 99999999999999999999",
+        "telemetry",
+        "telemetry -1",
+    ] {
+        assert!(
+            matches!(console::parse(line), Err(ParseError::BadArgument)),
+            "{}",
+            line
+        );
+    }
+}
+
+#[test]
+fn line_endings() {
+    let mut buffer = LineBuffer::new();
+    assert_eq!(
+        lines(&mut buffer, b"status\rfeed\nmode\r\ntpi\n\r"),
+        [
+            Ok("status".into()),
+            Ok("feed".into()),
+            Ok("mode".into()),
+            Ok("tpi".into()),
+        ]
+    );
+    assert_eq!(lines(&mut buffer, b"\r\n\n\r"), []);
+    // Incomplete lines are kept until their end arrives.
+    assert_eq!(lines(&mut buffer, b"enc"), []);
+    assert_eq!(lines(&mut buffer, b"oder\r"), [Ok("encoder".into())]);
+}
+
+#[test]
+fn line_backspace() {
+    let mut buffer = LineBuffer::new();
+    assert_eq!(
+        lines(&mut buffer, b"feex\x08d 10\x7f50\n"),
+        [Ok("feed 150".into())]
+    );
+    // Backspace at the start of a line does nothing.
+    assert_eq!(lines(&mut buffer, b"\x08\x08mode\n"), [Ok("mode".into())]);
+}
+
+#[test]
+fn line_overflow() {
+    let mut buffer = LineBuffer::new();
+    let mut bytes = vec![b'x'; 100];
+    bytes.extend(b"\nstatus\n");
+    assert_eq!(
+        lines(&mut buffer, &bytes),
+        [Err(ParseError::TooLong.to_string()), Ok("status".into())]
+    );
+    // The longest line that fits.
+    let mut bytes = vec![b'x'; 64];
+    bytes.push(b'\n');
+    assert_eq!(lines(&mut buffer, &bytes), [Ok("x".repeat(64))]);
+}
+
+#[test]
+fn line_not_utf8() {
+    let mut buffer = LineBuffer::new();
+    assert_eq!(
+        lines(&mut buffer, b"feed \xff\n"),
+        [Err(ParseError::BadArgument.to_string())]
+    );
+}
+
+#[test]
+fn responses() {
+    let mut display = NoDisplay;
+    let mut ui = UI::new(&mut display);
+    let mut machine = Machine::new();
+    let (action, out) = machine.handle(&mut ui, Ok("feed"));
+    assert!(action == Action::None);
+    assert_eq!(out, "feed=80\nok\n");
+    let (_, out) = machine.handle(&mut ui, Ok("mode feed"));
+    assert_eq!(out, "ok\n");
+    assert!(ui.get_mode() == Mode::Feed);
+    let (_, out) = machine.handle(&mut ui, Ok("config motor_ppr"));
+    assert_eq!(out, "motor_ppr=3200\nok\n");
+    let (action, out) = machine.handle(&mut ui, Ok("telemetry 10"));
+    assert!(action == Action::Telemetry(10));
+    assert_eq!(out, "ok\n");
+    let (action, out) = machine.handle(&mut ui, Ok("capture dump"));
+    assert!(action == Action::CaptureDump);
+    assert!(out.starts_with("# els-capture "));
+    assert!(out.ends_with("time_ms,delta\n"));
+}
+
+#[test]
+fn carriage_stop() {
+    let mut display = NoDisplay;
+    let mut ui = UI::new(&mut display);
+    let mut machine = Machine::new();
+    let (_, out) = machine.handle(&mut ui, Ok("stop"));
+    assert_eq!(out, "stop=none\nok\n");
+    machine.motor_pos = 3200;
+    let position = machine.control.pulses_to_micron(machine.motor_pos);
+    let (_, out) = machine.handle(&mut ui, Ok("stop -40000"));
+    assert_eq!(out, "ok\n");
+    assert_eq!(ui.get_stop(), Some(-40000));
+    let (_, out) = machine.handle(&mut ui, Ok("stop"));
+    assert_eq!(
+        out,
+        format!("stop=-40000 distance={}\nok\n", -40000 - position)
+    );
+    let (_, out) = machine.handle(&mut ui, Ok("stop clear"));
+    assert_eq!(out, "ok\n");
+    assert_eq!(ui.get_stop(), None);
+}
+
+#[test]
+fn capture_during_dump() {
+    let mut display = NoDisplay;
+    let mut ui = UI::new(&mut display);
+    let mut machine = Machine::new();
+    machine.handle(&mut ui, Ok("capture start"));
+    machine.dumping = true;
+    for line in ["capture start", "capture stop", "capture dump"] {
+        let (action, out) = machine.handle(&mut ui, Ok(line));
+        assert!(action == Action::None);
+        assert_eq!(out, "err dump in progress\n");
+    }
+    assert!(machine.capture.is_running());
+    let (_, out) = machine.handle(&mut ui, Ok("capture"));
+    assert!(out.ends_with("ok\n"));
+}
+
+#[test]
+fn error_responses() {
+    let mut display = NoDisplay;
+    let mut ui = UI::new(&mut display);
+    let mut machine = Machine::new();
+    for (line, err) in [
+        (Err(ParseError::TooLong), "err line too long\n"),
+        (Ok("launch"), "err unknown command\n"),
+        (Ok("feed 12"), "err unsupported feed rate\n"),
+        (Ok("pitch 1600"), "err unsupported pitch\n"),
+        (Ok("tpi 19"), "err unsupported pitch\n"),
+        (Ok("config spindle_ppr"), "err unknown key\n"),
+        (Ok("config spindle_ppr 1"), "err unknown key\n"),
+        (Ok("config motor_ppr 0"), "err invalid value\n"),
+        (Ok("telemetry 100000"), "err rate too high\n"),
+        (Ok("telemetry 300"), "err rate must divide 1000\n"),
+    ] {
+        let (action, out) = machine.handle(&mut ui, line);
+        assert!(action == Action::None);
+        assert_eq!(out, err);
+    }
+    assert_eq!(
+        machine.control.get_config().get("motor_ppr").ok(),
+        Some(3200)
+    );
+
+    // Commands refused while the machine is running.
+    machine.handle(&mut ui, Ok("mode thread"));
+    let (_, out) = machine.handle(&mut ui, Ok("config motor_ppr 4000"));
+    assert_eq!(out, "err servo must be off to change configuration\n");
+    machine.rpm = 500;
+    let (_, out) = machine.handle(&mut ui, Ok("mode feed"));
+    assert_eq!(out, "err stop spindle to change mode\n");
+    let (_, out) = machine.handle(&mut ui, Ok("pitch 1500"));
+    assert_eq!(out, "err stop spindle to change pitch\n");
+    let (action, out) = machine.handle(&mut ui, Ok("dfu"));
+    assert!(action == Action::None);
+    assert_eq!(out, "err servo must be off and spindle stopped\n");
+    assert!(ui.get_mode() == Mode::ThreadMetric);
+    assert_eq!(ui.get_metric_thread_pitch(), 1000);
+}
+
+#[test]
+fn busy() {
+    let mut display = NoDisplay;
+    let mut ui = UI::new(&mut display);
+    let mut machine = Machine::new();
+    let backlog = machine.out.free() - RESPONSE_MAX + 1;
+    assert!(machine.out.push_all(&vec![b'.'; backlog]));
+    let (_, out) = machine.handle(&mut ui, Ok("feed 100"));
+    assert_eq!(out, format!("{}err busy\n", ".".repeat(backlog)));
+    assert_eq!(ui.get_feed_rate(), 80);
+
+    // Streamed output leaves room for a response.
+    while machine.out.push_stream(&[b'.'; 30]) {}
+    let (_, out) = machine.handle(&mut ui, Ok("feed 100"));
+    assert!(out.ends_with(".ok\n"));
+    assert_eq!(ui.get_feed_rate(), 100);
+}
+
+// The longest responses fit in the room kept for them, so they are never
+// cut short.
+#[test]
+fn display_error() {
+    let mut display = NoDisplay;
+    let mut ui = UI::new(&mut display);
+    let mut machine = Machine::new();
+    machine.update(&mut ui);
+    let (_, out) = machine.handle(&mut ui, Ok("status"));
+    assert!(out.contains(" display=ok "), "{}", out);
+    let mut display = StuckDisplay;
+    let mut ui = UI::new(&mut display);
+    machine.update(&mut ui);
+    let (_, out) = machine.handle(&mut ui, Ok("status"));
+    assert!(out.contains(" display=busy_timeout "), "{}", out);
+}
+
+#[test]
+fn longest_responses() {
+    let mut display = StuckDisplay;
+    let mut ui = UI::new(&mut display);
+    let mut machine = Machine::new();
+    machine.update(&mut ui);
+    for (key, value) in [
+        ("encoder_ppr", 100_000),
+        ("encoder_ratio_spindle", -1000),
+        ("encoder_ratio_encoder", -1000),
+        ("leadscrew_pitch", 100_000),
+        ("drive_ratio_motor", -1000),
+        ("drive_ratio_leadscrew", -1000),
+        ("motor_ppr", 100_000),
+        ("spindle_enc_prescaler", 4),
+        ("feed_enc_prescaler", 4),
+        ("mode_enc_prescaler", 4),
+        ("display_dim_timeout", 86_400),
+    ] {
+        let (_, out) = machine.handle(&mut ui, Ok(&format!("config {} {}", key, value)));
+        assert_eq!(out, "ok\n", "{}", key);
+    }
+    machine.spindle_velocity = i32::MIN as i64;
+    machine.spindle_acceleration = i32::MIN as i64;
+    machine.spindle_pos = i32::MIN as i64;
+    machine.motor_pos = i32::MIN as i64;
+    machine.rpm = i16::MIN as i32;
+    machine.timing.min_us = u32::MAX - 1;
+    machine.timing.max_us = u32::MAX;
+    machine.timing.pulse_max_us = u32::MAX;
+    machine.timing.slow_count = u32::MAX;
+    machine.timing.histogram.fill(u32::MAX);
+    machine.index.checked = u32::MAX;
+    machine.index.errors = u32::MAX;
+    machine.index.last_error = i32::MIN;
+    machine.index.max_error = i32::MIN;
+    for line in [
+        "help", "status", "config", "capture", "timing", "encoder", "mode", "tpi", "stop",
+    ] {
+        let (_, out) = machine.handle(&mut ui, Ok(line));
+        assert!(out.ends_with("ok\n"), "{}: {}", line, out);
+        assert!(out.len() <= RESPONSE_MAX, "{}: {} bytes", line, out.len());
+    }
+    // The capture dump's header, before the samples are streamed.
+    let (_, out) = machine.handle(&mut ui, Ok("capture dump"));
+    assert!(
+        out.len() <= RESPONSE_MAX,
+        "capture dump: {} bytes",
+        out.len()
+    );
+}
@@ -0,0 +1,98 @@
+//! Step, noise and glitch response of the firmware's signal filters, and
+//! their arithmetic at full scale.
+use els_host::filter::{Filter, Fir, Iir, Median};
+use els_host::response::{response, settling, Response, GLITCH, NOISE_SD, STEP};
+
+fn common(name: &str, r: &Response) {
+    assert!(
+        r.step.windows(2).all(|w| w[0] <= w[1]),
+        "{}: step response not monotonic",
+        name
+    );
+    assert_eq!(
+        r.step.last(),
+        Some(&STEP),
+        "{}: step response doesn't reach the step",
+        name
+    );
+    assert!(r.noise_sd < NOISE_SD, "{}: noise not reduced", name);
+}
+
+#[test]
+fn iir() {
+    let r = response(Iir::<3>::new);
+    common("iir", &r);
+    // 1000 * (7/8)^35 < 10.
+    assert_eq!(settling(&r.step), Some(35));
+}
+
+#[test]
+fn median() {
+    let r = response(Median::<3>::new);
+    common("median", &r);
+    // Steps are delayed but kept sharp, and glitches are removed entirely.
+    assert_eq!(r.step[..2], [0, STEP]);
+    assert_eq!(r.glitch, 0);
+
+    let r = response(Median::<5>::new);
+    common("median", &r);
+    assert_eq!(r.step[..3], [0, 0, STEP]);
+    assert_eq!(r.glitch, 0);
+}
+
+#[test]
+fn boxcar() {
+    let r = response(Fir::<20>::boxcar);
+    common("boxcar", &r);
+    assert_eq!(settling(&r.step), Some(20));
+    assert_eq!(r.glitch, GLITCH / 20);
+    assert!(r.noise_sd < NOISE_SD / 4.0);
+}
+
+#[test]
+fn triangle() {
+    let mut taps = [0; 9];
+    for (i, tap) in taps.iter_mut().enumerate() {
+        *tap = 5 - (i as i32 - 4).abs();
+    }
+    let r = response(|| Fir::new(taps));
+    common("triangle", &r);
+    assert_eq!(r.step[0], STEP / 25, "first tap weight");
+    assert_eq!(settling(&r.step), Some(9));
+}
+
+// Full scale samples must not overflow the sums.
+#[test]
+fn full_scale() {
+    let mut fir = Fir::<1000>::boxcar();
+    for _ in 0..1000 {
+        fir.update(i32::MAX);
+    }
+    assert_eq!(fir.value(), i32::MAX);
+    let mut iir = Iir::<3>::new();
+    for _ in 0..1000 {
+        iir.update(i32::MIN);
+    }
+    assert_eq!(iir.value(), i32::MIN);
+    let mut median = Median::<2>::new();
+    median.update(i32::MAX);
+    median.update(i32::MAX);
+    assert_eq!(median.value(), i32::MAX);
+}
+
+// Rounding must be symmetric about zero.
+#[test]
+fn rounding() {
+    for v in [1, 2, 3, 5] {
+        let mut pos = Fir::<2>::boxcar();
+        let mut neg = Fir::<2>::boxcar();
+        pos.update(v);
+        neg.update(-v);
+        assert_eq!(pos.value(), -neg.value(), "fir {}", v);
+        let mut pos = Median::<2>::new();
+        let mut neg = Median::<2>::new();
+        pos.update(v);
+        neg.update(-v);
+        assert_eq!(pos.value(), -neg.value(), "median {}", v);
+    }
+}
@@ -0,0 +1,425 @@
+//! Knob and button events from scripted input timelines.
+//!
+//! The firmware's input module is fed raw button levels and knob counts,
+//! sampled every millisecond as the main loop does. Timelines check the
+//! events the user interface takes at its updates; the timing tests take
+//! them every millisecond, to check each threshold to the millisecond.
+use els_host::input::{Button, Event, Input, Knob};
+
+use Button::*;
+use Event::*;
+
+// User interface update period, as in the firmware's main.rs.
+const UI_UPDATE_MS: i64 = 100;
+// Encoder pulses per knob detent, as in the firmware's main.rs.
+const PULSES_PER_DETENT: i32 = 2;
+// As in firmware/src/input.rs.
+const DEBOUNCE_MS: i64 = 20;
+const LONG_PRESS_MS: i64 = 1000;
+const DOUBLE_CLICK_MS: i64 = 400;
+const CHORD_MS: i64 = 1000;
+
+#[derive(Clone, Copy)]
+enum Step {
+    // Advance the clock, sampling every millisecond.
+    Wait(i64),
+    Down(Button),
+    Up(Button),
+    // Turn a knob by some encoder pulses.
+    Pulses(Knob, i32),
+}
+
+use Step::*;
+
+// A switch that bounces for a few milliseconds before settling.
+fn bounce(button: Button, down: bool) -> Vec<Step> {
+    let (settled, other) = if down {
+        (Down(button), Up(button))
+    } else {
+        (Up(button), Down(button))
+    };
+    vec![
+        settled,
+        Wait(2),
+        other,
+        Wait(1),
+        settled,
+        Wait(3),
+        other,
+        Wait(2),
+        settled,
+    ]
+}
+
+// Held for `ms` of samples.
+fn click(button: Button, ms: i64) -> Vec<Step> {
+    vec![Down(button), Wait(ms), Up(button)]
+}
+
+fn detents(knob: Knob, detents: i32) -> Step {
+    Pulses(knob, detents * PULSES_PER_DETENT)
+}
+
+// Events taken every `every_ms`, with the time they were taken. The first
+// sample is at 1ms.
+fn run(steps: &[Step], every_ms: i64) -> Vec<(i64, Event)> {
+    let mut input = Input::new(0, 0);
+    let mut buttons = [false; 3];
+    let mut counts = [0u32; 2];
+    let mut now_ms = 0;
+    let mut events = Vec::new();
+    for &step in steps {
+        match step {
+            Wait(ms) => {
+                for _ in 0..ms {
+                    now_ms += 1;
+                    input.update(
+                        now_ms, buttons[0], buttons[1], buttons[2], counts[0], counts[1],
+                    );
+                    if now_ms % every_ms == 0 {
+                        while let Some(event) = input.next_event() {
+                            events.push((now_ms, event));
+                        }
+                    }
+                }
+            }
+            Down(button) => buttons[button as usize] = true,
+            Up(button) => buttons[button as usize] = false,
+            Pulses(knob, pulses) => {
+                let count = &mut counts[knob as usize];
+                *count = count.wrapping_add_signed(pulses) & 0xffff;
+            }
+        }
+    }
+    events
+}
+
+// Events as the user interface takes them.
+fn ui(steps: &[Step]) -> Vec<(i64, Event)> {
+    run(steps, UI_UPDATE_MS)
+}
+
+// Events as soon as they happen.
+fn exact(steps: &[Step]) -> Vec<(i64, Event)> {
+    run(steps, 1)
+}
+
+#[test]
+fn click_timeline() {
+    assert_eq!(
+        ui(&[click(Button1, 100), vec![Wait(1000)]].concat()),
+        [(100, Press(Button1)), (200, Release(Button1))]
+    );
+}
+
+#[test]
+fn bouncing_contacts() {
+    let steps = [
+        bounce(Button1, true),
+        vec![Wait(200)],
+        bounce(Button1, false),
+        vec![Wait(1000)],
+    ]
+    .concat();
+    assert_eq!(ui(&steps), [(100, Press(Button1)), (300, Release(Button1))]);
+    // The press is taken once the contacts have settled for long enough,
+    // counting from the last bounce at 9ms.
+    assert_eq!(exact(&steps)[0], (9 + DEBOUNCE_MS, Press(Button1)));
+}
+
+#[test]
+fn debounce() {
+    // Samples from 1ms to DEBOUNCE_MS read pressed; one more is needed.
+    let steps = [click(Button1, DEBOUNCE_MS), vec![Wait(500)]].concat();
+    assert_eq!(exact(&steps), []);
+    let steps = [click(Button1, DEBOUNCE_MS + 1), vec![Wait(500)]].concat();
+    assert_eq!(
+        exact(&steps),
+        [
+            (1 + DEBOUNCE_MS, Press(Button1)),
+            (2 + 2 * DEBOUNCE_MS, Release(Button1))
+        ]
+    );
+    // Nor is a release shorter than that seen.
+    let steps = [
+        click(Button1, 100),
+        vec![Wait(DEBOUNCE_MS), Down(Button1), Wait(500)],
+    ]
+    .concat();
+    assert_eq!(exact(&steps), [(1 + DEBOUNCE_MS, Press(Button1))]);
+}
+
+#[test]
+fn long_press_timeline() {
+    let steps = [click(ModeKnob, 1500), vec![Wait(500)]].concat();
+    assert_eq!(
+        ui(&steps),
+        [
+            (100, Press(ModeKnob)),
+            (1100, LongPress(ModeKnob)),
+            (1600, Release(ModeKnob)),
+        ]
+    );
+}
+
+#[test]
+fn long_press() {
+    let pressed = 1 + DEBOUNCE_MS;
+    let steps = [click(ModeKnob, 1500), vec![Wait(500)]].concat();
+    assert_eq!(
+        exact(&steps)[1],
+        (pressed + LONG_PRESS_MS, LongPress(ModeKnob))
+    );
+    // Released just before, it's a click.
+    let steps = [click(ModeKnob, LONG_PRESS_MS - 1), vec![Wait(500)]].concat();
+    assert_eq!(
+        exact(&steps),
+        [
+            (pressed, Press(ModeKnob)),
+            (pressed + LONG_PRESS_MS - 1, Release(ModeKnob)),
+        ]
+    );
+}
+
+#[test]
+fn double_click_timeline() {
+    let steps = [
+        click(FeedKnob, 100),
+        vec![Wait(150)],
+        click(FeedKnob, 100),
+        vec![Wait(500)],
+    ]
+    .concat();
+    assert_eq!(
+        ui(&steps),
+        [
+            (100, Press(FeedKnob)),
+            (200, Release(FeedKnob)),
+            (300, Press(FeedKnob)),
+            (400, Release(FeedKnob)),
+            (400, DoubleClick(FeedKnob)),
+        ]
+    );
+}
+
+#[test]
+fn clicks_too_far_apart() {
+    let steps = [
+        click(FeedKnob, 100),
+        vec![Wait(500)],
+        click(FeedKnob, 100),
+        vec![Wait(500)],
+    ]
+    .concat();
+    assert_eq!(
+        ui(&steps),
+        [
+            (100, Press(FeedKnob)),
+            (200, Release(FeedKnob)),
+            (700, Press(FeedKnob)),
+            (800, Release(FeedKnob)),
+        ]
+    );
+}
+
+// Two clicks whose releases are `apart` ms apart.
+fn two_clicks(apart: i64) -> Vec<(i64, Event)> {
+    let steps = [
+        click(FeedKnob, 100),
+        vec![Wait(apart - 100)],
+        click(FeedKnob, 100),
+        vec![Wait(1000)],
+    ]
+    .concat();
+    exact(&steps)
+}
+
+#[test]
+fn double_click() {
+    let events = two_clicks(DOUBLE_CLICK_MS);
+    assert_eq!(events.len(), 5);
+    assert_eq!(events[3].0 - events[1].0, DOUBLE_CLICK_MS);
+    assert_eq!(events[4], (events[3].0, DoubleClick(FeedKnob)));
+    assert!(!two_clicks(DOUBLE_CLICK_MS + 1)
+        .iter()
+        .any(|&(_, e)| e == DoubleClick(FeedKnob)));
+    // A third click starts again, rather than being a second double click.
+    let steps = [
+        click(FeedKnob, 100),
+        vec![Wait(100)],
+        click(FeedKnob, 100),
+        vec![Wait(100)],
+        click(FeedKnob, 100),
+        vec![Wait(1000)],
+    ]
+    .concat();
+    let doubles = exact(&steps)
+        .iter()
+        .filter(|&&(_, e)| e == DoubleClick(FeedKnob))
+        .count();
+    assert_eq!(doubles, 1);
+}
+
+#[test]
+fn long_press_then_click() {
+    let steps = [
+        vec![Down(Button1), Wait(1200), Up(Button1), Wait(100)],
+        click(Button1, 100),
+        vec![Wait(500)],
+    ]
+    .concat();
+    assert_eq!(
+        ui(&steps),
+        [
+            (100, Press(Button1)),
+            (1100, LongPress(Button1)),
+            (1300, Release(Button1)),
+            (1400, Press(Button1)),
+            (1500, Release(Button1)),
+        ]
+    );
+}
+
+#[test]
+fn chord_timeline() {
+    let steps = [
+        Down(ModeKnob),
+        Wait(50),
+        Down(FeedKnob),
+        Wait(1200),
+        detents(Knob::Mode, 1),
+        Wait(100),
+        Up(ModeKnob),
+        Up(FeedKnob),
+        Wait(500),
+    ];
+    assert_eq!(
+        ui(&steps),
+        [
+            (100, Press(ModeKnob)),
+            (100, Press(FeedKnob)),
+            (1100, Chord(ModeKnob, FeedKnob)),
+            (1400, Release(ModeKnob)),
+            (1400, Release(FeedKnob)),
+        ]
+    );
+}
+
+// Two buttons pressed `apart` ms apart, then both held for `held` ms from
+// the second.
+fn chord_events(apart: i64, held: i64) -> Vec<(i64, Event)> {
+    exact(&[
+        Down(FeedKnob),
+        Wait(apart),
+        Down(Button1),
+        Wait(held),
+        Up(FeedKnob),
+        Up(Button1),
+        Wait(1000),
+    ])
+}
+
+#[test]
+fn chord() {
+    // Timed from the second press, and the first pressed comes first.
+    let second = 1 + 300 + DEBOUNCE_MS;
+    assert_eq!(
+        chord_events(300, 1500),
+        [
+            (1 + DEBOUNCE_MS, Press(FeedKnob)),
+            (second, Press(Button1)),
+            (second + CHORD_MS, Chord(FeedKnob, Button1)),
+            (second + 1500, Release(Button1)),
+            (second + 1500, Release(FeedKnob)),
+        ]
+    );
+    // Released in the same millisecond, it's nothing, and neither button is
+    // a click or a long press, even though the first was held long enough
+    // for one.
+    let events = chord_events(300, CHORD_MS);
+    assert_eq!(events.len(), 4);
+    assert!(events
+        .iter()
+        .all(|&(_, e)| matches!(e, Press(_) | Release(_))));
+    assert_eq!(
+        chord_events(300, CHORD_MS + 1)[2],
+        (second + CHORD_MS, Chord(FeedKnob, Button1))
+    );
+}
+
+#[test]
+fn chords_not_clicks() {
+    // Two short chords in quick succession aren't double clicks.
+    let events = exact(&[
+        Down(FeedKnob),
+        Down(ModeKnob),
+        Wait(100),
+        Up(FeedKnob),
+        Up(ModeKnob),
+        Wait(100),
+        Down(FeedKnob),
+        Down(ModeKnob),
+        Wait(100),
+        Up(FeedKnob),
+        Up(ModeKnob),
+        Wait(1000),
+    ]);
+    assert_eq!(events.len(), 8);
+    assert!(events
+        .iter()
+        .all(|&(_, e)| matches!(e, Press(_) | Release(_))));
+}
+
+#[test]
+fn turn() {
+    let steps = [
+        detents(Knob::Mode, 1),
+        Wait(30),
+        detents(Knob::Mode, 2),
+        Wait(100),
+        detents(Knob::Feed, -3),
+        Wait(100),
+    ];
+    assert_eq!(
+        ui(&steps),
+        [(100, Turn(Knob::Mode, 3)), (200, Turn(Knob::Feed, -3))]
+    );
+}
+
+#[test]
+fn jitter_at_a_detent() {
+    let steps = [
+        Pulses(Knob::Feed, 1),
+        Wait(10),
+        Pulses(Knob::Feed, -1),
+        Wait(10),
+        Pulses(Knob::Feed, 1),
+        Wait(10),
+        Pulses(Knob::Feed, -1),
+        Wait(200),
+    ];
+    assert_eq!(ui(&steps), []);
+}
+
+#[test]
+fn turn_while_pressed() {
+    let steps = [
+        Down(FeedKnob),
+        Wait(100),
+        detents(Knob::Feed, 2),
+        Wait(100),
+        detents(Knob::Mode, 1),
+        Wait(1500),
+        Up(FeedKnob),
+        Wait(500),
+    ];
+    assert_eq!(
+        ui(&steps),
+        [
+            (100, Press(FeedKnob)),
+            (200, PressedTurn(Knob::Feed, 2)),
+            (300, Turn(Knob::Mode, 1)),
+            (1800, Release(FeedKnob)),
+        ]
+    );
+}
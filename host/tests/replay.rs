@@ -0,0 +1,39 @@
+//! Captures in `fixtures` replayed through the feed calculations.
+//!
+//! Each `<name>.<setting>-<value>.csv` is the replay of `<name>.capture`
+//! with that feed setting. To add a case, save a capture to `fixtures` and
+//! create an empty file for each feed setting, then run with BLESS=1.
+use std::fs;
+use std::path::Path;
+
+use els_host::harness::golden;
+use els_host::replay::{replay, CaptureFile, Feed};
+
+#[test]
+fn fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    let mut entries: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "csv"))
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty());
+    let mut failures = Vec::new();
+    for expected_path in entries {
+        // <name>.<setting>-<value>.csv
+        let stem = expected_path.file_stem().unwrap().to_string_lossy();
+        let feed = stem
+            .rsplit_once('.')
+            .and_then(|(name, case)| Some((name, case.split_once('-')?)))
+            .and_then(|(name, (setting, value))| Some((name, Feed::parse(setting, value)?)));
+        let Some((name, feed)) = feed else {
+            panic!("{}: bad fixture name", expected_path.display());
+        };
+        let capture = CaptureFile::load(&dir.join(format!("{}.capture", name))).unwrap();
+        if let Err(e) = golden(&expected_path, &replay(&capture, feed)) {
+            failures.push(e);
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
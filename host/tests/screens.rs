@@ -0,0 +1,36 @@
+//! The user interface's screens on each display, against the golden files
+//! in `screens`.
+use std::path::Path;
+
+use els_host::harness::golden;
+use els_host::screens::{oled_screens, screens};
+
+fn check(size: &str, (screens, problems): (String, Vec<String>)) {
+    assert!(problems.is_empty(), "{}", problems.join("\n"));
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("screens")
+        .join(format!("{}.txt", size));
+    if let Err(e) = golden(&path, &screens) {
+        panic!("{}", e);
+    }
+}
+
+#[test]
+fn text_16x2() {
+    check("16x2", screens::<16, 2>());
+}
+
+#[test]
+fn text_20x4() {
+    check("20x4", screens::<20, 4>());
+}
+
+#[test]
+fn text_40x2() {
+    check("40x2", screens::<40, 2>());
+}
+
+#[test]
+fn oled() {
+    check("oled", oled_screens());
+}
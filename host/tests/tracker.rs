@@ -0,0 +1,27 @@
+//! Carriage following error with the spindle phase tracker, against a
+//! simulated spindle with speed ripple.
+use els_host::config::Config;
+use els_host::following::{follow, spindle};
+
+// The tracker must do better than following the sampled encoder count
+// directly, at every speed and feed. Its worst case may only match, as
+// either is a whole step behind at times and at low feeds that's the most.
+#[test]
+fn tracked_error_lower() {
+    let config = Config::new();
+    let mut failures = Vec::new();
+    for rpm in [300.0, 1400.0, 3000.0] {
+        let angle = spindle(rpm);
+        for feed in [100, 1000, 4000] {
+            let raw = follow(&angle, &config, feed, false);
+            let tracked = follow(&angle, &config, feed, true);
+            if tracked.rms >= raw.rms || tracked.max > raw.max {
+                failures.push(format!(
+                    "{}RPM feed {}: tracked {:.3}/{:.3}μm, untracked {:.3}/{:.3}μm rms/max",
+                    rpm, feed, tracked.rms, tracked.max, raw.rms, raw.max
+                ));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}